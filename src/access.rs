@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Debug, thiserror::Error)]
+#[error("\"{0}\" isn't an address range, such as 192.168.0.0/16 or fd00::/8")]
+pub struct ParseError(String);
+
+/// A range of addresses, such as `192.168.0.0/16`. A lone address is a range of
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    addr:   IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(range) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(range) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = ParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (addr, prefix) = s.split_once('/').unwrap_or((&s, ""));
+        let Ok(addr) = addr.trim().parse::<IpAddr>() else {
+            return Err(ParseError(s));
+        };
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix.trim() {
+            "" => Some(max),
+            prefix => prefix.parse().ok().filter(|x| *x <= max),
+        };
+        match prefix {
+            Some(prefix) => Ok(Self { addr, prefix }),
+            None => Err(ParseError(s)),
+        }
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Which addresses may reach the server, and its owner's routes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Ranges allowed to reach the server. Every address is, when empty.
+    pub allow:       Vec<Cidr>,
+    /// Ranges never allowed to, even when `allow` has them.
+    pub deny:        Vec<Cidr>,
+    /// Ranges allowed to reach `admin_paths`, besides being allowed at all. Every
+    /// allowed address is, when empty.
+    pub admin:       Vec<Cidr>,
+    /// Paths starting with these are the owner's routes.
+    pub admin_paths: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            allow:       Vec::new(),
+            deny:        Vec::new(),
+            admin:       Vec::new(),
+            admin_paths: ["/api/", "/dav", "/edit/", "/login", "/status", "/metrics"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+impl Config {
+    /// Whether a request from `addr` may reach `path`. Requests from an unknown
+    /// address only may when no ranges are given.
+    pub fn allows(&self, addr: Option<IpAddr>, path: &str) -> bool {
+        let within = |ranges: &[Cidr]| {
+            ranges.is_empty()
+                || addr.is_some_and(|x| ranges.iter().any(|r| r.contains(x)))
+        };
+        let denied = addr.is_some_and(|x| self.deny.iter().any(|r| r.contains(x)));
+        let admin = self
+            .admin_paths
+            .iter()
+            .any(|x| path.starts_with(x.as_str()));
+        !denied && within(&self.allow) && (!admin || within(&self.admin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        Cidr::try_from(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn ranges() {
+        let lan = cidr("192.168.0.0/16");
+        assert!(lan.contains("192.168.1.20".parse().unwrap()));
+        assert!(lan.contains("::ffff:192.168.1.20".parse().unwrap()));
+        assert!(!lan.contains("192.169.0.1".parse().unwrap()));
+        assert!(cidr("0.0.0.0/0").contains("8.8.8.8".parse().unwrap()));
+        assert!(cidr("fd00::/8").contains("fd12::1".parse().unwrap()));
+        assert!(!cidr("::1").contains("::2".parse().unwrap()));
+        assert_eq!(cidr("10.0.0.1").to_string(), "10.0.0.1/32");
+        assert!(Cidr::try_from(String::from("10.0.0.0/33")).is_err());
+        assert!(Cidr::try_from(String::from("example.com")).is_err());
+    }
+
+    #[test]
+    fn access() {
+        let config = Config {
+            deny: vec![cidr("192.168.1.66")],
+            admin: vec![cidr("192.168.0.0/16")],
+            ..Config::default()
+        };
+        let lan = "192.168.1.20".parse().ok();
+        let outside = "203.0.113.5".parse().ok();
+        assert!(config.allows(outside, "/note/a.md"));
+        assert!(!config.allows(outside, "/api/upload"));
+        assert!(config.allows(lan, "/api/upload"));
+        assert!(!config.allows("192.168.1.66".parse().ok(), "/"));
+        assert!(!config.allows(None, "/api/upload"));
+        assert!(Config::default().allows(None, "/api/upload"));
+    }
+}
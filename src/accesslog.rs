@@ -0,0 +1,162 @@
+use crate::http::Request;
+use chrono::{DateTime, FixedOffset, Local};
+use log::error;
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Where requests are logged, once opened.
+static FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+thread_local! {
+    /// When the current thread began handling its request, and who it's from.
+    static CURRENT: Cell<Option<(Instant, Option<IpAddr>)>> = const { Cell::new(None) };
+}
+
+/// Starts logging requests to the end of `path`.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Notes that the current thread began handling a request from `addr`, which may
+/// differ from the connection's when it came through a proxy.
+pub fn begin(addr: Option<IpAddr>) {
+    CURRENT.set(Some((Instant::now(), addr)));
+}
+
+/// A request answered, as a line of the Combined Log Format.
+pub struct Entry {
+    addr:       Option<IpAddr>,
+    time:       DateTime<FixedOffset>,
+    /// The method, URL and HTTP version.
+    request:    String,
+    status:     u16,
+    bytes:      Option<usize>,
+    referer:    Option<String>,
+    user_agent: Option<String>,
+    start:      Option<Instant>,
+}
+
+impl Entry {
+    /// What to log about `request`, answered with `status` and a body of `bytes`,
+    /// or `None` when nothing's logged.
+    pub fn new(request: &Request, status: u16, bytes: Option<usize>) -> Option<Self> {
+        FILE.get()?;
+        let (start, addr) = match CURRENT.take() {
+            Some((start, addr)) => (Some(start), addr),
+            None => (None, request.remote_addr().map(|x| x.ip())),
+        };
+        let header = |name: &str| {
+            request
+                .headers()
+                .iter()
+                .find(|x| x.field.as_str().eq_ignore_ascii_case(name))
+                .map(|x| x.value.to_string())
+        };
+        Some(Self {
+            addr,
+            time: Local::now().fixed_offset(),
+            request: format!(
+                "{} {} {:?}",
+                request.method(),
+                request.url(),
+                request.http_version()
+            ),
+            status,
+            bytes,
+            referer: header("Referer"),
+            user_agent: header("User-Agent"),
+            start,
+        })
+    }
+
+    /// The line, ending with how long answering took in microseconds, like
+    /// Apache's `%D`.
+    fn line(&self, latency: Option<Duration>) -> String {
+        let field = |x: Option<&str>| match x {
+            Some(x) => format!("\"{}\"", quoted(x)),
+            None => String::from("\"-\""),
+        };
+        let or_dash = |x: Option<String>| x.unwrap_or_else(|| String::from("-"));
+        format!(
+            "{} - - [{}] \"{}\" {} {} {} {} {}\n",
+            or_dash(self.addr.map(|x| x.to_canonical().to_string())),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            quoted(&self.request),
+            self.status,
+            or_dash(self.bytes.map(|x| x.to_string())),
+            field(self.referer.as_deref()),
+            field(self.user_agent.as_deref()),
+            or_dash(latency.map(|x| x.as_micros().to_string())),
+        )
+    }
+
+    /// Logs the entry, once the response has been sent.
+    pub fn write(self) {
+        let latency = self.start.map(|x| x.elapsed());
+        let line = self.line(latency);
+        let Some(file) = FILE.get() else { return };
+        let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            error!("Failed to write to the access log: {e}");
+        }
+    }
+}
+
+/// `x` made safe to put in quotes, escaping quotes, backslashes and control
+/// characters as Apache does.
+fn quoted(x: &str) -> String {
+    let mut quoted = String::with_capacity(x.len());
+    for c in x.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let entry = Entry {
+            addr:       Some("::ffff:192.0.2.1".parse().unwrap()),
+            time:       DateTime::parse_from_rfc3339("2025-10-10T13:55:36-07:00")
+                .unwrap(),
+            request:    String::from("GET /a\"b HTTP/1.1"),
+            status:     200,
+            bytes:      Some(2326),
+            referer:    None,
+            user_agent: Some(String::from("curl/8.0\n")),
+            start:      None,
+        };
+        assert_eq!(
+            entry.line(Some(Duration::from_millis(3))),
+            "192.0.2.1 - - [10/Oct/2025:13:55:36 -0700] \"GET /a\\\"b HTTP/1.1\" 200 2326 \"-\" \"curl/8.0\\x0a\" 3000\n"
+        );
+        let entry = Entry {
+            addr: None,
+            bytes: None,
+            ..entry
+        };
+        assert!(entry.line(None).starts_with("- - - ["));
+        assert!(
+            entry
+                .line(None)
+                .ends_with(" 200 - \"-\" \"curl/8.0\\x0a\" -\n")
+        );
+    }
+}
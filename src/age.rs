@@ -0,0 +1,108 @@
+use chrono::{Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, thiserror::Error)]
+#[error("\"{0}\" isn't an age, such as 2y, 6m, 3w or 10d")]
+pub struct ParseError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// A whole number of days, weeks, months or years, written like `2y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Age {
+    count: u32,
+    unit:  Unit,
+}
+
+impl Age {
+    /// Whether something from `date` is older than this on `today`.
+    pub fn exceeded(self, date: NaiveDate, today: NaiveDate) -> bool {
+        let until = match self.unit {
+            Unit::Day => date.checked_add_days(Days::new(self.count.into())),
+            Unit::Week => date.checked_add_days(Days::new(7 * u64::from(self.count))),
+            Unit::Month => date.checked_add_months(Months::new(self.count)),
+            Unit::Year => self
+                .count
+                .checked_mul(12)
+                .and_then(|months| date.checked_add_months(Months::new(months))),
+        };
+        until.is_some_and(|until| until < today)
+    }
+}
+
+impl TryFrom<String> for Age {
+    type Error = ParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let last = s.chars().last().map_or(0, char::len_utf8);
+        let (count, unit) = s.split_at(s.len() - last);
+        let unit = match unit {
+            "d" => Some(Unit::Day),
+            "w" => Some(Unit::Week),
+            "m" => Some(Unit::Month),
+            "y" => Some(Unit::Year),
+            _ => None,
+        };
+        match (count.parse(), unit) {
+            (Ok(count), Some(unit)) => Ok(Self { count, unit }),
+            _ => Err(ParseError(s)),
+        }
+    }
+}
+
+impl From<Age> for String {
+    fn from(age: Age) -> Self {
+        let unit = match age.unit {
+            Unit::Day => 'd',
+            Unit::Week => 'w',
+            Unit::Month => 'm',
+            Unit::Year => 'y',
+        };
+        format!("{}{unit}", age.count)
+    }
+}
+
+/// In words, such as `2 years`.
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.unit {
+            Unit::Day => "day",
+            Unit::Week => "week",
+            Unit::Month => "month",
+            Unit::Year => "year",
+        };
+        let plural = if self.count == 1 { "" } else { "s" };
+        write!(f, "{} {unit}{plural}", self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages() {
+        let age = Age::try_from(String::from("2y")).unwrap();
+        assert_eq!(age.to_string(), "2 years");
+        assert_eq!(String::from(age), "2y");
+        let date = NaiveDate::from_ymd_opt(2022, 3, 1).unwrap();
+        assert!(!age.exceeded(date, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()));
+        assert!(age.exceeded(date, NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()));
+
+        let age = Age::try_from(String::from("1w")).unwrap();
+        assert_eq!(age.to_string(), "1 week");
+        assert!(age.exceeded(date, NaiveDate::from_ymd_opt(2022, 3, 9).unwrap()));
+
+        assert!(Age::try_from(String::from("2")).is_err());
+        assert!(Age::try_from(String::from("y")).is_err());
+        assert!(Age::try_from(String::new()).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Values of the theme's CSS custom properties, overriding the theme's own so the
+/// site can be branded without writing one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The color of links, as `--accent-color`.
+    pub accent_color:       Option<String>,
+    /// As `--font-family`, such as `'Inter', sans-serif`.
+    pub font_family:        Option<String>,
+    /// As `--header-font-family`.
+    pub header_font_family: Option<String>,
+    /// As `--code-font-family`.
+    pub code_font_family:   Option<String>,
+    /// How wide the content is at most, as `--content-width`, such as `80ex`.
+    pub max_width:          Option<String>,
+}
+
+/// A stylesheet setting the properties `config` gives, to follow the theme's.
+/// Values that could end the declaration, or the `<style>` element, are left out.
+pub fn css(config: &Config) -> String {
+    let properties = [
+        ("--accent-color", &config.accent_color),
+        ("--font-family", &config.font_family),
+        ("--header-font-family", &config.header_font_family),
+        ("--code-font-family", &config.code_font_family),
+        ("--content-width", &config.max_width),
+    ];
+    let mut css = String::new();
+    for (property, value) in properties {
+        let Some(value) = value else {
+            continue;
+        };
+        if value.contains([';', '{', '}', '<', '>', '\\']) {
+            warn!("Ignoring appearance {property}: {value:?} isn't a plain CSS value");
+            continue;
+        }
+        css.push_str(&format!("    {property}: {};\n", value.trim()));
+    }
+    match css.is_empty() {
+        true => css,
+        false => format!(":root {{\n{css}}}\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn properties() {
+        assert_eq!(css(&Config::default()), "");
+        let config = Config {
+            accent_color: Some(String::from("#e01b24")),
+            max_width: Some(String::from("80ex}</style>")),
+            ..Config::default()
+        };
+        assert_eq!(css(&config), ":root {\n    --accent-color: #e01b24;\n}\n");
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Read;
+use url::Url;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("failed to fetch \"{0}\": {1}")]
+    Fetch(Url, Box<ureq::Error>),
+    #[error("failed to read response: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("response is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("failed to extract article: {0}")]
+    Extract(String),
+}
+
+pub struct Article {
+    pub url:      Url,
+    pub title:    String,
+    pub markdown: String,
+}
+
+/// An image embedded in an article, as downloaded.
+pub struct Image {
+    /// The URL as written in the article's markdown.
+    pub dest_url: String,
+    pub url:      Url,
+    pub data:     Result<Vec<u8>, Error>,
+}
+
+impl Article {
+    /// Every image the article embeds, resolved against its URL.
+    pub fn images(&self) -> Vec<(String, Url)> {
+        use pulldown_cmark::{Event, Parser, Tag};
+
+        Parser::new(&self.markdown)
+            .filter_map(|event| match event {
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    let resolved = self.url.join(&dest_url).ok()?;
+                    Some((dest_url.into_string(), resolved))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Downloads every image the article embeds, reading at most `limit` bytes of
+    /// each.
+    pub fn fetch_images(&self, limit: u64) -> Vec<Image> {
+        self.images()
+            .into_iter()
+            .map(|(dest_url, url)| {
+                let data = fetch(&url, limit);
+                Image {
+                    dest_url,
+                    url,
+                    data,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Downloads `url`, reading at most `limit` bytes.
+pub fn fetch(url: &Url, limit: u64) -> Result<Vec<u8>, Error> {
+    let response = ureq::get(url.as_str())
+        .call()
+        .map_err(|e| Error::Fetch(url.clone(), Box::new(e)))?;
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(limit + 1)
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(Error::TooLarge(limit));
+    }
+    Ok(data)
+}
+
+/// Fetches a web page and extracts its main article as markdown.
+pub fn article(url: &str, limit: u64) -> Result<Article, Error> {
+    let url = Url::parse(url.trim())?;
+    let html = fetch(&url, limit)?;
+    let product = readability::extractor::extract(&mut html.as_slice(), &url)
+        .map_err(|e| Error::Extract(e.to_string()))?;
+    Ok(Article {
+        url,
+        title: product.title,
+        markdown: html2md::parse_html(&product.content),
+    })
+}
@@ -0,0 +1,90 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+/// Files in the content directory that no note uses, and files notes use that
+/// aren't there.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Paths of files nothing links to or embeds, candidates for deletion.
+    pub unused:  Vec<String>,
+    /// Paths of notes, and of the missing files they link to or embed.
+    pub missing: Vec<(String, String)>,
+}
+
+impl Report {
+    /// Cross-references `files`, the paths of every file that isn't a note, with
+    /// `references`, the paths of notes and of what each one links to or embeds.
+    pub fn new<'a>(
+        files: &BTreeSet<String>,
+        notes: &HashSet<&str>,
+        references: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        let mut used = HashSet::new();
+        let mut missing = Vec::new();
+        for (note, path) in references {
+            if files.contains(path) {
+                used.insert(path);
+            } else if !notes.contains(path) {
+                missing.push((note.to_string(), path.to_string()));
+            }
+        }
+        missing.sort();
+        missing.dedup();
+        Self {
+            unused: files
+                .iter()
+                .filter(|x| !used.contains(x.as_str()))
+                .cloned()
+                .collect(),
+            missing,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unused.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// One line for each missing and unused file.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (note, path) in &self.missing {
+            writeln!(f, "missing: {path} (used by {note})")?;
+        }
+        for path in &self.unused {
+            writeln!(f, "unused: {path}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_referencing() {
+        let files = BTreeSet::from(["cat.png", "old.png"].map(String::from));
+        let notes = HashSet::from(["a.md", "b.md"]);
+        let report = Report::new(
+            &files,
+            &notes,
+            [
+                ("a.md", "cat.png"),
+                ("a.md", "b.md"),
+                ("b.md", "dog.png"),
+                ("b.md", "dog.png"),
+            ],
+        );
+        assert_eq!(report.unused, ["old.png"]);
+        assert_eq!(
+            report.missing,
+            [(String::from("b.md"), String::from("dog.png"))]
+        );
+        assert!(!report.is_empty());
+        assert_eq!(
+            report.to_string(),
+            "missing: dog.png (used by b.md)\nunused: old.png\n"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// The first file of every backup, listing every file backed up.
+pub const MANIFEST: &str = "manifest.json";
+
+/// The SHA-256 of every file backed up, by its path in the archive, such as
+/// `content/notes.md`.
+pub type Manifest = BTreeMap<String, String>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid {MANIFEST}: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("\"{0:?}\" doesn't start with a {MANIFEST}")]
+    NoManifest(PathBuf),
+    #[error("\"{0}\" isn't in any of the backups, or has changed in them")]
+    Missing(String),
+}
+
+/// Writes the files under each of `roots`, a directory and the name it has in the
+/// archive, to a `.tar.zst` archive at `dest`. Files whose hash is the one `since`
+/// has are only listed in the manifest, for restoring from the earlier backup.
+/// Hidden files are left out. Returns how many files were backed up, and stored.
+pub fn backup(
+    dest: &Path,
+    roots: &[(&str, &Path)],
+    since: Option<&Manifest>,
+) -> Result<(usize, usize), Error> {
+    let mut files = Vec::new();
+    let mut manifest = Manifest::new();
+    for &(name, root) in roots.iter().filter(|(_, root)| root.exists()) {
+        crate::walk(root, &mut |is_dir, path| {
+            let hidden = path
+                .file_name()
+                .is_some_and(|x| x.as_encoded_bytes().starts_with(b"."));
+            if hidden || is_dir {
+                return Ok(!hidden);
+            }
+            let rel_path = path.strip_prefix(root).unwrap_or(path);
+            let entry = format!("{name}/{}", rel_path.to_string_lossy());
+            let hash = hash(&mut File::open(path)?)?;
+            if since.and_then(|x| x.get(&entry)) != Some(&hash) {
+                files.push((entry.clone(), path.to_path_buf()));
+            }
+            manifest.insert(entry, hash);
+            Ok(true)
+        })?;
+    }
+
+    let encoder = zstd::Encoder::new(File::create(dest)?, 0)?;
+    let mut archive = tar::Builder::new(encoder);
+    let data = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST, data.as_slice())?;
+    for (entry, path) in &files {
+        archive.append_path_with_name(path, entry)?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok((manifest.len(), files.len()))
+}
+
+/// The manifest of the backup at `path`.
+pub fn manifest(path: &Path) -> Result<Manifest, Error> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    let entry = archive.entries()?.next().transpose()?;
+    match entry {
+        Some(entry) if entry.path()?.as_os_str() == MANIFEST => {
+            Ok(serde_json::from_reader(entry)?)
+        }
+        _ => Err(Error::NoManifest(path.to_path_buf())),
+    }
+}
+
+/// Restores the files listed by the last of `backups`, each of which may only have
+/// the files that changed since the one before it, into `roots`, skipping files
+/// already there. Returns how many files were written.
+pub fn restore(backups: &[PathBuf], roots: &[(&str, &Path)]) -> Result<usize, Error> {
+    let Some(last) = backups.last() else {
+        return Ok(0);
+    };
+    let mut wanted = manifest(last)?;
+    // Files already as they were backed up are left alone.
+    wanted.retain(|entry, hash| {
+        let existing = destination(entry, roots).and_then(|x| File::open(x).ok());
+        existing.and_then(|mut file| self::hash(&mut file).ok()) != Some(hash.clone())
+    });
+    let mut written = 0;
+    for backup in backups.iter().rev() {
+        let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(backup)?)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let Some(dest) = destination(&name, roots) else {
+                continue;
+            };
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            // The newest copy is restored first, and takes the file off the list.
+            if wanted.get(&name) != Some(&hash(&mut data.as_slice())?) {
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&dest, data)?;
+            wanted.remove(&name);
+            written += 1;
+        }
+    }
+    match wanted.into_keys().next() {
+        Some(missing) => Err(Error::Missing(missing)),
+        None => Ok(written),
+    }
+}
+
+/// Where the file at `entry` in a backup goes. `None` for a manifest, or anything
+/// that would end up outside of `roots`.
+pub fn destination(entry: &str, roots: &[(&str, &Path)]) -> Option<PathBuf> {
+    let (name, rel_path) = entry.split_once('/')?;
+    let (_, root) = roots.iter().find(|(x, _)| *x == name)?;
+    let rel_path = Path::new(rel_path);
+    rel_path
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+        .then(|| root.join(rel_path))
+}
+
+pub fn hash(reader: &mut impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(crate::hex(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations() {
+        let roots = [("content", Path::new("/notes"))];
+        assert_eq!(
+            destination("content/a/b.md", &roots),
+            Some(PathBuf::from("/notes/a/b.md"))
+        );
+        assert_eq!(destination("content/../etc/passwd", &roots), None);
+        assert_eq!(destination("data/store.json", &roots), None);
+        assert_eq!(destination(MANIFEST, &roots), None);
+    }
+}
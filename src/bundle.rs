@@ -0,0 +1,116 @@
+use crate::uri;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Where the page of the note at `rel_path` is in a bundle.
+pub fn page_path(rel_path: &str) -> String {
+    format!("{rel_path}.html")
+}
+
+/// What the URL `url`, on the page of the note at `from`, points to within the
+/// content directory, and its fragment, if it's a note or a file next to one.
+pub fn resolve<'a>(from: &str, url: &'a str) -> Option<(String, &'a str)> {
+    let (url, fragment) = match url.find('#') {
+        Some(at) => url.split_at(at),
+        None => (url, ""),
+    };
+    let url = url.split_once('?').map_or(url, |(url, _)| url);
+    let path = match url.strip_prefix("/note/") {
+        Some(path) => path.to_string(),
+        // Other pages of the site, and other sites.
+        None if url.is_empty() || url.starts_with('/') || url.contains(':') => {
+            return None;
+        }
+        None => match from.rsplit_once('/') {
+            Some((dir, _)) => format!("{dir}/{url}"),
+            None => url.to_string(),
+        },
+    };
+    let path = uri::percent_decode(&path)?;
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some((parts.join("/"), fragment)).filter(|(path, _)| !path.is_empty())
+}
+
+/// The URL of `to` relative to `from`, both paths within a bundle.
+pub fn relative(from: &str, to: &str) -> String {
+    let from: Vec<_> = from.split('/').collect();
+    let to: Vec<_> = to.split('/').collect();
+    let from_dir = &from[..from.len() - 1];
+    let shared = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from_dir.len() - shared];
+    let encoded: Vec<_> = to[shared..].iter().map(uri::percent_encode).collect();
+    parts.extend(encoded.iter().map(String::as_str));
+    parts.join("/")
+}
+
+/// `href` and `src` attributes, and their values.
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\b(href|src)="([^"]*)""#).unwrap());
+
+/// The URL of every link and embed in `html`.
+pub fn urls(html: &str) -> impl Iterator<Item = &str> {
+    ATTRIBUTE
+        .captures_iter(html)
+        .filter_map(|caps| Some(caps.get(2)?.as_str()))
+}
+
+/// `html` with the URL of every link and embed replaced by what `rewrite` makes of
+/// it, unless that's `None`.
+pub fn rewrite(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    ATTRIBUTE
+        .replace_all(html, |caps: &Captures| match rewrite(&caps[2]) {
+            Some(url) => format!(r#"{}="{}""#, &caps[1], crate::escape_html(&url)),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            resolve("a/b.md", "/note/c/My%20note.md#top"),
+            Some((String::from("c/My note.md"), "#top"))
+        );
+        assert_eq!(
+            resolve("a/b.md", "../img/cat.png?v=1"),
+            Some((String::from("img/cat.png"), ""))
+        );
+        assert_eq!(
+            resolve("b.md", "cat.png"),
+            Some((String::from("cat.png"), ""))
+        );
+        assert_eq!(resolve("b.md", "../../cat.png"), None);
+        assert_eq!(resolve("b.md", "/tag/rust"), None);
+        assert_eq!(resolve("b.md", "https://example.com/"), None);
+        assert_eq!(resolve("b.md", "#top"), None);
+
+        assert_eq!(relative("a/b.md.html", "a/c.png"), "c.png");
+        assert_eq!(
+            relative("a/b.md.html", "d/My note.md.html"),
+            "../d/My%20note.md.html"
+        );
+        assert_eq!(relative("b.md.html", "a/c.png"), "a/c.png");
+
+        let html = r#"<a href="/note/c.md">C</a><img src="x.png" alt="x">"#;
+        assert_eq!(urls(html).collect::<Vec<_>>(), ["/note/c.md", "x.png"]);
+        let rewritten =
+            rewrite(html, |url| (url == "x.png").then(|| String::from("y.png")));
+        assert_eq!(
+            rewritten,
+            r#"<a href="/note/c.md">C</a><img src="y.png" alt="x">"#
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Limits on how much memory caches may use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Most rendered pages kept in memory.
+    pub html_entries:      usize,
+    /// Most bytes of rendered pages kept in memory.
+    pub html_bytes:        usize,
+    /// Most outputs of filters kept in memory.
+    pub filter_entries:    usize,
+    /// Most bytes of filter outputs kept in memory.
+    pub filter_bytes:      usize,
+    /// Most highlighted code blocks kept in memory.
+    pub highlight_entries: usize,
+    /// Most bytes of highlighted code blocks kept in memory.
+    pub highlight_bytes:   usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            html_entries:      256,
+            html_bytes:        32 * 1024 * 1024,
+            filter_entries:    1024,
+            filter_bytes:      8 * 1024 * 1024,
+            highlight_entries: 4096,
+            highlight_bytes:   16 * 1024 * 1024,
+        }
+    }
+}
+
+/// A map that forgets its least recently used entries once it holds more than
+/// `max_entries` entries or `max_bytes` bytes.
+#[derive(Debug)]
+pub struct Lru<K, V> {
+    entries:     HashMap<K, (V, u64)>,
+    /// Keys by when they were last used.
+    order:       BTreeMap<u64, K>,
+    tick:        u64,
+    bytes:       usize,
+    max_entries: usize,
+    max_bytes:   usize,
+    size:        fn(&V) -> usize,
+    pub hits:    u64,
+    pub misses:  u64,
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+    pub fn new(max_entries: usize, max_bytes: usize, size: fn(&V) -> usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            max_entries,
+            max_bytes,
+            size,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let Some((value, used)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        self.tick += 1;
+        let key = self.order.remove(used).expect("entries are ordered");
+        *used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+        let size = (self.size)(&value);
+        // Something that can never fit would only push everything else out.
+        if size > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+        self.tick += 1;
+        self.bytes += size;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+        while self.entries.len() > self.max_entries || self.bytes > self.max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((value, _)) = self.entries.remove(&oldest) {
+                self.bytes -= (self.size)(&value);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((value, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+            self.bytes -= (self.size)(&value);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self::new(0, 0, |_| 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eviction() {
+        let mut lru = Lru::new(3, 10, String::len);
+        lru.insert("a", String::from("aaaa"));
+        lru.insert("b", String::from("bbbb"));
+        // Using "a" makes "b" the least recently used.
+        assert_eq!(lru.get(&"a").map(String::as_str), Some("aaaa"));
+        lru.insert("c", String::from("cc"));
+        assert_eq!(lru.bytes(), 10);
+        lru.insert("d", String::from("d"));
+        assert_eq!(lru.get(&"b"), None);
+        assert_eq!(lru.len(), 3);
+        assert_eq!(lru.bytes(), 7);
+
+        // Only three entries fit.
+        lru.insert("e", String::new());
+        assert_eq!(lru.get(&"a"), None);
+        assert!(lru.get(&"c").is_some());
+
+        lru.insert("huge", "x".repeat(11));
+        assert_eq!(lru.get(&"huge"), None);
+        assert_eq!((lru.hits, lru.misses), (2, 3));
+    }
+}
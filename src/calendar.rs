@@ -0,0 +1,181 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use pulldown_cmark::{Event as MdEvent, Parser, Tag, TagEnd};
+use serde::Deserialize;
+
+/// A day, or a moment in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum When {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub when:    When,
+    pub summary: String,
+}
+
+/// Finds `@YYYY-MM-DD` markers in a note's text outside of code, each becoming an
+/// event summarized by the rest of its paragraph.
+pub fn markers(md: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut paragraph = String::new();
+    let mut in_code = false;
+    let mut finish = |paragraph: &mut String| {
+        let mut dates = Vec::new();
+        let mut rest = paragraph.as_str();
+        let mut summary = String::new();
+        while let Some(at) = rest.find('@') {
+            let (before, after) = rest.split_at(at);
+            let at_word = before.chars().next_back().is_none_or(char::is_whitespace);
+            match after
+                .get(1..11)
+                .filter(|_| at_word)
+                .and_then(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").ok())
+            {
+                Some(date) => {
+                    dates.push(date);
+                    summary.push_str(before);
+                    rest = &after[11..];
+                }
+                None => {
+                    summary.push_str(&rest[..at + 1]);
+                    rest = &after[1..];
+                }
+            }
+        }
+        summary.push_str(rest);
+        let summary = summary.split_whitespace().collect::<Vec<_>>().join(" ");
+        events.extend(dates.into_iter().map(|date| Event {
+            when:    When::Date(date),
+            summary: summary.clone(),
+        }));
+        paragraph.clear();
+    };
+    for event in Parser::new(md) {
+        match event {
+            MdEvent::Start(Tag::CodeBlock(_)) => in_code = true,
+            MdEvent::End(TagEnd::CodeBlock) => in_code = false,
+            MdEvent::Text(text) if !in_code => paragraph.push_str(&text),
+            MdEvent::SoftBreak | MdEvent::HardBreak => paragraph.push(' '),
+            MdEvent::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::TableCell,
+            ) => finish(&mut paragraph),
+            _ => {}
+        }
+    }
+    finish(&mut paragraph);
+    events
+}
+
+/// Escapes text for an iCalendar (or vCard) property value.
+pub fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Writes an iCalendar (or vCard) content line, folding it so no line is longer
+/// than 75 octets.
+pub fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Renders events as an iCalendar document. Each event is given with the path of
+/// the note it came from, which makes up its `UID`. `updated` is when any of the
+/// events last changed.
+pub fn ics<'a>(
+    events: impl IntoIterator<Item = (&'a str, &'a Event)>,
+    updated: DateTime<Utc>,
+) -> String {
+    let stamp = updated.format("%Y%m%dT%H%M%SZ");
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//notes//calendar//EN");
+    for (i, (rel_path, event)) in events.into_iter().enumerate() {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{i}-{}@notes", escape(rel_path)));
+        push_line(&mut ics, &format!("DTSTAMP:{stamp}"));
+        match event.when {
+            When::Date(date) => {
+                let end = date.succ_opt().unwrap_or(date);
+                push_line(
+                    &mut ics,
+                    &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+                );
+                push_line(
+                    &mut ics,
+                    &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+                );
+            }
+            When::DateTime(time) => push_line(
+                &mut ics,
+                &format!("DTSTART:{}", time.format("%Y%m%dT%H%M%S")),
+            ),
+        }
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&event.summary)));
+        push_line(&mut ics, &format!("DESCRIPTION:/note/{}", escape(rel_path)));
+        push_line(&mut ics, "END:VEVENT");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_markers() {
+        let md = "\
+Dentist @2025-07-01, bring the form.
+
+Mail me@2025-07-02.example or not @2025-13-01.
+
+```
+@2025-07-03
+```
+
+- Both @2025-08-01 and @2025-08-02
+";
+        let date = |m, d| When::Date(NaiveDate::from_ymd_opt(2025, m, d).unwrap());
+        let event = |when, summary: &str| Event {
+            when,
+            summary: summary.to_string(),
+        };
+        assert_eq!(
+            markers(md),
+            [
+                event(date(7, 1), "Dentist , bring the form."),
+                event(date(8, 1), "Both and"),
+                event(date(8, 2), "Both and"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ics_lines() {
+        let event = Event {
+            when:    When::Date(NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()),
+            summary: format!("Party; bring snacks, {}", "x".repeat(80)),
+        };
+        let ics = ics([("a.md", &event)], Utc::now());
+        assert!(ics.contains("DTSTART;VALUE=DATE:20251231\r\n"));
+        assert!(ics.contains("DTEND;VALUE=DATE:20260101\r\n"));
+        assert!(ics.contains("SUMMARY:Party\\; bring snacks\\, xxx"));
+        assert!(ics.lines().all(|x| x.len() <= 76));
+        assert!(ics.contains("\r\n x"));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{IndexedDocument, escape_html};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// A flashcard found in a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    pub front: String,
+    pub back:  String,
+}
+
+impl Card {
+    /// What the card's reviews are kept under: its note and its front.
+    pub fn key(&self, rel_path: &str) -> String {
+        format!("{rel_path}: {}", self.front)
+    }
+}
+
+/// Finds the cards in a note's text, ignoring code blocks: questions on lines
+/// starting with `Q:`, each answered by the lines after it starting with `A:` up to
+/// the end of the paragraph, and paragraphs hiding words in `{{...}}` (or Anki's
+/// `{{c1::...}}`), which are blanked on the front.
+pub fn find(md: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut paragraph = Vec::new();
+    let mut fence = None;
+    for line in md.lines() {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        }
+        if fence.is_some() || trimmed.is_empty() {
+            cards.extend(paragraph_cards(&paragraph));
+            paragraph.clear();
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    cards.extend(paragraph_cards(&paragraph));
+    cards
+}
+
+/// The question and answer pairs in a paragraph's `lines`, followed by the cloze
+/// made of the rest, if it hides anything.
+fn paragraph_cards(lines: &[&str]) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut text = Vec::new();
+    let mut question: Option<(String, Option<String>)> = None;
+    let mut finish = |question: Option<(String, Option<String>)>| {
+        if let Some((front, Some(back))) = question {
+            cards.push(Card { front, back });
+        }
+    };
+    for line in lines {
+        if let Some(front) = line.strip_prefix("Q:") {
+            finish(question.take());
+            question = Some((front.trim().to_string(), None));
+        } else if let Some(back) = line.strip_prefix("A:").filter(|_| question.is_some())
+        {
+            if let Some((_, answer)) = &mut question {
+                answer.get_or_insert_default().push_str(back.trim());
+            }
+        } else if let Some((front, answer)) = &mut question {
+            let part = answer.as_mut().unwrap_or(front);
+            part.push(' ');
+            part.push_str(line);
+        } else {
+            text.push(*line);
+        }
+    }
+    finish(question);
+    cards.extend(cloze(&text.join(" ")));
+    cards
+}
+
+/// A card blanking what `text` hides in `{{...}}` on the front, and showing it on
+/// the back. `None` when it hides nothing.
+fn cloze(text: &str) -> Option<Card> {
+    let mut front = String::new();
+    let mut back = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let hidden = &rest[start + 2..start + 2 + len];
+        let hidden = hidden
+            .split_once("::")
+            .filter(|(n, _)| {
+                n.strip_prefix('c').is_some_and(|n| {
+                    !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())
+                })
+            })
+            .map_or(hidden, |(_, hidden)| hidden);
+        front.push_str(&rest[..start]);
+        front.push_str("[…]");
+        back.push_str(&rest[..start]);
+        back.push_str(hidden);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    if front.is_empty() {
+        return None;
+    }
+    front.push_str(rest);
+    back.push_str(rest);
+    Some(Card { front, back })
+}
+
+/// Cards as tab-separated values Anki can import: the front, the back and the
+/// tags, given with each card.
+pub fn tsv<'a>(cards: impl IntoIterator<Item = (&'a Card, &'a [String])>) -> String {
+    let field = |x: &str| x.replace('\t', " ").replace('\n', "<br>");
+    let mut tsv = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+    for (card, tags) in cards {
+        let tags: Vec<_> = tags.iter().map(|x| x.replace(' ', "_")).collect();
+        tsv.push_str(&format!(
+            "{}\t{}\t{}\n",
+            field(&card.front),
+            field(&card.back),
+            field(&tags.join(" "))
+        ));
+    }
+    tsv
+}
+
+/// How well the owner knows a card, scheduled with SM-2.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    /// Times in a row the card was remembered.
+    pub repetitions: u32,
+    /// Days between the last review and the next.
+    pub interval:    u32,
+    /// How quickly the interval grows.
+    pub ease:        f32,
+    pub due:         NaiveDate,
+}
+
+impl Default for Review {
+    /// A card never reviewed, due right away.
+    fn default() -> Self {
+        Self {
+            repetitions: 0,
+            interval:    0,
+            ease:        2.5,
+            due:         NaiveDate::MIN,
+        }
+    }
+}
+
+impl Review {
+    /// Schedules the next review after an answer on `today` graded `quality`, from 0
+    /// for a blank to 5 for a perfect answer. Below 3, the card starts over.
+    pub fn grade(&mut self, quality: u8, today: NaiveDate) {
+        let quality = quality.min(5);
+        if quality < 3 {
+            self.repetitions = 0;
+            self.interval = 1;
+        } else {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f32 * self.ease).round() as u32,
+            };
+            self.repetitions += 1;
+        }
+        let miss = f32::from(5 - quality);
+        self.ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(1.3);
+        self.due = today
+            .checked_add_days(Days::new(self.interval.into()))
+            .unwrap_or(NaiveDate::MAX);
+    }
+
+    pub fn is_due(&self, today: NaiveDate) -> bool {
+        self.due <= today
+    }
+}
+
+/// Lists the flashcards in each note, with their answers hidden until they're
+/// opened, and a way to export them for Anki, or for the `owner`, to review them.
+pub fn html(index: &[IndexedDocument], owner: bool) -> String {
+    let mut page = String::from(r#"<p><a href="/cards.tsv">Export for Anki</a>"#);
+    if owner {
+        page.push_str(r#" · <a href="/cards/review">Review</a>"#);
+    }
+    page.push_str("</p>");
+    let mut empty = true;
+    for doc in index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .filter(|doc| !doc.cards.is_empty())
+    {
+        empty = false;
+        page.push_str(&format!(
+            r#"<h2><a href="/note/{path}">{title}</a></h2><dl class="cards">"#,
+            path = doc.rel_path,
+            title = escape_html(&doc.title),
+        ));
+        for card in &doc.cards {
+            page.push_str(&format!(
+                r#"<dt>{front}</dt><dd><details><summary>Answer</summary>{back}</details></dd>"#,
+                front = escape_html(&card.front),
+                back = escape_html(&card.back),
+            ));
+        }
+        page.push_str("</dl>");
+    }
+    match empty {
+        true => String::from("<p>No notes have flashcards.</p>"),
+        false => page,
+    }
+}
+
+/// Every listed note's flashcards, tagged like their note, for importing into Anki.
+pub fn listed_tsv(index: &[IndexedDocument]) -> String {
+    tsv(index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .flat_map(|doc| doc.cards.iter().map(|card| (card, doc.tags.as_slice()))))
+}
+
+/// The first of the `due` flashcards, with buttons for how well it was remembered
+/// under its hidden answer.
+pub fn review_html(due: &[(&IndexedDocument, &Card)]) -> String {
+    let Some((doc, card)) = due.first() else {
+        return String::from("<p>No cards are due.</p>");
+    };
+    let grades = [(0, "Forgot"), (3, "Hard"), (4, "Good"), (5, "Easy")];
+    let buttons: String = grades
+        .iter()
+        .map(|(grade, label)| {
+            format!(r#"<button name="grade" value="{grade}">{label}</button>"#)
+        })
+        .collect();
+    format!(
+        r#"<p>{count} due</p><div class="card"><p>{front}</p><details><summary>Answer</summary><p>{back}</p><form method="post" action="/api/cards/review"><input type="hidden" name="card" value="{key}">{buttons}</form></details><p>From <a href="/note/{path}">{title}</a></p></div>"#,
+        count = due.len(),
+        front = escape_html(&card.front),
+        back = escape_html(&card.back),
+        key = escape_html(&card.key(&doc.rel_path)),
+        path = doc.rel_path,
+        title = escape_html(&doc.title),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(front: &str, back: &str) -> Card {
+        Card {
+            front: front.to_string(),
+            back:  back.to_string(),
+        }
+    }
+
+    #[test]
+    fn finding() {
+        let md = "Q: What is SM-2?\nA: A spaced repetition\nalgorithm.\nQ: Unanswered?\n\n\
+                  The capital of France is {{Paris}}, of Peru {{c1::Lima}}.\n\n\
+                  ```\nQ: In code?\nA: Ignored {{x}}\n```\n\
+                  Not {{closed.";
+        assert_eq!(
+            find(md),
+            [
+                card("What is SM-2?", "A spaced repetition algorithm."),
+                card(
+                    "The capital of France is […], of Peru […].",
+                    "The capital of France is Paris, of Peru Lima."
+                ),
+            ]
+        );
+        assert_eq!(
+            tsv([(&card("a\tb", "c\nd"), &[String::from("big cats")][..])]),
+            "#separator:tab\n#html:true\n#tags column:3\na b\tc<br>d\tbig_cats\n"
+        );
+    }
+
+    #[test]
+    fn scheduling() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let mut review = Review::default();
+        assert!(review.is_due(today));
+        review.grade(5, today);
+        assert_eq!(
+            (review.interval, review.due.to_string()),
+            (1, "2025-01-02".into())
+        );
+        review.grade(4, today);
+        assert_eq!(review.interval, 6);
+        review.grade(4, today);
+        assert_eq!(review.interval, 16);
+        assert!(!review.is_due(today));
+        review.grade(1, today);
+        assert_eq!((review.repetitions, review.interval), (0, 1));
+        assert!(review.ease < 2.6 && review.ease >= 1.3);
+    }
+}
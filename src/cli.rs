@@ -0,0 +1,91 @@
+use crate::Config;
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Serves a directory of markdown notes.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// The config file [default: notes/notes.toml in the user's config directory]
+    #[arg(long)]
+    pub config:    Option<PathBuf>,
+    /// Where to listen, overriding `bind` in the config
+    #[arg(long)]
+    pub bind:      Option<SocketAddr>,
+    /// The directory of notes, overriding `content_path` in the config
+    #[arg(long)]
+    pub content:   Option<PathBuf>,
+    /// The most detailed messages to log: off, error, warn, info, debug or trace
+    #[arg(long, default_value = "debug")]
+    pub log_level: LevelFilter,
+    /// Answer an assistant over MCP on stdin and stdout instead of serving HTTP
+    #[arg(long)]
+    pub mcp:       bool,
+    #[command(subcommand)]
+    pub command:   Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List files no note uses and files notes use that are missing, then exit,
+    /// failing if any are missing
+    Check,
+    /// Check that the notes haven't changed since they were last modified, by their
+    /// checksums, then exit, failing if any did or can't be read
+    Verify,
+    /// Request the index, a note, the feed and the health check from a running
+    /// server, then exit, failing if any of them fail
+    Selfcheck {
+        /// The server to check [default: the config's `bind` address]
+        #[arg(long)]
+        url: Option<url::Url>,
+    },
+    /// Upload the site, as a running server serves it, to an S3-compatible bucket,
+    /// sending only what changed since the last time, then exit. Credentials are
+    /// read from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+    Publish {
+        /// Where to, such as `s3://bucket/prefix`
+        #[arg(long)]
+        target:   String,
+        /// The server to publish [default: the config's `bind` address]
+        #[arg(long)]
+        url:      Option<url::Url>,
+        /// The S3 API to use [default: AWS_ENDPOINT_URL, or AWS's for the region]
+        #[arg(long)]
+        endpoint: Option<url::Url>,
+        /// The bucket's region [default: AWS_REGION, or us-east-1]
+        #[arg(long)]
+        region:   Option<String>,
+    },
+    /// Print the lines of a torrc publishing the onion service, then exit
+    Torrc,
+    /// Write the notes, the data directory and the config directory to a
+    /// `.tar.zst` archive, then exit
+    Backup {
+        dest:  PathBuf,
+        /// Only store the files that changed since this earlier backup, which is
+        /// needed to restore this one
+        #[arg(long)]
+        since: Option<PathBuf>,
+    },
+    /// Restore the files of a backup, skipping those already there, then exit
+    Restore {
+        /// The backup, preceded by the ones it builds on, oldest first
+        #[arg(required = true)]
+        backups: Vec<PathBuf>,
+    },
+}
+
+impl Args {
+    /// Overrides what `config` says with what was given on the command line.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+        if let Some(content) = &self.content {
+            config.content_path = content.clone();
+        }
+    }
+}
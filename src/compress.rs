@@ -0,0 +1,161 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Compressing HTML responses for clients that accept it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Gzip responses for clients sending `Accept-Encoding: gzip`.
+    pub gzip:     bool,
+    /// Compress responses with Brotli for clients sending `Accept-Encoding: br`,
+    /// which they get rather than gzip unless they prefer that.
+    pub brotli:   bool,
+    /// Smaller responses are sent as they are, in bytes.
+    pub min_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            gzip:     true,
+            brotli:   true,
+            min_size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// The encoding's name in `Accept-Encoding` and `Content-Encoding`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+}
+
+/// Brotli's quality, from 0 to 11. Higher levels are too slow to compress pages as
+/// they're sent.
+const BROTLI_QUALITY: u32 = 5;
+/// The base 2 logarithm of Brotli's window size.
+const BROTLI_WINDOW: u32 = 22;
+
+/// How to encode the responses to a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Encoder {
+    encoding: Option<Encoding>,
+    min_size: usize,
+}
+
+impl Encoder {
+    /// Picks the encoding the request's `Accept-Encoding` header prefers, of those
+    /// that are on.
+    pub fn new(config: &Config, accept_encoding: Option<&str>) -> Self {
+        let header = accept_encoding.unwrap_or_default();
+        let mut encoding = None;
+        let mut best = 0.0;
+        // Brotli comes first, to win ties.
+        for (on, candidate) in [
+            (config.brotli, Encoding::Brotli),
+            (config.gzip, Encoding::Gzip),
+        ] {
+            let q = quality(header, candidate.name());
+            if on && q > best {
+                (encoding, best) = (Some(candidate), q);
+            }
+        }
+        Self {
+            encoding,
+            min_size: config.min_size,
+        }
+    }
+
+    /// How a body of `len` bytes would be encoded.
+    pub fn encoding(&self, len: usize) -> Option<Encoding> {
+        self.encoding.filter(|_| len >= self.min_size)
+    }
+
+    /// `body`, compressed if that's worth it, and how it was.
+    pub fn encode(&self, body: Vec<u8>) -> (Vec<u8>, Option<Encoding>) {
+        let Some(encoding) = self.encoding(body.len()) else {
+            return (body, None);
+        };
+        let compressed = match encoding {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&body).and_then(|()| encoder.finish())
+            }
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    4096,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder.write_all(&body).map(|()| encoder.into_inner())
+            }
+        };
+        match compressed {
+            Ok(compressed) => (compressed, Some(encoding)),
+            Err(e) => {
+                log::error!("Failed to compress response: {e}");
+                (body, None)
+            }
+        }
+    }
+}
+
+/// How much an `Accept-Encoding` header wants the encoding `name`, from its
+/// quality values: 0 when it doesn't.
+fn quality(header: &str, name: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for item in header.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or_default().trim();
+        let q = params
+            .find_map(|x| x.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if coding.eq_ignore_ascii_case(name) {
+            return q;
+        }
+        if coding == "*" {
+            wildcard = q;
+        }
+    }
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiating() {
+        let config = Config::default();
+        let encoding =
+            |header: &str| Encoder::new(&config, Some(header)).encoding(usize::MAX);
+        assert_eq!(encoding("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(encoding("br;q=0.5, GZIP;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(encoding("*"), Some(Encoding::Brotli));
+        assert_eq!(encoding("br;q=0, *"), Some(Encoding::Gzip));
+        assert_eq!(encoding("identity"), None);
+        assert_eq!(encoding(""), None);
+
+        let config = Config {
+            brotli: false,
+            ..Config::default()
+        };
+        let encoder = Encoder::new(&config, Some("br, gzip"));
+        assert_eq!(encoder.encoding(usize::MAX), Some(Encoding::Gzip));
+        assert_eq!(encoder.encoding(config.min_size - 1), None);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::Serialize;
+
+/// Most tokens in a chunk, unless a single paragraph is longer.
+const MAX_TOKENS: usize = 400;
+
+/// Most chunks handed out for one query.
+pub const MAX_CHUNKS: usize = 50;
+
+/// Part of a note, small enough to paste into a language model's prompt.
+#[derive(Debug, Clone, Serialize)]
+pub struct Chunk {
+    pub title:    String,
+    pub url:      String,
+    /// The heading the chunk falls under, if any.
+    pub heading:  Option<String>,
+    pub markdown: String,
+    pub tokens:   usize,
+    pub score:    f32,
+}
+
+/// A rough count of the tokens in `text`, at four characters to a token.
+pub fn tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Splits a note's markdown into sections at its headings, and sections into
+/// chunks of whole paragraphs of about `MAX_TOKENS`. The meta block is left out.
+pub fn split(markdown: &str) -> Vec<(Option<String>, String)> {
+    let mut chunks = Vec::new();
+    let mut heading = None;
+    let mut chunk = String::new();
+    let mut fence: Option<&str> = None;
+    let mut meta = false;
+    let mut flush = |heading: &Option<String>, chunk: &mut String| {
+        let text = chunk.trim();
+        if !text.is_empty() {
+            chunks.push((heading.clone(), text.to_string()));
+        }
+        chunk.clear();
+    };
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker)
+                && trimmed.trim_start_matches(marker).trim().is_empty()
+            {
+                fence = None;
+                if std::mem::take(&mut meta) {
+                    continue;
+                }
+            } else if meta {
+                continue;
+            }
+        } else if let Some(marker) =
+            ["```", "~~~"].into_iter().find(|x| trimmed.starts_with(x))
+        {
+            fence = Some(marker);
+            if trimmed[marker.len()..].trim() == "meta" {
+                meta = true;
+                continue;
+            }
+        } else if trimmed.starts_with('#') {
+            let title = trimmed.trim_start_matches('#');
+            if title.is_empty() || title.starts_with(' ') {
+                flush(&heading, &mut chunk);
+                heading = Some(title.trim().trim_end_matches('#').trim().to_string());
+            }
+        } else if line.trim().is_empty() && tokens(&chunk) >= MAX_TOKENS {
+            flush(&heading, &mut chunk);
+            continue;
+        }
+        chunk.push_str(line);
+        chunk.push('\n');
+    }
+    flush(&heading, &mut chunk);
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks() {
+        let paragraph = "word ".repeat(MAX_TOKENS);
+        let markdown = format!(
+            "```meta\ntitle = \"A\"\n```\n\nIntro.\n\n## First ##\n\n```sh\n# not a heading\n```\n\n# Second\n\n{paragraph}\n\n{paragraph}\n"
+        );
+        let chunks = split(&markdown);
+        let headings: Vec<_> = chunks.iter().map(|(x, _)| x.as_deref()).collect();
+        assert_eq!(
+            headings,
+            [None, Some("First"), Some("Second"), Some("Second")]
+        );
+        assert_eq!(chunks[0].1, "Intro.");
+        assert!(chunks[1].1.contains("# not a heading"));
+        assert!(chunks[3].1.starts_with("word"));
+        assert_eq!(tokens("12345"), 2);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::http::Header;
+use serde::{Deserialize, Serialize};
+
+/// Which other origins' pages may use the JSON API from the browser.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Origins such as `https://app.example.com`, or `*` for any.
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    /// Request headers pages may send, besides the ones browsers always allow.
+    pub headers: Vec<String>,
+    /// How long browsers may remember a preflight's answer, in seconds.
+    pub max_age: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            methods: vec![String::from("GET"), String::from("POST")],
+            headers: vec![String::from("Authorization"), String::from("Content-Type")],
+            max_age: 600,
+        }
+    }
+}
+
+impl Config {
+    /// Headers allowing a request from `origin`, or none when it isn't allowed. A
+    /// preflight also learns what it may send.
+    pub fn headers(&self, origin: Option<&str>, preflight: bool) -> Vec<Header> {
+        let Some(origin) = origin.filter(|origin| {
+            self.origins
+                .iter()
+                .any(|x| x == "*" || x.eq_ignore_ascii_case(origin))
+        }) else {
+            return Vec::new();
+        };
+        let mut headers = vec![
+            ("Access-Control-Allow-Origin", origin.to_string()),
+            ("Vary", String::from("Origin")),
+        ];
+        if preflight {
+            headers.extend([
+                ("Access-Control-Allow-Methods", self.methods.join(", ")),
+                ("Access-Control-Allow-Headers", self.headers.join(", ")),
+                ("Access-Control-Max-Age", self.max_age.to_string()),
+            ]);
+        }
+        headers
+            .into_iter()
+            .filter_map(|(name, value)| Header::from_bytes(name, value).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins() {
+        let config = Config {
+            origins: vec![String::from("https://app.example.com")],
+            ..Config::default()
+        };
+        assert!(
+            config
+                .headers(Some("https://evil.example.com"), false)
+                .is_empty()
+        );
+        assert!(config.headers(None, true).is_empty());
+        let headers = config.headers(Some("https://app.example.com"), true);
+        let get = |name| {
+            headers
+                .iter()
+                .find(|x| x.field.as_str().eq_ignore_ascii_case(name))
+                .map(|x| x.value.as_str())
+        };
+        assert_eq!(
+            get("Access-Control-Allow-Origin"),
+            Some("https://app.example.com")
+        );
+        assert_eq!(get("Access-Control-Allow-Methods"), Some("GET, POST"));
+        assert_eq!(get("Access-Control-Max-Age"), Some("600"));
+    }
+}
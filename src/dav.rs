@@ -0,0 +1,119 @@
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// Where the content directory is mounted.
+pub const PREFIX: &str = "/dav/";
+
+/// What `OPTIONS` says can be done.
+pub const ALLOW: &str = "OPTIONS, GET, HEAD, PUT, PROPFIND, MKCOL";
+
+/// A file or directory in a `PROPFIND` response.
+#[derive(Debug)]
+pub struct Resource {
+    /// Its path within the content directory, empty for the directory itself.
+    pub rel_path:   String,
+    pub collection: bool,
+    pub len:        u64,
+    pub modified:   SystemTime,
+}
+
+/// The path within the content directory that `path`, what's after [`PREFIX`],
+/// names. `None` when it would leave the directory, or names a hidden file.
+pub fn rel_path(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            part if part.starts_with('.') => return None,
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// The URL of the file or directory at `rel_path`, ending with `/` for a directory.
+pub fn href(rel_path: &str, collection: bool) -> String {
+    let mut href = String::from(PREFIX);
+    let parts: Vec<_> = rel_path
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .map(crate::uri::percent_encode)
+        .collect();
+    href.push_str(&parts.join("/"));
+    if collection && !parts.is_empty() {
+        href.push('/');
+    }
+    href
+}
+
+/// The password of a `Basic` `Authorization` header, which is how WebDAV clients
+/// send credentials. The user name is ignored.
+pub fn basic_password(authorization: &str) -> Option<String> {
+    let encoded = authorization.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+    Some(password.to_string())
+}
+
+/// A `207 Multi-Status` body describing `resources`.
+pub fn multistatus(resources: &[Resource]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#,
+    );
+    for resource in resources {
+        let name = resource.rel_path.rsplit('/').next().unwrap_or_default();
+        let modified =
+            DateTime::<Utc>::from(resource.modified).format("%a, %d %b %Y %H:%M:%S GMT");
+        let kind = match resource.collection {
+            true => String::from("<D:resourcetype><D:collection/></D:resourcetype>"),
+            false => format!(
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>{}</D:getcontenttype>",
+                resource.len,
+                mime_guess::from_path(&resource.rel_path).first_or_octet_stream()
+            ),
+        };
+        xml.push_str(&format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname>{kind}<D:getlastmodified>{modified}</D:getlastmodified></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+            crate::escape_html(&href(&resource.rel_path, resource.collection)),
+            crate::escape_html(name),
+        ));
+    }
+    xml.push_str("</D:multistatus>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(rel_path("a/./b.md"), Some(String::from("a/b.md")));
+        assert_eq!(rel_path(""), Some(String::new()));
+        assert_eq!(rel_path("a/../../etc/passwd"), None);
+        assert_eq!(rel_path(".git/config"), None);
+        assert_eq!(href("a/My note.md", false), "/dav/a/My%20note.md");
+        assert_eq!(href("a", true), "/dav/a/");
+        assert_eq!(href("", true), "/dav/");
+        // "notes:token"
+        assert_eq!(
+            basic_password("Basic bm90ZXM6dG9rZW4="),
+            Some(String::from("token"))
+        );
+        assert_eq!(basic_password("Bearer token"), None);
+
+        let xml = multistatus(&[Resource {
+            rel_path:   String::from("a"),
+            collection: true,
+            len:        0,
+            modified:   SystemTime::UNIX_EPOCH,
+        }]);
+        assert!(xml.contains("<D:href>/dav/a/</D:href>"));
+        assert!(xml.contains("<D:collection/>"));
+        assert!(xml.contains("Thu, 01 Jan 1970 00:00:00 GMT"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// How often an idle stream gets a comment, which keeps proxies from closing it
+/// and notices clients that have gone.
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// Most clients listening at once, each of which has a thread.
+const MAX_LISTENERS: usize = 64;
+
+/// Formats a server-sent event.
+fn message(name: &str, data: &str) -> String {
+    let mut message = format!("event: {name}\n");
+    for line in data.lines() {
+        message.push_str(&format!("data: {line}\n"));
+    }
+    message.push('\n');
+    message
+}
+
+/// Sends events to the clients listening on `/events`.
+#[derive(Debug, Default)]
+pub struct Broadcast {
+    listeners: Vec<Sender<String>>,
+}
+
+impl Broadcast {
+    /// Whether another client would be too many, after forgetting the ones that
+    /// have gone.
+    pub fn is_full(&mut self) -> bool {
+        if self.listeners.len() >= MAX_LISTENERS {
+            self.listeners
+                .retain(|x| x.send(String::from(":\n\n")).is_ok());
+        }
+        self.listeners.len() >= MAX_LISTENERS
+    }
+
+    /// Streams events to `writer`, the body of a client's response, on a thread of
+    /// its own until the client goes away. Check [`Self::is_full`] first.
+    pub fn listen(&mut self, mut writer: Box<dyn Write + Send>) {
+        let (send, receive) = mpsc::channel();
+        self.listeners.push(send);
+        std::thread::spawn(move || {
+            // A comment first, so the response's headers are sent at once.
+            let mut message = String::from(":\n\n");
+            loop {
+                let sent = writer
+                    .write_all(message.as_bytes())
+                    .and_then(|()| writer.flush());
+                if sent.is_err() {
+                    return;
+                }
+                message = match receive.recv_timeout(KEEPALIVE) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => String::from(":\n\n"),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+            }
+        });
+    }
+
+    /// Sends the event `name` to every client, forgetting the ones that have gone.
+    pub fn send(&mut self, name: &str, data: &str) {
+        let message = message(name, data);
+        self.listeners.retain(|x| x.send(message.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        assert_eq!(
+            message("note-changed", "a.md"),
+            "event: note-changed\ndata: a.md\n\n"
+        );
+        assert_eq!(message("x", "1\n2"), "event: x\ndata: 1\ndata: 2\n\n");
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::NaiveDateTime;
+
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+/// Finds the TIFF structure inside a JPEG's `APP1` Exif segment.
+fn jpeg_tiff(data: &[u8]) -> Option<&[u8]> {
+    let mut rest = data.strip_prefix(&[0xFF, 0xD8])?;
+    while rest.len() >= 4 && rest[0] == 0xFF {
+        let marker = rest[1];
+        // Start of scan, there's no metadata past this point.
+        if marker == 0xDA {
+            break;
+        }
+        let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let segment = rest.get(4..2 + len)?;
+        let exif = segment.strip_prefix(b"Exif\0\0").filter(|_| marker == 0xE1);
+        if exif.is_some() {
+            return exif;
+        }
+        rest = &rest[2 + len..];
+    }
+    None
+}
+
+struct Tiff<'a> {
+    data:   &'a [u8],
+    little: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let tiff = Self { data, little };
+        (tiff.u16(2)? == 42).then_some(tiff)
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b = self.data.get(at..at + 2)?;
+        Some(if self.little {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b = self.data.get(at..at + 4)?;
+        Some(if self.little {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+
+    /// Returns the offset of the value field for `tag` in the IFD at `ifd`.
+    fn find(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|&entry| self.u16(entry) == Some(tag))
+            .map(|entry| entry + 8)
+    }
+
+    fn date(&self, ifd: usize, tag: u16) -> Option<NaiveDateTime> {
+        let value = self.find(ifd, tag)?;
+        // "YYYY:MM:DD HH:MM:SS\0" never fits inline, so this is always an offset.
+        let offset = self.u32(value)? as usize;
+        let raw = self.data.get(offset..offset + 19)?;
+        NaiveDateTime::parse_from_str(std::str::from_utf8(raw).ok()?, "%Y:%m:%d %H:%M:%S")
+            .ok()
+    }
+}
+
+/// Reads the date a JPEG photo was taken, falling back to the image's `DateTime`.
+pub fn date_taken(data: &[u8]) -> Option<NaiveDateTime> {
+    let tiff = Tiff::new(jpeg_tiff(data)?)?;
+    let ifd0 = tiff.u32(4)? as usize;
+    tiff.find(ifd0, TAG_EXIF_IFD)
+        .and_then(|value| tiff.u32(value))
+        .and_then(|exif| tiff.date(exif as usize, TAG_DATE_TIME_ORIGINAL))
+        .or_else(|| tiff.date(ifd0, TAG_DATE_TIME))
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Removes Exif and XMP metadata (which is where GPS coordinates live) from JPEG and
+/// PNG images. Anything else is returned unchanged.
+pub fn strip(data: Vec<u8>) -> Vec<u8> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(&data).unwrap_or(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        strip_png(&data).unwrap_or(data)
+    } else {
+        data
+    }
+}
+
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+    let mut rest = &data[2..];
+    while rest.len() >= 4 && rest[0] == 0xFF && rest[1] != 0xDA {
+        let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let segment = rest.get(..2 + len)?;
+        // APP1 holds both Exif and XMP.
+        if rest[1] != 0xE1 {
+            out.extend_from_slice(segment);
+        }
+        rest = &rest[2 + len..];
+    }
+    out.extend_from_slice(rest);
+    Some(out)
+}
+
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(PNG_SIGNATURE);
+    let mut rest = &data[PNG_SIGNATURE.len()..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        // Length, type, data and CRC.
+        let chunk = rest.get(..12 + len)?;
+        let is_metadata = match &chunk[4..8] {
+            b"eXIf" => true,
+            b"iTXt" => chunk[8..].starts_with(b"XML:com.adobe.xmp\0"),
+            _ => false,
+        };
+        if !is_metadata {
+            out.extend_from_slice(chunk);
+        }
+        rest = &rest[12 + len..];
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A big-endian JPEG header with an IFD0 holding only a `DateTime` entry.
+    fn jpeg_with_date(date: &[u8; 20]) -> Vec<u8> {
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(b"MM\0\x2a\0\0\0\x08");
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&TAG_DATE_TIME.to_be_bytes());
+        tiff.extend_from_slice(&2u16.to_be_bytes());
+        tiff.extend_from_slice(&20u32.to_be_bytes());
+        tiff.extend_from_slice(&26u32.to_be_bytes());
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff.extend_from_slice(date);
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+        jpeg
+    }
+
+    #[test]
+    fn date() {
+        let jpeg = jpeg_with_date(b"2024:06:01 12:30:00\0");
+        assert_eq!(
+            date_taken(&jpeg),
+            NaiveDateTime::parse_from_str("2024-06-01 12:30:00", "%Y-%m-%d %H:%M:%S")
+                .ok()
+        );
+        assert_eq!(date_taken(b"not a jpeg"), None);
+    }
+
+    #[test]
+    fn strip_metadata() {
+        let jpeg = jpeg_with_date(b"2024:06:01 12:30:00\0");
+        let stripped = strip(jpeg);
+        assert_eq!(stripped, [0xFF, 0xD8, 0xFF, 0xDA]);
+        assert_eq!(date_taken(&stripped), None);
+        assert_eq!(strip(b"not a jpeg".to_vec()), b"not a jpeg");
+    }
+}
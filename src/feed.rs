@@ -0,0 +1,284 @@
+use crate::escape_html;
+use chrono::{DateTime, Utc};
+use std::ops::Range;
+
+/// A note as it appears in a feed.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Absolute URL of the note, which also identifies the entry in RSS.
+    pub id:        String,
+    /// A `tag:` URI made from the note's path, which identifies the entry in Atom
+    /// even if the site moves.
+    pub tag:       String,
+    /// `None` for micro-posts.
+    pub title:     Option<String>,
+    /// Where the entry leads: the note, or the page a bookmark points to.
+    pub link:      String,
+    pub published: DateTime<Utc>,
+    pub updated:   DateTime<Utc>,
+    pub summary:   Option<String>,
+    /// The whole note as HTML, for micro-posts, which are short enough.
+    pub content:   Option<String>,
+    /// The URL of the license the note is published under.
+    pub license:   Option<String>,
+    /// A content warning, shown instead of the summary, with the content hidden
+    /// behind it.
+    pub warning:   Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rss,
+    Atom,
+}
+
+impl Format {
+    /// The format served under the file name `file`.
+    pub fn from_file(file: &str) -> Option<Self> {
+        match file {
+            "feed.xml" => Some(Self::Rss),
+            "atom.xml" => Some(Self::Atom),
+            _ => None,
+        }
+    }
+
+    pub fn file(self) -> &'static str {
+        match self {
+            Self::Rss => "feed.xml",
+            Self::Atom => "atom.xml",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Rss => "application/rss+xml; charset=utf-8",
+            Self::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+
+    pub fn render(self, feed: &Feed, page: &Page) -> String {
+        match self {
+            Self::Rss => rss(feed, page),
+            Self::Atom => atom(feed, page),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Feed {
+    pub title:   String,
+    /// Who wrote the notes, which Atom requires.
+    pub author:  String,
+    /// Absolute URL of the feed itself.
+    pub url:     String,
+    /// Absolute URL of the page listing the same notes.
+    pub site:    String,
+    /// Newest first.
+    pub entries: Vec<Entry>,
+}
+
+/// Part of a feed: either the current document, with the newest entries, or one of
+/// the complete archives (RFC 5005) older entries are paged into. Archives are
+/// numbered from the oldest, so they keep their entries as new ones come in.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Page {
+    /// Which of the feed's entries the page holds.
+    pub entries: Range<usize>,
+    pub archive: Option<usize>,
+    /// The next older archive.
+    pub prev:    Option<usize>,
+    /// The next newer archive.
+    pub next:    Option<usize>,
+}
+
+impl Page {
+    /// Pages a feed of `len` entries into `size` entries per document. `None` when
+    /// there's no such archive.
+    pub fn new(len: usize, size: usize, archive: Option<usize>) -> Option<Self> {
+        let size = size.max(1);
+        let archives = len / size;
+        let Some(archive) = archive else {
+            return Some(Self {
+                entries: 0..len.min(size),
+                archive: None,
+                prev:    (len > size).then_some(archives),
+                next:    None,
+            });
+        };
+        if archive == 0 || archive > archives {
+            return None;
+        }
+        Some(Self {
+            entries: len - archive * size..len - (archive - 1) * size,
+            archive: Some(archive),
+            prev:    (archive > 1).then(|| archive - 1),
+            next:    (archive < archives).then(|| archive + 1),
+        })
+    }
+
+    fn url(feed: &Feed, archive: Option<usize>) -> String {
+        match archive {
+            Some(archive) => format!("{}?archive={archive}", feed.url),
+            None => feed.url.clone(),
+        }
+    }
+
+    /// Links to the page itself and its neighbours, and whether it's an archive.
+    fn links(&self, feed: &Feed, tag: &str) -> String {
+        let link = |rel: &str, archive| {
+            format!(
+                r#"<{tag} rel="{rel}" href="{}"/>"#,
+                escape_html(&Self::url(feed, archive))
+            )
+        };
+        let mut links = link("self", self.archive);
+        if let Some(prev) = self.prev {
+            links.push_str(&link("prev-archive", Some(prev)));
+        }
+        if self.archive.is_some() {
+            links.push_str(&link("current", None));
+            if let Some(next) = self.next {
+                links.push_str(&link("next-archive", Some(next)));
+            }
+            links.push_str("<fh:archive/>");
+        }
+        links
+    }
+}
+
+/// Renders a page of the feed as RSS 2.0.
+pub fn rss(feed: &Feed, page: &Page) -> String {
+    let entries = &feed.entries[page.entries.clone()];
+    let updated = entries.iter().map(|x| x.updated).max().unwrap_or_default();
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:fh="http://purl.org/syndication/history/1.0" xmlns:creativeCommons="http://backend.userland.com/creativeCommonsRssModule">
+<channel>
+"#,
+    );
+    xml.push_str(&format!(
+        "<title>{title}</title><link>{site}</link><description>{title}</description><lastBuildDate>{updated}</lastBuildDate>{links}\n",
+        title = escape_html(&feed.title),
+        site = escape_html(&feed.site),
+        updated = updated.to_rfc2822(),
+        links = page.links(feed, "atom:link"),
+    ));
+    for entry in entries {
+        xml.push_str("<item>");
+        if let Some(title) = &entry.title {
+            xml.push_str(&format!("<title>{}</title>", escape_html(title)));
+        }
+        xml.push_str(&format!(
+            r#"<link>{link}</link><guid isPermaLink="true">{id}</guid><pubDate>{published}</pubDate>"#,
+            link = escape_html(&entry.link),
+            id = escape_html(&entry.id),
+            published = entry.published.to_rfc2822(),
+        ));
+        let description = match (&entry.warning, &entry.summary) {
+            // The content is already behind the warning.
+            (Some(warning), summary) if entry.content.is_none() => {
+                let summary = summary.as_deref().map(escape_html).unwrap_or_default();
+                Some(crate::content_warning_html(warning, &summary))
+            }
+            _ => entry.content.clone().or_else(|| entry.summary.clone()),
+        };
+        if let Some(description) = description {
+            xml.push_str(&format!(
+                "<description>{}</description>",
+                escape_html(&description)
+            ));
+        }
+        if let Some(license) = &entry.license {
+            xml.push_str(&format!(
+                "<creativeCommons:license>{}</creativeCommons:license>",
+                escape_html(license)
+            ));
+        }
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// Renders a page of the feed as Atom.
+pub fn atom(feed: &Feed, page: &Page) -> String {
+    let entries = &feed.entries[page.entries.clone()];
+    let updated = entries.iter().map(|x| x.updated).max().unwrap_or_default();
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:fh="http://purl.org/syndication/history/1.0">
+"#,
+    );
+    xml.push_str(&format!(
+        r#"<title>{title}</title><id>{id}</id><updated>{updated}</updated><author><name>{author}</name></author><link rel="alternate" type="text/html" href="{site}"/>{links}"#,
+        title = escape_html(&feed.title),
+        id = escape_html(&feed.url),
+        updated = updated.to_rfc3339(),
+        author = escape_html(&feed.author),
+        site = escape_html(&feed.site),
+        links = page.links(feed, "link"),
+    ));
+    xml.push('\n');
+    for entry in entries {
+        // Atom entries need a title, even an empty one.
+        xml.push_str(&format!(
+            r#"<entry><title>{title}</title><id>{tag}</id><link rel="alternate" href="{link}"/><published>{published}</published><updated>{updated}</updated>"#,
+            title = escape_html(entry.title.as_deref().unwrap_or_default()),
+            tag = escape_html(&entry.tag),
+            link = escape_html(&entry.link),
+            published = entry.published.to_rfc3339(),
+            updated = entry.updated.to_rfc3339(),
+        ));
+        // Fediverse software shows an Atom summary as the content warning.
+        if let Some(summary) = entry.warning.as_ref().or(entry.summary.as_ref()) {
+            xml.push_str(&format!("<summary>{}</summary>", escape_html(summary)));
+        }
+        if let Some(content) = &entry.content {
+            xml.push_str(&format!(
+                r#"<content type="html">{}</content>"#,
+                escape_html(content)
+            ));
+        }
+        if let Some(license) = &entry.license {
+            xml.push_str(&format!(
+                r#"<link rel="license" href="{}"/>"#,
+                escape_html(license)
+            ));
+        }
+        xml.push_str("</entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paging() {
+        let page = |archive| Page::new(7, 3, archive);
+        assert_eq!(
+            page(None),
+            Some(Page {
+                entries: 0..3,
+                archive: None,
+                prev:    Some(2),
+                next:    None,
+            })
+        );
+        // The oldest three, then the three after them.
+        assert_eq!(page(Some(1)).unwrap().entries, 4..7);
+        assert_eq!(
+            page(Some(2)),
+            Some(Page {
+                entries: 1..4,
+                archive: Some(2),
+                prev:    Some(1),
+                next:    None,
+            })
+        );
+        assert_eq!(page(Some(3)), None);
+        assert_eq!(Page::new(3, 3, None).unwrap().prev, None);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::cache;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// External commands that render parts of notes, reading the source on stdin and
+/// writing the result to stdout. Each command is a program and its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Fenced-block languages, such as `plantuml`, and the commands whose output
+    /// replaces such blocks as HTML.
+    pub blocks:  BTreeMap<String, Vec<String>>,
+    /// File extensions, such as `adoc`, and the commands turning such files into
+    /// markdown, which makes them notes.
+    pub files:   BTreeMap<String, Vec<String>>,
+    /// How long a command may run, in milliseconds.
+    pub timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            blocks:  BTreeMap::new(),
+            files:   BTreeMap::new(),
+            timeout: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("filter has no command")]
+    Empty,
+    #[error("failed to run \"{0}\": {1}")]
+    Spawn(String, io::Error),
+    #[error("\"{0}\" took longer than {1:?}")]
+    Timeout(String, Duration),
+    #[error("\"{0}\" failed with {1}: {2}")]
+    Failed(String, ExitStatus, String),
+}
+
+/// Runs `command` with `input` on stdin, killing it after `timeout`.
+fn run(command: &[String], input: &str, timeout: Duration) -> Result<String, Error> {
+    let (program, args) = command.split_first().ok_or(Error::Empty)?;
+    let spawn_error = |e| Error::Spawn(program.clone(), e);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    // Writing and reading on threads of their own keeps a command that fills
+    // one pipe while we wait on another from blocking forever.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let read = |mut pipe: Box<dyn Read + Send>| {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = pipe.read_to_end(&mut output);
+            String::from_utf8_lossy(&output).into_owned()
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read(Box::new(child.stderr.take().expect("stderr is piped")));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(spawn_error)? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Timeout(program.clone(), timeout));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(Error::Failed(
+            program.clone(),
+            status,
+            stderr.trim().to_string(),
+        ));
+    }
+    Ok(stdout)
+}
+
+/// The configured filters, with their recent output kept in memory so unchanged
+/// notes don't run them again.
+#[derive(Debug)]
+pub struct Filters {
+    config: Config,
+    /// Output by a hash of the command and its input.
+    cache:  Mutex<cache::Lru<String, String>>,
+}
+
+impl Filters {
+    pub fn new(config: Config, cache: &cache::Config) -> Self {
+        Self {
+            config,
+            cache: Mutex::new(cache::Lru::new(
+                cache.filter_entries,
+                cache.filter_bytes,
+                String::len,
+            )),
+        }
+    }
+
+    fn run_cached(&self, command: &[String], input: &str) -> Result<String, Error> {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        for arg in command {
+            hasher.update(arg);
+            hasher.update([0]);
+        }
+        hasher.update(input);
+        let key = crate::hex(&hasher.finalize());
+        if let Some(output) = self.cache.lock().unwrap().get(&key) {
+            return Ok(output.clone());
+        }
+        let timeout = Duration::from_millis(self.config.timeout);
+        let output = crate::timing::record("filter", || run(command, input, timeout))?;
+        self.cache.lock().unwrap().insert(key, output.clone());
+        Ok(output)
+    }
+
+    /// The HTML for a fenced block in `lang`, or `None` when no filter handles it.
+    pub fn block(&self, lang: &str, code: &str) -> Option<Result<String, Error>> {
+        let command = self.config.blocks.get(lang)?;
+        Some(self.run_cached(command, code))
+    }
+
+    /// Whether files with `extension` are notes, through a filter.
+    pub fn handles_file(&self, extension: &str) -> bool {
+        self.config.files.contains_key(extension)
+    }
+
+    /// The markdown for a file with `extension`, or `None` when no filter
+    /// handles it.
+    pub fn file(&self, extension: &str, contents: &str) -> Option<Result<String, Error>> {
+        let command = self.config.files.get(extension)?;
+        Some(self.run_cached(command, contents))
+    }
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self::new(Config::default(), &cache::Config::default())
+    }
+}
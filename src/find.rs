@@ -0,0 +1,68 @@
+/// `html` with every occurrence of `term` in its text, ignoring ASCII case, in a
+/// `<mark id="find-<n>">` followed by a link to the next one, and how many there
+/// are. The last one links back to the first.
+pub fn highlight(html: &str, term: &str) -> (String, usize) {
+    let needle = crate::escape_html(term.trim()).to_ascii_lowercase();
+    if needle.is_empty() {
+        return (html.to_string(), 0);
+    }
+    let lower = html.to_ascii_lowercase();
+    let (bytes, needle) = (lower.as_bytes(), needle.as_bytes());
+    let mut found = Vec::new();
+    let mut in_tag = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' => in_tag = true,
+            b'>' => in_tag = false,
+            _ if in_tag => {}
+            _ if bytes[i..].starts_with(needle) => {
+                found.push(i);
+                i += needle.len();
+                continue;
+            }
+            // Character references are matched whole, or not at all.
+            b'&' => {
+                if let Some(end) = bytes[i..].iter().take(12).position(|&b| b == b';') {
+                    i += end;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut out = String::with_capacity(html.len() + found.len() * 100);
+    let mut last = 0;
+    for (n, &at) in found.iter().enumerate() {
+        let next = (n + 1) % found.len();
+        let end = at + needle.len();
+        out.push_str(&html[last..at]);
+        out.push_str(&format!(
+            r##"<mark class="find" id="find-{n}">{}</mark><a class="find-next" href="#find-{next}" title="Next match">↓</a>"##,
+            &html[at..end]
+        ));
+        last = end;
+    }
+    out.push_str(&html[last..]);
+    (out, found.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlighting() {
+        let html = r#"<p title="rust">Rust &amp; more rust</p>"#;
+        let (found, count) = highlight(html, "RUST");
+        assert_eq!(count, 2);
+        assert_eq!(
+            found,
+            r##"<p title="rust"><mark class="find" id="find-0">Rust</mark><a class="find-next" href="#find-1" title="Next match">↓</a> &amp; more <mark class="find" id="find-1">rust</mark><a class="find-next" href="#find-0" title="Next match">↓</a></p>"##
+        );
+        assert_eq!(highlight(html, "amp").1, 0);
+        assert_eq!(highlight(html, "& more").1, 1);
+        assert_eq!(highlight(html, " ").1, 0);
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What every page ends with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// HTML to show.
+    pub html:       Option<String>,
+    /// A markdown file to show after `html`, relative to the config directory.
+    pub file:       Option<PathBuf>,
+    /// Also show the version serving the site, when it last reloaded, and links to
+    /// the feed and the sitemap.
+    pub build_info: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            html:       None,
+            file:       None,
+            build_info: true,
+        }
+    }
+}
+
+/// Renders the footer, as of `reloaded`. A missing or unreadable file is left out.
+pub fn render(config: &Config, config_dir: &Path, reloaded: DateTime<Utc>) -> String {
+    let mut html = config.html.clone().unwrap_or_default();
+    if let Some(file) = &config.file {
+        let path = config_dir.join(file);
+        match fs::read_to_string(&path) {
+            Ok(markdown) => {
+                let parser = pulldown_cmark::Parser::new(&markdown);
+                pulldown_cmark::html::push_html(&mut html, parser);
+            }
+            Err(e) => error!("Failed to read footer \"{path:?}\": {e}"),
+        }
+    }
+    if config.build_info {
+        html.push_str(&format!(
+            "<p class=\"build-info\">Served by notes {}, last reloaded {}. \
+             <a href=\"/feed.xml\">Feed</a> · <a href=\"/sitemap.xml\">Sitemap</a></p>",
+            env!("CARGO_PKG_VERSION"),
+            reloaded.format("%Y-%m-%d %H:%M UTC"),
+        ));
+    }
+    html
+}
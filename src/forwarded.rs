@@ -0,0 +1,147 @@
+use std::net::IpAddr;
+
+/// Who sent a request and how they reached the site, as far as the proxies in
+/// front of the server can be trusted to say.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Client {
+    pub addr:  Option<IpAddr>,
+    /// `http` or `https`.
+    pub proto: String,
+    pub host:  Option<String>,
+    /// Whether it came through the onion service.
+    pub onion: bool,
+}
+
+impl Client {
+    /// The site's origin as the client sees it, such as `https://notes.example.com`.
+    pub fn origin(&self) -> String {
+        let host = self.host.as_deref().unwrap_or("localhost");
+        format!("{}://{host}", self.proto)
+    }
+}
+
+/// Parses a node from a `Forwarded` or `X-Forwarded-For` header, which may be
+/// quoted, bracketed or come with a port. Obfuscated and `unknown` nodes are `None`.
+fn node(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Some(rest) = s.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    s.parse()
+        .ok()
+        .or_else(|| s.rsplit_once(':')?.0.parse().ok())
+}
+
+/// The client's address from the addresses a request passed through, nearest to
+/// the server last: the last one that isn't one of our proxies.
+fn nearest_untrusted(hops: &[Option<IpAddr>], trusted: &[IpAddr]) -> Option<IpAddr> {
+    for hop in hops.iter().rev() {
+        match hop {
+            Some(addr) if trusted.contains(addr) => continue,
+            hop => return *hop,
+        }
+    }
+    hops.first().copied().flatten()
+}
+
+/// Works out the client of a request that came from `peer`, over TLS if `secure`,
+/// believing its `Forwarded` (or `X-Forwarded-*`) headers only when `peer` is a
+/// trusted proxy.
+pub fn client<'a>(
+    peer: Option<IpAddr>,
+    secure: bool,
+    header: impl Fn(&str) -> Option<&'a str>,
+    trusted: &[IpAddr],
+) -> Client {
+    let mut client = Client {
+        addr:  peer,
+        proto: String::from(if secure { "https" } else { "http" }),
+        host:  header("Host").map(str::to_string),
+        onion: false,
+    };
+    if !peer.is_some_and(|x| trusted.contains(&x)) {
+        return client;
+    }
+    let first = |x: &str| x.split(',').next().unwrap_or_default().trim().to_string();
+    if let Some(forwarded) = header("Forwarded") {
+        let mut hops = Vec::new();
+        for element in forwarded.split(',') {
+            let mut hop = None;
+            for pair in element.split(';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop = node(value),
+                    // The first proxy saw the request as the client sent it.
+                    "proto" if hops.is_empty() => client.proto = value.to_string(),
+                    "host" if hops.is_empty() => client.host = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            hops.push(hop);
+        }
+        client.addr = nearest_untrusted(&hops, trusted);
+    } else if let Some(forwarded_for) = header("X-Forwarded-For") {
+        let hops: Vec<_> = forwarded_for.split(',').map(node).collect();
+        client.addr = nearest_untrusted(&hops, trusted);
+        if let Some(proto) = header("X-Forwarded-Proto") {
+            client.proto = first(proto);
+        }
+        if let Some(host) = header("X-Forwarded-Host") {
+            client.host = Some(first(host));
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let headers = |headers: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                headers
+                    .iter()
+                    .find(|(x, _)| x.eq_ignore_ascii_case(name))
+                    .map(|(_, x)| *x)
+            }
+        };
+
+        let client = client(
+            Some(proxy),
+            false,
+            headers(&[
+                ("Host", "127.0.0.1:3000"),
+                (
+                    "Forwarded",
+                    r#"for=203.0.113.7;proto=https;host=notes.example.com, for="[2001:db8::1]:4711", for=10.0.0.1"#,
+                ),
+            ]),
+            &[proxy],
+        );
+        assert_eq!(client.addr, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(client.origin(), "https://notes.example.com");
+
+        let xff = headers(&[
+            ("Host", "notes.example.com"),
+            ("X-Forwarded-For", "198.51.100.2, 10.0.0.1"),
+            ("X-Forwarded-Proto", "https"),
+        ]);
+        let via_proxy = super::client(Some(proxy), false, xff, &[proxy]);
+        assert_eq!(via_proxy.addr, Some("198.51.100.2".parse().unwrap()));
+        assert_eq!(via_proxy.origin(), "https://notes.example.com");
+
+        // Anyone else could say anything.
+        let stranger = "192.0.2.9".parse().unwrap();
+        let direct = super::client(Some(stranger), false, xff, &[proxy]);
+        assert_eq!(direct.addr, Some(stranger));
+        assert_eq!(direct.origin(), "http://notes.example.com");
+        let secure = super::client(Some(stranger), true, xff, &[proxy]);
+        assert_eq!(secure.origin(), "https://notes.example.com");
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{IndexedDocument, SrvState, search};
+use juniper::{
+    EmptyMutation, EmptySubscription, GraphQLObject, RootNode, graphql_object,
+};
+use std::collections::BTreeMap;
+
+impl juniper::Context for SrvState {}
+
+#[derive(GraphQLObject)]
+struct Note {
+    /// Path relative to the content directory, as in `/note/<path>`.
+    path:        String,
+    title:       String,
+    /// `YYYY-MM-DD`.
+    date:        String,
+    /// `note`, `bookmark`, `micro` or `gallery`.
+    kind:        String,
+    tags:        Vec<String>,
+    /// The page a bookmark points to.
+    url:         Option<String>,
+    description: Option<String>,
+    lang:        Option<String>,
+    status:      Option<String>,
+}
+
+impl From<&IndexedDocument> for Note {
+    fn from(doc: &IndexedDocument) -> Self {
+        Self {
+            path:        doc.rel_path.clone(),
+            title:       doc.title.clone(),
+            date:        doc.created.to_string(),
+            kind:        format!("{:?}", doc.kind).to_lowercase(),
+            tags:        doc.tags.clone(),
+            url:         doc.url.clone(),
+            description: doc.desc.clone(),
+            lang:        doc.lang.clone(),
+            status:      doc.status.clone(),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+struct Tag {
+    name:  String,
+    /// How many notes have the tag.
+    count: i32,
+}
+
+pub struct Query;
+
+#[graphql_object(context = SrvState)]
+impl Query {
+    /// Notes, newest first, optionally only those tagged `tag`.
+    fn notes(context: &SrvState, tag: Option<String>, first: Option<i32>) -> Vec<Note> {
+        let first = first
+            .and_then(|x| usize::try_from(x).ok())
+            .unwrap_or(usize::MAX);
+        context
+            .index
+            .iter()
+            .filter(|doc| !doc.unlisted)
+            .filter(|doc| {
+                tag.as_ref().is_none_or(|tag| {
+                    doc.tags.iter().any(|x| x.eq_ignore_ascii_case(tag))
+                })
+            })
+            .take(first)
+            .map(Note::from)
+            .collect()
+    }
+
+    fn note(context: &SrvState, path: String) -> Option<Note> {
+        context
+            .index
+            .iter()
+            .find(|doc| doc.rel_path == path)
+            .map(Note::from)
+    }
+
+    /// Every tag, with how many notes have it.
+    fn tags(context: &SrvState) -> Vec<Tag> {
+        let mut counts = BTreeMap::<_, i32>::new();
+        let listed = context.index.iter().filter(|doc| !doc.unlisted);
+        for tag in listed.flat_map(|doc| &doc.tags) {
+            *counts.entry(tag.to_lowercase()).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(name, count)| Tag { name, count })
+            .collect()
+    }
+
+    /// Notes matching `query`, in the same syntax as `/search`, best first.
+    fn search(context: &SrvState, query: String) -> Vec<Note> {
+        let query = search::Query::parse(&query);
+        context
+            .find(&query)
+            .iter()
+            .filter_map(|hit| {
+                context
+                    .index
+                    .iter()
+                    .find(|doc| doc.rel_path == hit.doc.rel_path)
+            })
+            .map(Note::from)
+            .collect()
+    }
+}
+
+pub type Schema =
+    RootNode<'static, Query, EmptyMutation<SrvState>, EmptySubscription<SrvState>>;
+
+pub fn schema() -> Schema {
+    Schema::new(Query, EmptyMutation::new(), EmptySubscription::new())
+}
@@ -0,0 +1,73 @@
+//! Syntax highlighting of fenced code blocks. The same blocks turn up across
+//! revisions and transclusions, so their HTML is kept in memory by language and
+//! content.
+
+use crate::cache;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    let theme_set = ThemeSet::load_defaults();
+    theme_set.themes["base16-ocean.dark"].clone()
+});
+static CACHE: OnceLock<Mutex<cache::Lru<String, String>>> = OnceLock::new();
+
+fn new_cache(config: &cache::Config) -> Mutex<cache::Lru<String, String>> {
+    Mutex::new(cache::Lru::new(
+        config.highlight_entries,
+        config.highlight_bytes,
+        String::len,
+    ))
+}
+
+/// Sizes the cache. Only the first call counts, so later changes to the limits
+/// need a restart.
+pub fn configure(config: &cache::Config) {
+    CACHE.get_or_init(|| new_cache(config));
+}
+
+/// The highlighted HTML by a hash of the language and code.
+pub fn cache() -> &'static Mutex<cache::Lru<String, String>> {
+    CACHE.get_or_init(|| new_cache(&cache::Config::default()))
+}
+
+fn key(lang: &str, code: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(lang);
+    hasher.update([0]);
+    hasher.update(code);
+    crate::hex(&hasher.finalize())
+}
+
+/// `code` as HTML highlighted for `lang`, or as plain text when `lang` is unknown.
+pub fn html(lang: &str, code: &str) -> String {
+    let key = key(lang, code);
+    if let Some(html) = cache().lock().unwrap().get(&key) {
+        return html.clone();
+    }
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let html = crate::timing::record("highlight", || {
+        syntect::html::highlighted_html_for_string(code, &SYNTAX_SET, syntax, &THEME)
+    })
+    .unwrap_or_else(|_| code.to_string());
+    cache().lock().unwrap().insert(key, html.clone());
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(key("rust", "fn main() {}"), key("rust", "fn main() {}"));
+        assert_ne!(key("rust", "fn main() {}"), key("c", "fn main() {}"));
+        assert_ne!(key("ab", "c"), key("a", "bc"));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::IndexedDocument;
+use crate::http::Response;
+#[cfg(feature = "lua")]
+use log::error;
+use std::io;
+use std::path::Path;
+
+/// The script, next to the config file, defining any of these global functions:
+///
+/// - `on_index_entry(note)` gets each note listed on the index, as a table with
+///   `path`, `title`, `date`, `kind` and `tags`. It returns `false` to leave the note
+///   out, a string to sort by instead of the date (newest, or greatest, first), or
+///   nothing.
+/// - `on_render(note, html)` gets a note as above and its HTML, and may return new
+///   HTML.
+/// - `on_request(request)` gets a table with `method`, `path`, `query` (a table)
+///   and `owner`, before routing. It may return a response, as a table with `body`,
+///   and optionally `status` and `content_type`, or nothing to carry on as usual.
+pub const FILE: &str = "hooks.lua";
+
+/// The loaded script, if any.
+#[derive(Default)]
+pub struct Hooks {
+    #[cfg(feature = "lua")]
+    lua: Option<mlua::Lua>,
+}
+
+#[cfg(feature = "lua")]
+impl Hooks {
+    pub fn load(path: &Path) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                error!("Failed to read hooks \"{path:?}\": {e}");
+                return Self::default();
+            }
+        };
+        let lua = mlua::Lua::new();
+        match lua.load(&source).set_name(FILE).exec() {
+            Ok(()) => {
+                log::info!("Loaded hooks \"{path:?}\"");
+                Self { lua: Some(lua) }
+            }
+            Err(e) => {
+                error!("Failed to load hooks \"{path:?}\": {e}");
+                Self::default()
+            }
+        }
+    }
+
+    /// The global function `name`, if the script defines it.
+    fn hook(&self, name: &str) -> Option<(&mlua::Lua, mlua::Function)> {
+        let lua = self.lua.as_ref()?;
+        let hook = lua.globals().get::<Option<mlua::Function>>(name).ok()??;
+        Some((lua, hook))
+    }
+
+    fn note(lua: &mlua::Lua, doc: &IndexedDocument) -> mlua::Result<mlua::Table> {
+        let note = lua.create_table()?;
+        note.set("path", doc.rel_path.as_str())?;
+        note.set("title", doc.title.as_str())?;
+        note.set("date", doc.created.to_string())?;
+        note.set("kind", format!("{:?}", doc.kind).to_lowercase())?;
+        note.set("tags", doc.tags.clone())?;
+        Ok(note)
+    }
+
+    /// The notes to list on the index, in order. Unlisted notes never are.
+    pub fn index<'a>(&self, index: &'a [IndexedDocument]) -> Vec<&'a IndexedDocument> {
+        let listed = index.iter().filter(|doc| !doc.unlisted);
+        let Some((lua, hook)) = self.hook("on_index_entry") else {
+            return listed.collect();
+        };
+        let mut listed: Vec<_> = listed
+            .filter_map(|doc| {
+                let call =
+                    Self::note(lua, doc).and_then(|note| hook.call::<mlua::Value>(note));
+                match call {
+                    Ok(mlua::Value::Boolean(false)) => None,
+                    Ok(mlua::Value::String(key)) => Some((key.to_string_lossy(), doc)),
+                    Ok(_) => Some((doc.created.to_string(), doc)),
+                    Err(e) => {
+                        error!("on_index_entry failed for \"{}\": {e}", doc.rel_path);
+                        Some((doc.created.to_string(), doc))
+                    }
+                }
+            })
+            .collect();
+        listed.sort_by(|(a, _), (b, _)| b.cmp(a));
+        listed.into_iter().map(|(_, doc)| doc).collect()
+    }
+
+    /// A note's HTML, as the script would have it.
+    pub fn render(&self, doc: &IndexedDocument, html: String) -> String {
+        let Some((lua, hook)) = self.hook("on_render") else {
+            return html;
+        };
+        let call = Self::note(lua, doc)
+            .and_then(|note| hook.call::<Option<String>>((note, html.as_str())));
+        match call {
+            Ok(Some(rendered)) => rendered,
+            Ok(None) => html,
+            Err(e) => {
+                error!("on_render failed for \"{}\": {e}", doc.rel_path);
+                html
+            }
+        }
+    }
+
+    /// The script's response to a request, if it has one.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(String, String)],
+        owner: bool,
+    ) -> Option<Response<io::Cursor<Vec<u8>>>> {
+        let (lua, hook) = self.hook("on_request")?;
+        let call = (|| {
+            let request = lua.create_table()?;
+            request.set("method", method)?;
+            request.set("path", path)?;
+            request.set("query", lua.create_table_from(query.iter().cloned())?)?;
+            request.set("owner", owner)?;
+            let Some(response) = hook.call::<Option<mlua::Table>>(request)? else {
+                return Ok(None);
+            };
+            let body: String = response.get("body")?;
+            let status: Option<u16> = response.get("status")?;
+            let content_type: Option<String> = response.get("content_type")?;
+            Ok::<_, mlua::Error>(Some((body, status, content_type)))
+        })();
+        let (body, status, content_type) = match call {
+            Ok(response) => response?,
+            Err(e) => {
+                error!("on_request failed for \"{path}\": {e}");
+                return None;
+            }
+        };
+        let content_type = content_type.unwrap_or_else(|| String::from("text/html"));
+        Some(
+            Response::from_string(body)
+                .with_status_code(status.unwrap_or(200))
+                .with_header(
+                    crate::http::Header::from_bytes(b"Content-Type", content_type)
+                        .unwrap_or_else(|()| {
+                            crate::http::Header::from_bytes(
+                                b"Content-Type",
+                                b"text/plain",
+                            )
+                            .unwrap()
+                        }),
+                ),
+        )
+    }
+}
+
+#[cfg(not(feature = "lua"))]
+impl Hooks {
+    pub fn load(path: &Path) -> Self {
+        if path.exists() {
+            log::warn!("Ignoring hooks \"{path:?}\", which this build doesn't support");
+        }
+        Self {}
+    }
+
+    pub fn index<'a>(&self, index: &'a [IndexedDocument]) -> Vec<&'a IndexedDocument> {
+        index.iter().filter(|doc| !doc.unlisted).collect()
+    }
+
+    pub fn render(&self, _: &IndexedDocument, html: String) -> String {
+        html
+    }
+
+    pub fn request(
+        &self,
+        _: &str,
+        _: &str,
+        _: &[(String, String)],
+        _: bool,
+    ) -> Option<Response<io::Cursor<Vec<u8>>>> {
+        None
+    }
+}
@@ -0,0 +1,766 @@
+use hyper::body::{Body as _, Bytes, Frame, Incoming, SizeHint};
+use hyper::header::HeaderName;
+use hyper::server::conn::http1;
+use hyper::{StatusCode, Version};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use log::{debug, error};
+use std::convert::Infallible;
+use std::fs::File;
+use std::future::poll_fn;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot};
+use tokio_io_timeout::TimeoutStream;
+
+pub use hyper::Method;
+
+/// How much of a response body is read at once.
+const CHUNK: usize = 64 * 1024;
+
+/// Most requests waiting for a worker. Beyond it, connections wait before handing
+/// over theirs.
+const QUEUE: usize = 64;
+
+/// Where a response body is sent, a chunk at a time.
+type Chunks = mpsc::Sender<io::Result<Bytes>>;
+
+/// A header of a request or response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub field: HeaderName,
+    pub value: String,
+}
+
+impl Header {
+    /// The header `field: value`, or `Err` when either can't be sent in one. Values
+    /// must be printable ASCII.
+    pub fn from_bytes(
+        field: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<Self, ()> {
+        let field = HeaderName::from_bytes(field.as_ref()).map_err(|_| ())?;
+        let value = std::str::from_utf8(value.as_ref()).map_err(|_| ())?;
+        if !value
+            .bytes()
+            .all(|x| x == b'\t' || (b' '..=b'~').contains(&x))
+        {
+            return Err(());
+        }
+        Ok(Self {
+            field,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// A request whose body has been read, waiting to be answered by a worker.
+pub struct Request {
+    method:      Method,
+    url:         String,
+    version:     Version,
+    headers:     Vec<Header>,
+    remote_addr: Option<SocketAddr>,
+    secure:      bool,
+    body:        Cursor<Vec<u8>>,
+    reply:       Option<oneshot::Sender<Reply>>,
+}
+
+impl Request {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path and query asked for.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn http_version(&self) -> Version {
+        self.version
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    pub fn remote_addr(&self) -> Option<&SocketAddr> {
+        self.remote_addr.as_ref()
+    }
+
+    /// Whether the request came over TLS.
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    /// How long the body is, up to one byte longer than the server's limit.
+    pub fn body_length(&self) -> usize {
+        self.body.get_ref().len()
+    }
+
+    /// The body, which is at most one byte longer than the server's limit.
+    pub fn as_reader(&mut self) -> &mut dyn Read {
+        &mut self.body
+    }
+
+    /// Answers with `response`. Up to [`CHUNK`] bytes of it are read at once, and
+    /// anything longer is read as the client takes it, without holding up the
+    /// thread.
+    pub fn respond<R: Read + Send + 'static>(
+        self,
+        response: Response<R>,
+    ) -> io::Result<()> {
+        let Response {
+            status,
+            headers,
+            mut data,
+            length,
+        } = response;
+        let mut first = Vec::new();
+        (&mut data).take(CHUNK as u64).read_to_end(&mut first)?;
+        let full = first.len() == CHUNK;
+        let mut body = Body {
+            first:  Some(Bytes::from(first)),
+            rest:   None,
+            length: length.map(|x| x as u64),
+        };
+        let mut rest = None;
+        if full {
+            let (sender, receiver) = mpsc::channel(1);
+            body.rest = Some(receiver);
+            rest = Some((Box::new(data) as Box<dyn Read + Send>, sender));
+        }
+        self.send(Reply {
+            response: response_of(status, &headers, body)?,
+            rest,
+        })
+    }
+
+    /// Answers with `response`'s status and headers, then streams whatever's written
+    /// to the writer as the body, until the client goes away.
+    pub fn into_writer(self, response: Response<io::Empty>) -> Box<dyn Write + Send> {
+        let (sender, receiver) = mpsc::channel(1);
+        let body = Body {
+            first:  None,
+            rest:   Some(receiver),
+            length: None,
+        };
+        let sent =
+            response_of(response.status, &response.headers, body).and_then(|response| {
+                self.send(Reply {
+                    response,
+                    rest: None,
+                })
+            });
+        if let Err(e) = sent {
+            error!("Failed to respond to request: {e}");
+        }
+        Box::new(Stream { sender })
+    }
+
+    fn send(mut self, reply: Reply) -> io::Result<()> {
+        match self.reply.take() {
+            Some(sender) => sender
+                .send(reply)
+                .map_err(|_| io::Error::from(io::ErrorKind::ConnectionAborted)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A response, whose body is read from `R`.
+pub struct Response<R> {
+    status:  u16,
+    headers: Vec<Header>,
+    data:    R,
+    length:  Option<usize>,
+}
+
+pub type ResponseBox = Response<Box<dyn Read + Send>>;
+
+impl Response<Cursor<Vec<u8>>> {
+    pub fn from_data(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        Self {
+            status:  200,
+            headers: Vec::new(),
+            length:  Some(data.len()),
+            data:    Cursor::new(data),
+        }
+    }
+
+    /// A plain text response, unless given another `Content-Type`.
+    pub fn from_string(data: impl Into<String>) -> Self {
+        let content_type =
+            Header::from_bytes("Content-Type", "text/plain; charset=UTF-8");
+        Self::from_data(data.into()).with_header(content_type.unwrap())
+    }
+}
+
+impl Response<File> {
+    /// The file's contents, whose `Content-Type` isn't guessed.
+    pub fn from_file(file: File) -> Self {
+        Self {
+            status:  200,
+            headers: Vec::new(),
+            length:  file.metadata().ok().map(|x| x.len() as usize),
+            data:    file,
+        }
+    }
+}
+
+impl Response<io::Empty> {
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            data: io::empty(),
+            length: Some(0),
+        }
+    }
+}
+
+impl<R: Read> Response<R> {
+    pub fn with_status_code(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Adds `header`, replacing any `Content-Type` already set. Headers about the
+    /// connection or the body's length are left to the server.
+    pub fn add_header(&mut self, header: Header) {
+        let server_set = [
+            "connection",
+            "content-length",
+            "trailer",
+            "transfer-encoding",
+            "upgrade",
+        ];
+        if server_set.contains(&header.field.as_str()) {
+            return;
+        }
+        if header.field == hyper::header::CONTENT_TYPE {
+            self.headers
+                .retain(|x| x.field != hyper::header::CONTENT_TYPE);
+        }
+        self.headers.push(header);
+    }
+
+    pub fn with_header(mut self, header: Header) -> Self {
+        self.add_header(header);
+        self
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    /// How long the body is, when that's known.
+    pub fn data_length(&self) -> Option<usize> {
+        self.length
+    }
+
+    pub fn boxed(self) -> ResponseBox
+    where
+        R: Send + 'static,
+    {
+        Response {
+            status:  self.status,
+            headers: self.headers,
+            data:    Box::new(self.data),
+            length:  self.length,
+        }
+    }
+}
+
+/// How a worker answers a request: the response, and what's left to read of its
+/// body.
+struct Reply {
+    response: hyper::Response<Body>,
+    rest:     Option<(Box<dyn Read + Send>, Chunks)>,
+}
+
+fn response_of(
+    status: u16,
+    headers: &[Header],
+    body: Body,
+) -> io::Result<hyper::Response<Body>> {
+    let mut response = hyper::Response::builder().status(status);
+    for header in headers {
+        response = response.header(&header.field, &header.value);
+    }
+    response.body(body).map_err(io::Error::other)
+}
+
+/// A response body: its first chunk, then whatever's sent on `rest`.
+struct Body {
+    first:  Option<Bytes>,
+    rest:   Option<mpsc::Receiver<io::Result<Bytes>>>,
+    length: Option<u64>,
+}
+
+impl Body {
+    fn empty() -> Self {
+        Self {
+            first:  None,
+            rest:   None,
+            length: Some(0),
+        }
+    }
+}
+
+impl hyper::body::Body for Body {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(Ok(Frame::data(first))));
+        }
+        match &mut self.rest {
+            Some(rest) => rest.poll_recv(cx).map(|x| x.map(|x| x.map(Frame::data))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.first.is_none() && self.rest.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.length.map(SizeHint::with_exact).unwrap_or_default()
+    }
+}
+
+/// Reads the rest of a response body, a chunk at a time, as the client takes it.
+async fn pump(mut data: Box<dyn Read + Send>, sender: Chunks) {
+    loop {
+        let read = tokio::task::spawn_blocking(move || {
+            let mut chunk = Vec::new();
+            let read = (&mut data).take(CHUNK as u64).read_to_end(&mut chunk);
+            (data, read.map(|_| chunk))
+        });
+        let Ok((rest, read)) = read.await else {
+            return;
+        };
+        data = rest;
+        match read {
+            Ok(chunk) if chunk.is_empty() => return,
+            Ok(chunk) => {
+                if sender.send(Ok(Bytes::from(chunk))).await.is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e)).await;
+                return;
+            }
+        }
+    }
+}
+
+/// What's written to it is sent as a response body.
+struct Stream {
+    sender: Chunks,
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serves HTTP/1.1 on its own runtime, handing requests to the threads that call
+/// [`Self::recv`]. Connections are kept alive between requests, and closed when a
+/// client is idle, or stalls sending headers or a body or reading a response, for
+/// longer than the timeout.
+pub struct Server {
+    queue:    mpsc::Sender<Option<Request>>,
+    requests: Mutex<mpsc::Receiver<Option<Request>>>,
+    /// Kept for as long as connections are served.
+    _runtime: tokio::runtime::Runtime,
+}
+
+impl Server {
+    /// Serves connections to `listener`, over TLS when given a PEM certificate chain
+    /// and private key. Request bodies are read up to `limit` bytes, and one more so
+    /// longer ones can be told apart.
+    pub fn from_listener(
+        listener: std::net::TcpListener,
+        timeout: Duration,
+        limit: u64,
+        tls: Option<(Vec<u8>, Vec<u8>)>,
+    ) -> io::Result<Self> {
+        let tls = tls.map(|(cert, key)| acceptor(&cert, &key)).transpose()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("http")
+            .enable_all()
+            .build()?;
+        listener.set_nonblocking(true)?;
+        let listener = {
+            let _guard = runtime.enter();
+            tokio::net::TcpListener::from_std(listener)?
+        };
+        let (queue, requests) = mpsc::channel(QUEUE);
+        let options = Options {
+            timeout,
+            limit: limit.saturating_add(1),
+            queue: queue.clone(),
+        };
+        runtime.spawn(accept(listener, tls, options));
+        Ok(Self {
+            queue,
+            requests: Mutex::new(requests),
+            _runtime: runtime,
+        })
+    }
+
+    /// Waits for a request, or fails once the server's been unblocked.
+    pub fn recv(&self) -> io::Result<Request> {
+        let mut requests = self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        match requests.blocking_recv() {
+            Some(Some(request)) => Ok(request),
+            Some(None) | None => Err(io::Error::other("the server was unblocked")),
+        }
+    }
+
+    /// Makes one call to [`Self::recv`] fail, after the requests already waiting.
+    pub fn unblock(&self) {
+        let _ = self.queue.blocking_send(None);
+    }
+}
+
+/// How every connection to a server is served.
+#[derive(Clone)]
+struct Options {
+    timeout: Duration,
+    limit:   u64,
+    queue:   mpsc::Sender<Option<Request>>,
+}
+
+#[cfg(feature = "tls")]
+type Acceptor = tokio_rustls::TlsAcceptor;
+
+#[cfg(not(feature = "tls"))]
+type Acceptor = Infallible;
+
+#[cfg(feature = "tls")]
+fn acceptor(certificate: &[u8], private_key: &[u8]) -> io::Result<Acceptor> {
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let chain = CertificateDer::pem_slice_iter(certificate)
+        .collect::<Result<_, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    let key =
+        PrivateKeyDer::from_pem_slice(private_key).map_err(|e| invalid(e.to_string()))?;
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| invalid(e.to_string()))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Acceptor::from(std::sync::Arc::new(config)))
+}
+
+#[cfg(not(feature = "tls"))]
+fn acceptor(_: &[u8], _: &[u8]) -> io::Result<Acceptor> {
+    Err(io::Error::other("this build doesn't support TLS"))
+}
+
+async fn accept(
+    listener: tokio::net::TcpListener,
+    tls: Option<Acceptor>,
+    options: Options,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Likely out of file descriptors, which closing others will free.
+                error!("Failed to accept a connection: {e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let options = options.clone();
+        match &tls {
+            None => tokio::spawn(serve(stream, addr, false, options)),
+            #[cfg(feature = "tls")]
+            Some(tls) => {
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(options.timeout, tls.accept(stream)).await
+                    {
+                        Ok(Ok(stream)) => serve(stream, addr, true, options).await,
+                        Ok(Err(e)) => debug!("TLS handshake with {addr} failed: {e}"),
+                        Err(_) => debug!("TLS handshake with {addr} timed out"),
+                    }
+                })
+            }
+            #[cfg(not(feature = "tls"))]
+            Some(never) => match *never {},
+        };
+    }
+}
+
+/// Serves requests on a connection from `addr` until either side closes it.
+async fn serve<S>(stream: S, addr: SocketAddr, secure: bool, options: Options)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let mut stream = TimeoutStream::new(stream);
+    stream.set_write_timeout(Some(options.timeout));
+    let timeout = options.timeout;
+    let service = hyper::service::service_fn(move |request| {
+        dispatch(request, addr, secure, options.clone())
+    });
+    let served = http1::Builder::new()
+        .timer(TokioTimer::new())
+        .header_read_timeout(timeout)
+        .serve_connection(TokioIo::new(Box::pin(stream)), service)
+        .await;
+    if let Err(e) = served {
+        debug!("Connection from {addr} failed: {e}");
+    }
+}
+
+/// Reads the body of `request`, then waits for a worker to answer it.
+async fn dispatch(
+    request: hyper::Request<Incoming>,
+    addr: SocketAddr,
+    secure: bool,
+    options: Options,
+) -> Result<hyper::Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let body = match read(body, options.limit, options.timeout).await {
+        Ok(body) => body,
+        Err(status) => return Ok(status_only(status)),
+    };
+    let headers = parts.headers.iter().filter_map(|(field, value)| {
+        Some(Header {
+            field: field.clone(),
+            value: value.to_str().ok()?.to_string(),
+        })
+    });
+    let (reply, replied) = oneshot::channel();
+    let request = Request {
+        method: parts.method,
+        url: parts
+            .uri
+            .path_and_query()
+            .map_or("/", |x| x.as_str())
+            .to_string(),
+        version: parts.version,
+        headers: headers.collect(),
+        remote_addr: Some(addr),
+        secure,
+        body: Cursor::new(body),
+        reply: Some(reply),
+    };
+    if options.queue.send(Some(request)).await.is_err() {
+        return Ok(status_only(StatusCode::SERVICE_UNAVAILABLE));
+    }
+    match replied.await {
+        Ok(Reply { response, rest }) => {
+            if let Some((data, sender)) = rest {
+                tokio::spawn(pump(data, sender));
+            }
+            Ok(response)
+        }
+        // Dropped unanswered, as when handling it panicked.
+        Err(_) => Ok(status_only(StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+fn status_only(status: StatusCode) -> hyper::Response<Body> {
+    let mut response = hyper::Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Reads up to about `limit` bytes of `body`, waiting at most `timeout` for each
+/// part of it.
+async fn read(
+    mut body: Incoming,
+    limit: u64,
+    timeout: Duration,
+) -> Result<Vec<u8>, StatusCode> {
+    let mut data = Vec::new();
+    while (data.len() as u64) < limit {
+        let frame = poll_fn(|cx| Pin::new(&mut body).poll_frame(cx));
+        match tokio::time::timeout(timeout, frame).await {
+            Ok(Some(Ok(frame))) => {
+                if let Ok(chunk) = frame.into_data() {
+                    data.extend_from_slice(&chunk);
+                }
+            }
+            Ok(None) => break,
+            Ok(Some(Err(e))) => {
+                debug!("Failed to read request body: {e}");
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            Err(_) => return Err(StatusCode::REQUEST_TIMEOUT),
+        }
+    }
+    data.truncate(limit as usize);
+    Ok(data)
+}
+
+/// A request made up for tests, as though a client had sent it.
+#[cfg(test)]
+pub struct TestRequest {
+    method:  Method,
+    path:    String,
+    headers: Vec<Header>,
+    body:    &'static str,
+}
+
+#[cfg(test)]
+impl TestRequest {
+    pub fn new() -> Self {
+        Self {
+            method:  Method::GET,
+            path:    String::from("/"),
+            headers: Vec::new(),
+            body:    "",
+        }
+    }
+
+    pub fn with_method(self, method: Method) -> Self {
+        Self { method, ..self }
+    }
+
+    pub fn with_path(self, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..self
+        }
+    }
+
+    pub fn with_header(mut self, header: Header) -> Self {
+        self.headers.push(header);
+        self
+    }
+
+    pub fn with_body(self, body: &'static str) -> Self {
+        Self { body, ..self }
+    }
+}
+
+#[cfg(test)]
+impl From<TestRequest> for Request {
+    fn from(request: TestRequest) -> Self {
+        Self {
+            method:      request.method,
+            url:         request.path,
+            version:     Version::HTTP_11,
+            headers:     request.headers,
+            remote_addr: Some(SocketAddr::from(([127, 0, 0, 1], 23456))),
+            secure:      false,
+            body:        Cursor::new(request.body.as_bytes().to_vec()),
+            reply:       None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    #[test]
+    fn headers() {
+        assert!(Header::from_bytes("Location", "/note/a.md").is_ok());
+        assert!(Header::from_bytes("Location", "/a\r\nSet-Cookie: x").is_err());
+        assert!(Header::from_bytes("Bad Name", "x").is_err());
+        let response = Response::from_string("{}")
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+            .with_header(Header::from_bytes("Connection", "close").unwrap());
+        let headers: Vec<_> = response.headers.iter().map(|x| x.value.as_str()).collect();
+        assert_eq!(headers, ["application/json"]);
+    }
+
+    /// Reads a response's status line and body, of `Content-Length`.
+    fn read_response(reader: &mut impl BufRead) -> (String, String) {
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:")
+            {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (
+            status.trim_end().to_string(),
+            String::from_utf8(body).unwrap(),
+        )
+    }
+
+    #[test]
+    fn connections_are_kept_for_more_requests() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::sync::Arc::new(
+            Server::from_listener(listener, Duration::from_secs(5), 4, None).unwrap(),
+        );
+        let worker = std::sync::Arc::clone(&server);
+        let worker = std::thread::spawn(move || {
+            while let Ok(mut request) = worker.recv() {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let text = format!("{} {} {body}", request.method(), request.url());
+                request.respond(Response::from_string(text)).unwrap();
+            }
+        });
+
+        let stream = std::net::TcpStream::connect(addr).unwrap();
+        let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writer
+            .write_all(b"GET /a?b HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        let (status, body) = read_response(&mut reader);
+        assert_eq!(
+            (status.as_str(), body.as_str()),
+            ("HTTP/1.1 200 OK", "GET /a?b ")
+        );
+        // Bodies past the limit are cut short, a byte over it.
+        writer
+            .write_all(
+                b"POST /c HTTP/1.1\r\nHost: x\r\nContent-Length: 9\r\n\r\n123456789",
+            )
+            .unwrap();
+        let (status, body) = read_response(&mut reader);
+        assert_eq!(
+            (status.as_str(), body.as_str()),
+            ("HTTP/1.1 200 OK", "POST /c 12345")
+        );
+
+        server.unblock();
+        worker.join().unwrap();
+    }
+}
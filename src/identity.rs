@@ -0,0 +1,93 @@
+use log::{info, warn};
+use url::Url;
+
+/// Finds the value of the attribute `name` in the HTML tag `tag`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(at) = rest.find(name) {
+        let before = rest[..at].chars().next_back();
+        let after = rest[at + name.len()..].trim_start();
+        rest = &rest[at + name.len()..];
+        let (true, Some(value)) = (
+            before.is_some_and(char::is_whitespace),
+            after.strip_prefix('='),
+        ) else {
+            continue;
+        };
+        let value = value.trim_start();
+        return Some(match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()?,
+        });
+    }
+    None
+}
+
+/// Whether `html` has an `<a>` or `<link>` with `rel="me"` pointing at `site`.
+pub fn links_back(html: &str, site: &Url) -> bool {
+    let site = site.as_str().trim_end_matches('/');
+    // Tag and attribute names are case-insensitive, and so is the comparison.
+    let html = html.to_ascii_lowercase().replace(['\n', '\r', '\t'], " ");
+    html.match_indices('<')
+        .map(|(at, _)| &html[at + 1..])
+        .filter(|tag| tag.starts_with("a ") || tag.starts_with("link "))
+        .filter_map(|tag| tag.split_once('>').map(|(tag, _)| tag))
+        .any(|tag| {
+            let is_me = attribute(tag, "rel")
+                .is_some_and(|rel| rel.split_whitespace().any(|x| x == "me"));
+            is_me
+                && attribute(tag, "href").is_some_and(|href| {
+                    href.trim_end_matches('/').eq_ignore_ascii_case(site)
+                })
+        })
+}
+
+/// Checks that every identity link's page links back to `site`, logging the result.
+pub fn verify(identities: &[String], site: &Url, limit: u64) {
+    for identity in identities {
+        let html = Url::parse(identity)
+            .map_err(crate::archive::Error::from)
+            .and_then(|url| crate::archive::fetch(&url, limit));
+        match html {
+            Ok(html) if links_back(&String::from_utf8_lossy(&html), site) => {
+                info!("Verified identity \"{identity}\"")
+            }
+            Ok(_) => {
+                warn!("\"{identity}\" doesn't link back to \"{site}\" with rel=\"me\"")
+            }
+            Err(e) => warn!("Failed to verify identity \"{identity}\": {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rel_me() {
+        let site = Url::parse("https://notes.example.com").unwrap();
+        assert!(links_back(
+            r#"<A class=x REL="nofollow me" href='https://notes.example.com/'>site</A>"#,
+            &site
+        ));
+        assert!(links_back(
+            "<link\n rel=me href=https://notes.example.com>",
+            &site
+        ));
+        assert!(!links_back(
+            r#"<a rel="nofollow" href="https://notes.example.com">site</a>"#,
+            &site
+        ));
+        assert!(!links_back(
+            r#"<a rel="me" href="https://notes.example.com.evil">site</a>"#,
+            &site
+        ));
+        assert!(!links_back(
+            r#"<a data-rel="me" href="https://notes.example.com">site</a>"#,
+            &site
+        ));
+    }
+}
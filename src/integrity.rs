@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What a file in the content directory was like when it was last modified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    /// Its SHA-256.
+    pub hash:     String,
+    pub len:      u64,
+    pub modified: SystemTime,
+}
+
+impl Checksum {
+    /// Whether `metadata` says the file hasn't been modified since.
+    fn is_current(&self, metadata: &fs::Metadata) -> bool {
+        metadata.len() == self.len && metadata.modified().ok() == Some(self.modified)
+    }
+}
+
+/// Checksums of the files in the content directory, by path.
+pub type Checksums = BTreeMap<String, Checksum>;
+
+/// Files that changed without having been modified, or couldn't be read.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// How many files were checked.
+    pub checked:    usize,
+    /// Paths of files whose contents differ from their checksum, although their
+    /// size and modification time haven't changed.
+    pub changed:    Vec<String>,
+    /// Paths of files that couldn't be read, and why.
+    pub unreadable: Vec<(String, String)>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.unreadable.is_empty()
+    }
+}
+
+/// One line for each file that changed or couldn't be read.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in &self.changed {
+            writeln!(f, "changed: {path}")?;
+        }
+        for (path, e) in &self.unreadable {
+            writeln!(f, "unreadable: {path} ({e})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls `f` with the path of every file in `content`, relative to it, leaving
+/// out hidden ones.
+fn files(
+    content: &Path,
+    f: &mut impl FnMut(String, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    crate::walk(content, &mut |is_dir, path| {
+        let hidden = path
+            .file_name()
+            .is_some_and(|x| x.as_encoded_bytes().starts_with(b"."));
+        if hidden || is_dir {
+            return Ok(!hidden);
+        }
+        let rel_path = path.strip_prefix(content).unwrap_or(path);
+        f(rel_path.to_string_lossy().into_owned(), path)?;
+        Ok(true)
+    })
+}
+
+/// Brings `checksums` up to date with `content`, hashing the files modified since
+/// they were recorded and forgetting those removed. Files that can't be read keep
+/// the checksum they had, for [`verify`] to report. Returns whether any changed.
+pub fn update(checksums: &mut Checksums, content: &Path) -> io::Result<bool> {
+    let mut found = Checksums::new();
+    let mut updated = false;
+    files(content, &mut |rel_path, path| {
+        let recorded = checksums.remove(&rel_path);
+        let current = fs::metadata(path).and_then(|metadata| match &recorded {
+            Some(checksum) if checksum.is_current(&metadata) => Ok(None),
+            _ => Ok(Some(Checksum {
+                hash:     crate::backup::hash(&mut File::open(path)?)?,
+                len:      metadata.len(),
+                modified: metadata.modified()?,
+            })),
+        });
+        let checksum = match (current, recorded) {
+            (Ok(Some(checksum)), _) => {
+                updated = true;
+                checksum
+            }
+            (_, Some(recorded)) => recorded,
+            (Err(_), None) => return Ok(()),
+            (Ok(None), None) => unreachable!("only recorded files are current"),
+        };
+        found.insert(rel_path, checksum);
+        Ok(())
+    })?;
+    updated |= !checksums.is_empty();
+    *checksums = found;
+    Ok(updated)
+}
+
+/// Hashes the files in `content` that haven't been modified since their checksum
+/// was recorded, to find those whose contents changed anyway.
+pub fn verify(checksums: &Checksums, content: &Path) -> io::Result<Report> {
+    let mut report = Report::default();
+    files(content, &mut |rel_path, path| {
+        let Some(checksum) = checksums.get(&rel_path) else {
+            return Ok(());
+        };
+        let hash = fs::metadata(path).and_then(|metadata| {
+            Ok(match checksum.is_current(&metadata) {
+                true => Some(crate::backup::hash(&mut File::open(path)?)?),
+                false => None,
+            })
+        });
+        match hash {
+            Ok(Some(hash)) => {
+                report.checked += 1;
+                if hash != checksum.hash {
+                    report.changed.push(rel_path);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => report.unreadable.push((rel_path, e.to_string())),
+        }
+        Ok(())
+    })?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports() {
+        let report = Report {
+            checked:    3,
+            changed:    vec![String::from("a.md")],
+            unreadable: vec![(String::from("b.png"), String::from("I/O error"))],
+        };
+        assert!(!report.is_empty());
+        assert_eq!(
+            report.to_string(),
+            "changed: a.md\nunreadable: b.png (I/O error)\n"
+        );
+        assert!(Report::default().is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::escape_html;
+use serde_json::json;
+
+/// The URL of a license given by its SPDX identifier, such as `CC-BY-4.0`, or by
+/// its URL.
+pub fn url(license: &str) -> String {
+    if license.starts_with("https://") || license.starts_with("http://") {
+        return license.to_string();
+    }
+    let lower = license.to_ascii_lowercase();
+    if let Some(version) = lower.strip_prefix("cc0-") {
+        return format!("https://creativecommons.org/publicdomain/zero/{version}/");
+    }
+    if let Some((kind, version)) =
+        lower.strip_prefix("cc-").and_then(|x| x.rsplit_once('-'))
+    {
+        return format!("https://creativecommons.org/licenses/{kind}/{version}/");
+    }
+    format!("https://spdx.org/licenses/{license}.html")
+}
+
+/// A line crediting the note `title` to its license, linked with `rel="license"`,
+/// and the same as JSON-LD.
+pub fn html(license: &str, title: &str) -> String {
+    let url = url(license);
+    let json_ld = json!({
+        "@context": "https://schema.org",
+        "@type": "CreativeWork",
+        "name": title,
+        "license": url,
+    });
+    format!(
+        r#"<p class="license">Licensed under <a rel="license" href="{url}">{license}</a>.</p><script type="application/ld+json">{json_ld}</script>"#,
+        url = escape_html(&url),
+        license = escape_html(license),
+        // Keeps a title like `</script>` from ending the script early.
+        json_ld = json_ld.to_string().replace('<', "\\u003c"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            url("CC-BY-SA-4.0"),
+            "https://creativecommons.org/licenses/by-sa/4.0/"
+        );
+        assert_eq!(
+            url("CC0-1.0"),
+            "https://creativecommons.org/publicdomain/zero/1.0/"
+        );
+        assert_eq!(url("MIT"), "https://spdx.org/licenses/MIT.html");
+        assert_eq!(
+            url("https://example.com/license"),
+            "https://example.com/license"
+        );
+    }
+}
@@ -1,28 +1,271 @@
 #![feature(path_file_prefix)]
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime};
-use log::{error, info, warn};
+use http::{Header, Method, Request, Response, ResponseBox, Server};
+use log::{debug, error, info, warn};
 use rinja::Template;
 use serde::{Deserialize, Serialize};
-use signal_hook::consts::SIGHUP;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use tiny_http::{Header, Method, Request, Response, Server};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
 
+mod access;
+mod accesslog;
+mod age;
+mod appearance;
+mod archive;
+mod assets;
+mod backup;
+mod bundle;
+mod cache;
+mod calendar;
+mod cards;
+mod cli;
+mod compress;
+mod context;
+mod cors;
+mod dav;
+mod events;
+mod exif;
+mod feed;
+mod filter;
+mod find;
+mod footer;
+mod forwarded;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod highlight;
+mod hooks;
+mod http;
+mod identity;
+mod integrity;
+mod license;
+mod mcp;
+mod mdns;
+mod members;
+mod multipart;
+mod notify;
+mod onion;
+mod outline;
+mod overrides;
+mod pages;
+mod plugin;
+mod profile;
+mod publish;
+mod ratelimit;
+mod redirects;
+mod replicate;
+mod rewrite;
+mod robots;
+#[cfg(target_os = "linux")]
+mod sandbox;
+mod search;
+mod selfcheck;
+mod semantic;
+mod shards;
+mod store;
+mod styleguide;
+mod suggest;
+mod summary;
+mod tasks;
+mod theme;
+mod timing;
+mod todos;
 #[allow(dead_code)]
 mod uri;
-
-const STYLES: &str = include_str!("styles.css");
+mod usage;
+mod watch;
+mod wikilink;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Config {
     #[serde(default = "Config::default_content_path")]
-    content_path: PathBuf,
+    content_path:      PathBuf,
     #[serde(default = "Config::default_bind")]
-    bind:         std::net::SocketAddr,
+    bind:              std::net::SocketAddr,
+    /// How many requests are handled at once.
+    #[serde(default = "Config::default_workers")]
+    workers:           usize,
+    /// How long, in seconds, a client may stall sending a request or reading a
+    /// response, or keep a connection idle, before it's dropped. Connections are
+    /// probed that often too, to notice clients that have gone.
+    #[serde(default = "Config::default_socket_timeout")]
+    socket_timeout:    u64,
+    /// Remove Exif/XMP metadata from served images. Notes can override this for
+    /// images in their directory with `strip_exif`.
+    #[serde(default = "Config::default_strip_exif")]
+    strip_exif:        bool,
+    /// Token required by the write API. The write API is disabled when unset.
+    api_token:         Option<String>,
+    /// Tokens that show readers the members-only part of notes, given as `?share=`
+    /// in a note's URL.
+    #[serde(default)]
+    share_tokens:      Vec<String>,
+    /// Where uploads are stored, relative to `content_path`.
+    #[serde(default = "Config::default_assets_dir")]
+    assets_dir:        PathBuf,
+    /// Maximum size of a request body for uploads, in bytes.
+    #[serde(default = "Config::default_max_upload_size")]
+    max_upload_size:   u64,
+    /// Most bytes the content and data directories may take together. Uploads and
+    /// edits that would go past it are refused.
+    quota:             Option<u64>,
+    /// Where captured notes are written, relative to `content_path`.
+    #[serde(default = "Config::default_inbox_dir")]
+    inbox_dir:         PathBuf,
+    /// Where archived articles are written, relative to `content_path`.
+    #[serde(default = "Config::default_archive_dir")]
+    archive_dir:       PathBuf,
+    /// Where state that isn't part of the notes, such as the search index, is kept.
+    #[serde(default = "Config::default_data_path")]
+    data_path:         PathBuf,
+    /// The site's public URL, such as `https://notes.example.com`.
+    base_url:          Option<url::Url>,
+    /// Also serve the site as a Tor onion service.
+    onion:             Option<onion::Config>,
+    /// Advertise the server to the local network.
+    mdns:              Option<mdns::Config>,
+    /// Keep a copy of another server's notes, as the `replicate` task pulls them.
+    replica:           Option<replicate::Config>,
+    /// The license notes are published under unless they say otherwise, as an SPDX
+    /// identifier such as `CC-BY-4.0` or a URL.
+    license:           Option<String>,
+    /// Profiles elsewhere (Mastodon, GitHub) linked with `rel="me"` from every
+    /// page.
+    #[serde(default)]
+    identities:        Vec<String>,
+    /// Where to post about newly published notes, such as an ntfy topic or a Matrix
+    /// room for each tag.
+    #[serde(default)]
+    notify:            Vec<notify::Rule>,
+    /// How long clients may cache feeds before checking for changes, in seconds.
+    #[serde(default = "Config::default_feed_max_age")]
+    feed_max_age:      u64,
+    /// How many of the newest notes feeds list. Older ones are paged into archives.
+    #[serde(default = "Config::default_feed_size")]
+    feed_size:         usize,
+    /// How long rendering a note may take before giving up on it, in milliseconds.
+    #[serde(default = "Config::default_render_timeout")]
+    render_timeout:    u64,
+    /// How many links away from a note `/export/<note>.zip` follows to other notes,
+    /// unless the request gives a `depth`.
+    #[serde(default = "Config::default_export_depth")]
+    export_depth:      usize,
+    /// How many notes each page of the index lists, so large collections are
+    /// paged into `/?page=2` and on.
+    #[serde(default = "Config::default_index_page_size")]
+    index_page_size:   usize,
+    /// Notes older than this, such as `2y`, get a banner saying so, and are marked
+    /// on the index.
+    stale_after:       Option<age::Age>,
+    /// Check on startup that every identity links back to `base_url`.
+    #[serde(default)]
+    verify_identities: bool,
+    /// An emoji, such as `🦀`, used as the favicon of pages whose note has no `icon`.
+    icon:              Option<String>,
+    /// The file in `content_path` served as `/favicon.ico`, for browsers that ask
+    /// for it whatever pages say. A built-in one is served without it.
+    favicon:           Option<PathBuf>,
+    /// Only list notes in the visitor's languages (from `Accept-Language`) on the
+    /// index, with a link to show all of them. Notes without a `lang` are always
+    /// listed.
+    #[serde(default)]
+    filter_languages:  bool,
+    /// After starting up, only allow access to the content and data directories
+    /// and to reading the config, using Landlock. Changes to those paths take a
+    /// restart.
+    #[serde(default)]
+    sandbox:           bool,
+    /// How pages look: a built-in theme, `default` or `light`, or the path of a theme
+    /// directory or zip archive, relative to the config directory.
+    #[serde(default = "Config::default_theme")]
+    theme:             String,
+    /// Colors, fonts and width overriding the theme's.
+    #[serde(default)]
+    appearance:        appearance::Config,
+    /// Reload open pages when their note changes, or the index when any does.
+    #[serde(default)]
+    live_reload:       bool,
+    /// Serve `/styleguide`, a note using everything the renderer can emit, for
+    /// working on themes. Debug builds always serve it.
+    #[serde(default)]
+    styleguide:        bool,
+    /// Reload when notes in the content directory change, besides on SIGHUP.
+    #[serde(default = "Config::default_watch")]
+    watch:             bool,
+    /// A PEM certificate chain to serve HTTPS with, along with `tls_key`.
+    tls_cert:          Option<PathBuf>,
+    /// The certificate's PEM private key.
+    tls_key:           Option<PathBuf>,
+    /// Proxies whose `Forwarded` and `X-Forwarded-*` headers are believed, to learn
+    /// the client's address and how it reached the site.
+    #[serde(default)]
+    trusted_proxies:   Vec<std::net::IpAddr>,
+    /// Addresses allowed to reach the server, or the owner's routes.
+    #[serde(default)]
+    access:            access::Config,
+    /// What crawlers are asked to do, and who made the site.
+    #[serde(default)]
+    robots:            robots::Config,
+    /// How many requests each client may make, without limit when unset.
+    rate_limit:        Option<ratelimit::Config>,
+    /// Where to log requests in the Combined Log Format, apart from the server's own
+    /// log.
+    access_log:        Option<PathBuf>,
+    #[serde(default)]
+    search:            search::Config,
+    #[serde(default)]
+    semantic:          semantic::Config,
+    #[serde(default)]
+    summaries:         summary::Config,
+    /// External commands rendering fenced blocks, or whole files, of notes.
+    #[serde(default)]
+    filters:           filter::Config,
+    #[serde(default)]
+    plugins:           plugin::Config,
+    /// Maintenance tasks and how often to run them, in seconds, such as
+    /// `git_pull = 600`.
+    #[serde(default)]
+    tasks:             tasks::Config,
+    /// Saved searches, each listed as a page at `/view/<name>`.
+    #[serde(default)]
+    views:             BTreeMap<String, String>,
+    #[serde(default)]
+    cache:             cache::Config,
+    /// Regexes and their replacements, rewriting request paths before routing, so
+    /// old URLs (say, from a previous blog engine) keep working. Write the
+    /// patterns as literal strings (`'^/(\d{4})/(.+)$'`), since TOML rejects
+    /// escapes like `\d` in basic strings.
+    #[serde(default)]
+    rewrites:          rewrite::Rules,
+    #[serde(default)]
+    cors:              cors::Config,
+    /// Compressing HTML responses.
+    #[serde(default)]
+    compression:       compress::Config,
+    #[serde(default)]
+    footer:            footer::Config,
+    /// Links shown in every page's header.
+    #[serde(default)]
+    menu:              Vec<MenuItem>,
+}
+
+/// A link in the site's header, to a note, a feed or anywhere else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MenuItem {
+    label:  String,
+    url:    String,
+    /// Items are shown lightest first, and in the order they're listed when they
+    /// weigh the same.
+    #[serde(default)]
+    weight: i32,
 }
 
 impl Config {
@@ -32,79 +275,603 @@ impl Config {
     fn default_bind() -> std::net::SocketAddr {
         "127.0.0.1:3000".parse().unwrap()
     }
+    fn default_workers() -> usize {
+        8
+    }
+    fn default_socket_timeout() -> u64 {
+        30
+    }
+    fn default_strip_exif() -> bool {
+        true
+    }
+    fn default_assets_dir() -> PathBuf {
+        PathBuf::from("assets")
+    }
+    fn default_max_upload_size() -> u64 {
+        16 * 1024 * 1024
+    }
+    fn default_inbox_dir() -> PathBuf {
+        PathBuf::from("inbox")
+    }
+    fn default_archive_dir() -> PathBuf {
+        PathBuf::from("archive")
+    }
+    fn default_feed_max_age() -> u64 {
+        5 * 60
+    }
+    fn default_feed_size() -> usize {
+        20
+    }
+    fn default_theme() -> String {
+        String::from("default")
+    }
+    fn default_watch() -> bool {
+        true
+    }
+    fn default_export_depth() -> usize {
+        1
+    }
+    fn default_index_page_size() -> usize {
+        200
+    }
+    fn default_render_timeout() -> u64 {
+        10 * 1000
+    }
+    fn default_data_path() -> PathBuf {
+        dirs::data_dir().expect("data directory").join("notes")
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            content_path: Self::default_content_path(),
-            bind:         Self::default_bind(),
+            content_path:      Self::default_content_path(),
+            bind:              Self::default_bind(),
+            workers:           Self::default_workers(),
+            socket_timeout:    Self::default_socket_timeout(),
+            strip_exif:        Self::default_strip_exif(),
+            api_token:         None,
+            share_tokens:      Vec::new(),
+            assets_dir:        Self::default_assets_dir(),
+            max_upload_size:   Self::default_max_upload_size(),
+            quota:             None,
+            inbox_dir:         Self::default_inbox_dir(),
+            archive_dir:       Self::default_archive_dir(),
+            data_path:         Self::default_data_path(),
+            search:            search::Config::default(),
+            semantic:          semantic::Config::default(),
+            summaries:         summary::Config::default(),
+            filters:           filter::Config::default(),
+            plugins:           plugin::Config::default(),
+            tasks:             tasks::Config::new(),
+            views:             BTreeMap::new(),
+            cache:             cache::Config::default(),
+            rewrites:          rewrite::Rules::default(),
+            cors:              cors::Config::default(),
+            compression:       compress::Config::default(),
+            footer:            footer::Config::default(),
+            appearance:        appearance::Config::default(),
+            menu:              Vec::new(),
+            base_url:          None,
+            onion:             None,
+            mdns:              None,
+            replica:           None,
+            license:           None,
+            identities:        Vec::new(),
+            notify:            Vec::new(),
+            feed_max_age:      Self::default_feed_max_age(),
+            feed_size:         Self::default_feed_size(),
+            render_timeout:    Self::default_render_timeout(),
+            export_depth:      Self::default_export_depth(),
+            index_page_size:   Self::default_index_page_size(),
+            stale_after:       None,
+            icon:              None,
+            favicon:           None,
+            verify_identities: false,
+            filter_languages:  false,
+            sandbox:           false,
+            tls_cert:          None,
+            tls_key:           None,
+            trusted_proxies:   Vec::new(),
+            access:            access::Config::default(),
+            robots:            robots::Config::default(),
+            rate_limit:        None,
+            access_log:        None,
+            theme:             Self::default_theme(),
+            live_reload:       false,
+            styleguide:        false,
+            watch:             Self::default_watch(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 struct IndexedDocument {
-    title:    String,
-    created:  NaiveDate,
-    rel_path: String,
+    title:      String,
+    created:    NaiveDate,
+    rel_path:   String,
+    kind:       NoteKind,
+    url:        Option<String>,
+    desc:       Option<String>,
+    /// Rendered body of micro-posts, which are shown inline on the index.
+    content:    Option<String>,
+    strip_exif: Option<bool>,
+    todos:      Vec<todos::Todo>,
+    tags:       Vec<String>,
+    status:     Option<String>,
+    events:     Vec<calendar::Event>,
+    lang:       Option<String>,
+    license:    Option<String>,
+    warning:    Option<String>,
+    /// When the note was last checked to still be right.
+    reviewed:   Option<NaiveDate>,
+    icon:       Option<String>,
+    /// Left off listings, but served to whoever has the URL.
+    unlisted:   bool,
+    cards:      Vec<cards::Card>,
+    /// Paths of the files and notes the note links to or embeds.
+    references: Vec<String>,
+    /// Modification time of the note's file.
+    modified:   SystemTime,
 }
 type Index = Vec<IndexedDocument>;
 
 fn main() {
-    use log::LevelFilter;
     env_logger::Builder::new()
-        .filter(None, LevelFilter::Debug)
+        .filter(None, ARGS.log_level)
         .init();
     let reload_state = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(SIGHUP, reload_state.clone()).unwrap();
 
-    let config_path = dirs::config_dir()
-        .expect("config directory")
-        .join("notes/notes.toml");
+    let config_path = config_path();
     let mut config = load_config(&config_path);
+    ARGS.apply(&mut config);
+    highlight::configure(&config.cache);
+
+    // Checking or publishing another server doesn't need this one's notes.
+    let served = match config.tls_cert {
+        Some(_) => format!("https://{}", config.bind),
+        None => format!("http://{}", config.bind),
+    };
+    let served = url::Url::parse(&served).expect("a socket address makes a URL");
+    if let Some(cli::Command::Selfcheck { url }) = &ARGS.command {
+        let failures = selfcheck::run(url.as_ref().unwrap_or(&served));
+        std::process::exit(i32::from(!failures.is_empty()));
+    }
+    if let Some(cli::Command::Publish {
+        target,
+        url,
+        endpoint,
+        region,
+    }) = &ARGS.command
+    {
+        if config.base_url.is_none() {
+            warn!("Without a base_url, feeds will link to the server being published");
+        }
+        let published = target.parse::<publish::Target>().and_then(|target| {
+            let bucket =
+                publish::Bucket::from_env(target, endpoint.clone(), region.clone())?;
+            let url = url.as_ref().unwrap_or(&served);
+            publish::publish(url, &bucket, &config.data_path.join("published.json"))
+        });
+        match published {
+            Ok((pages, sent)) => {
+                println!("Published {pages} pages, sending {sent} of them.")
+            }
+            Err(e) => {
+                error!("Failed to publish: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(cli::Command::Torrc) = ARGS.command {
+        match &config.onion {
+            Some(onion) => print!("{}", onion.torrc()),
+            None => {
+                error!("No onion service is configured");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Neither do backups.
+    let config_dir = config_path.parent().expect("config file has a parent dir");
+    let roots = [
+        ("content", config.content_path.as_path()),
+        ("data", config.data_path.as_path()),
+        ("config", config_dir),
+    ];
+    match &ARGS.command {
+        Some(cli::Command::Backup { dest, since }) => {
+            let backed_up = since
+                .as_deref()
+                .map(backup::manifest)
+                .transpose()
+                .and_then(|since| backup::backup(dest, &roots, since.as_ref()));
+            match backed_up {
+                Ok((files, stored)) => {
+                    println!("Backed up {files} files, storing {stored} of them.")
+                }
+                Err(e) => {
+                    error!("Failed to back up: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        // Before loading, which records the checksums of modified notes.
+        Some(cli::Command::Verify) => {
+            let verified = store::Store::open(config.data_path.join("store.json"))
+                .and_then(|store| {
+                    integrity::verify(&store.checksums, &config.content_path)
+                });
+            match verified {
+                Ok(report) => {
+                    print!("{report}");
+                    println!(
+                        "Checked {} files: {} changed, {} unreadable.",
+                        report.checked,
+                        report.changed.len(),
+                        report.unreadable.len()
+                    );
+                    std::process::exit(i32::from(!report.is_empty()));
+                }
+                Err(e) => {
+                    error!("Failed to verify: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(cli::Command::Restore { backups }) => {
+            match backup::restore(backups, &roots) {
+                Ok(written) => println!("Restored {written} files."),
+                Err(e) => {
+                    error!("Failed to restore: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
 
-    let content_path = fs::canonicalize(config.content_path).unwrap();
-    let state = match SrvState::load(content_path) {
-        Ok(s) => Arc::new(Mutex::new(s)),
+    let state = match SrvState::load(config.clone()) {
+        Ok(s) => Arc::new(RwLock::new(s)),
         Err(e) => {
             error!("Failed to load state: {e}");
             std::process::exit(1);
         }
     };
 
-    std::thread::spawn({
-        let state = Arc::clone(&state);
-        move || match Server::http(config.bind) {
-            Ok(server) => SrvState::serve(state, server),
+    if let Some(cli::Command::Check) = ARGS.command {
+        let state = state.read().unwrap();
+        match state.assets.is_empty() {
+            true => println!("Every file is used, and none are missing."),
+            false => print!("{}", state.assets),
+        }
+        std::process::exit(i32::from(!state.assets.missing.is_empty()));
+    }
+
+    // Read before sandboxing, since the certificate may live anywhere.
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            match fs::read(cert).and_then(|x| Ok((x, fs::read(key)?))) {
+                Ok(tls) => Some(tls),
+                Err(e) => {
+                    error!("Failed to read TLS certificate and key: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        (None, None) => None,
+        _ => {
+            error!("tls_cert and tls_key must be set together");
+            std::process::exit(1);
+        }
+    };
+
+    // Opened before sandboxing, since it may live anywhere.
+    if let Some(Err(e)) = config.access_log.as_deref().map(accesslog::open) {
+        error!("Failed to open the access log: {e}");
+        std::process::exit(1);
+    }
+
+    if config.sandbox {
+        sandbox(&config, &config_path);
+    }
+
+    if ARGS.mcp {
+        let state = state.read().unwrap();
+        if let Err(e) = mcp::serve(&state, io::stdin().lock(), io::stdout().lock()) {
+            error!("Failed to serve MCP: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let (true, Some(base_url)) = (config.verify_identities, &config.base_url) {
+        let (identities, base_url) = (config.identities.clone(), base_url.clone());
+        let limit = config.max_upload_size;
+        std::thread::spawn(move || identity::verify(&identities, &base_url, limit));
+    } else if config.verify_identities {
+        warn!("Can't verify identities without a base_url");
+    }
+
+    // When notes last changed, cleared once they've been reloaded.
+    let changed = Arc::new(Mutex::new(None));
+    let _watcher = match config.watch {
+        true => watch::watch(&config.content_path, Arc::clone(&changed))
+            .inspect_err(|e| {
+                error!(
+                    "Failed to watch \"{:?}\" for changes: {e}",
+                    config.content_path
+                )
+            })
+            .ok(),
+        false => None,
+    };
+
+    // Stop serving on SIGTERM or SIGINT. A second one exits at once, in case
+    // shutting down gets stuck.
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, shutdown.clone())
+            .unwrap();
+        signal_hook::flag::register(signal, shutdown.clone()).unwrap();
+    }
+    let socket_timeout = Duration::from_secs(config.socket_timeout);
+    let limit = config.max_upload_size;
+    let server = match bind(config.bind, socket_timeout, limit, tls) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            error!("Failed to bind server to {}: {}", config.bind, e);
+            std::process::exit(1);
+        }
+    };
+    let mut workers = SrvState::serve(
+        Arc::clone(&state),
+        Arc::clone(&server),
+        config.workers,
+        Arc::clone(&shutdown),
+        false,
+    );
+    let mut servers = vec![(server, workers.len())];
+    if let Some(onion) = &config.onion {
+        let server = match bind(onion.bind, socket_timeout, limit, None) {
+            Ok(server) => Arc::new(server),
             Err(e) => {
-                error!("Failed to bind server to {}: {}", config.bind, e);
+                error!("Failed to bind onion service to {}: {e}", onion.bind);
                 std::process::exit(1);
             }
+        };
+        let onion_workers = SrvState::serve(
+            Arc::clone(&state),
+            Arc::clone(&server),
+            config.workers,
+            Arc::clone(&shutdown),
+            true,
+        );
+        servers.push((server, onion_workers.len()));
+        workers.extend(onion_workers);
+    }
+    let mdns = config.mdns.as_ref().and_then(|mdns| {
+        if config.bind.ip().is_loopback() {
+            warn!("Advertising a server only this machine can reach");
         }
+        let tls = config.tls_cert.is_some();
+        mdns::advertise(mdns, config.bind.port(), tls)
+            .inspect_err(|e| error!("Failed to advertise the server: {e}"))
+            .ok()
     });
 
-    loop {
+    let mut schedule = tasks::Schedule::default();
+    let mut enriching: Option<std::thread::JoinHandle<()>> = None;
+    while !shutdown.load(Ordering::Relaxed) {
         config = load_config(&config_path);
-        if reload_state.swap(false, Ordering::Relaxed) {
+        ARGS.apply(&mut config);
+        for task in schedule.due(&config.tasks, std::time::Instant::now()) {
+            run_task(task, &state, &config, &reload_state);
+        }
+        let settled = changed
+            .lock()
+            .ok()
+            .and_then(|mut x| x.take_if(|x| x.elapsed() >= watch::DEBOUNCE))
+            .is_some();
+        // Reloading publishes the notes whose time has come.
+        let publishing = state.read().is_ok_and(|state| state.is_publishing_due());
+        if reload_state.swap(false, Ordering::Relaxed) || settled || publishing {
             info!("Reloading state...");
-            let Ok(mut state) = state.lock() else { break };
-            match SrvState::load(config.content_path) {
-                Ok(s) => {
-                    info!("State reloaded sucessfully!");
-                    *state = s;
-                }
+            let Ok(mut state) = state.write() else { break };
+            match state.reload(config.clone()) {
+                Ok(()) => info!("State reloaded sucessfully!"),
                 Err(e) => {
                     error!("Failed to reload state (retaining previous state): {e}")
                 }
             }
         }
+        // Summaries and embeddings are fetched after the reload that found them
+        // missing, one batch at a time.
+        let idle = enriching.as_ref().is_none_or(|x| x.is_finished());
+        if idle && state.read().is_ok_and(|state| state.needs_enriching()) {
+            let state = Arc::clone(&state);
+            enriching = Some(std::thread::spawn(move || SrvState::enrich(&state)));
+        }
 
         std::thread::sleep(std::time::Duration::from_millis(256));
     }
+
+    // Workers finish the requests they're handling, then stop when unblocked.
+    info!("Shutting down...");
+    if let Some(mdns) = mdns {
+        let _ = mdns.shutdown();
+    }
+    for (server, workers) in &servers {
+        for _ in 0..*workers {
+            server.unblock();
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+/// Runs `task` on its own thread if it may take a while, asking for a reload with
+/// `reload_state` when it needs one.
+fn run_task(
+    task: tasks::Task,
+    state: &RwLock<SrvState>,
+    config: &Config,
+    reload_state: &Arc<AtomicBool>,
+) {
+    debug!("Running {task:?}");
+    match task {
+        tasks::Task::Reload => reload_state.store(true, Ordering::Relaxed),
+        tasks::Task::ClearCache => {
+            if let Ok(state) = state.read() {
+                state.pages.lock().unwrap().clear();
+            }
+        }
+        tasks::Task::GitPull => {
+            let content_path = config.content_path.clone();
+            let reload_state = Arc::clone(reload_state);
+            std::thread::spawn(move || match tasks::git_pull(&content_path) {
+                Ok(()) => reload_state.store(true, Ordering::Relaxed),
+                Err(e) => error!("Failed to pull \"{content_path:?}\": {e}"),
+            });
+        }
+        tasks::Task::CheckLinks => {
+            let Ok(state) = state.read() else { return };
+            match asset_report(&state.content_path, &state.index) {
+                Ok(report) => {
+                    for (note, file) in &report.missing {
+                        warn!("\"{note}\" links to missing \"{file}\"");
+                    }
+                }
+                Err(e) => error!("Failed to look for missing files: {e}"),
+            }
+        }
+        tasks::Task::VerifyIdentities => match &config.base_url {
+            Some(base_url) => {
+                let (identities, base_url) =
+                    (config.identities.clone(), base_url.clone());
+                let limit = config.max_upload_size;
+                std::thread::spawn(move || {
+                    identity::verify(&identities, &base_url, limit)
+                });
+            }
+            None => warn!("Can't verify identities without a base_url"),
+        },
+        tasks::Task::Replicate => match &config.replica {
+            Some(replica) => {
+                let replica = replica.clone();
+                let content_path = config.content_path.clone();
+                let store = config.data_path.join(replicate::STORE);
+                let last = config.data_path.join("replicated");
+                let reload_state = Arc::clone(reload_state);
+                std::thread::spawn(move || {
+                    match replicate::pull(&replica, &content_path, &store, &last) {
+                        Ok(0) => {}
+                        Ok(count) => {
+                            info!("Replicated {count} files from {}", replica.primary);
+                            reload_state.store(true, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            error!("Failed to replicate from {}: {e}", replica.primary)
+                        }
+                    }
+                });
+            }
+            None => warn!("Can't replicate without a replica"),
+        },
+    }
+}
+
+/// Binds a server to `addr`, with TLS when given a certificate and key. Request
+/// bodies past `limit` bytes aren't read.
+fn bind(
+    addr: std::net::SocketAddr,
+    timeout: Duration,
+    limit: u64,
+    tls: Option<(Vec<u8>, Vec<u8>)>,
+) -> Result<Server, Box<dyn std::error::Error + Send + Sync>> {
+    use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+
+    let socket =
+        Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Like std's TcpListener, so a restarted server can bind again at once.
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    // Accepted connections inherit it, so clients that vanish are noticed.
+    let keepalive = TcpKeepalive::new()
+        .with_time(timeout)
+        .with_interval(timeout);
+    socket.set_tcp_keepalive(&keepalive)?;
+    Ok(Server::from_listener(socket.into(), timeout, limit, tls)?)
+}
+
+/// Confines the process to the files it needs, before any other threads start.
+#[cfg(target_os = "linux")]
+fn sandbox(config: &Config, config_path: &Path) {
+    if let Err(e) = fs::create_dir_all(&config.data_path) {
+        error!(
+            "Failed to create data directory \"{:?}\": {e}",
+            config.data_path
+        );
+    }
+    let writable = [config.content_path.as_path(), config.data_path.as_path()];
+    let config_dir = config_path.parent().unwrap_or(config_path);
+    // Themes may live outside the config directory, and are read again on reload.
+    let theme = theme::Theme::path(&config.theme, config_dir);
+    let mut readable = vec![config_dir];
+    readable.extend(theme.as_deref());
+    match sandbox::restrict(&writable, &readable) {
+        Ok(landlock::RulesetStatus::FullyEnforced) => info!("Sandboxed file access"),
+        Ok(landlock::RulesetStatus::PartiallyEnforced) => {
+            warn!("File access is only partly sandboxed by this kernel")
+        }
+        Ok(landlock::RulesetStatus::NotEnforced) => {
+            warn!("This kernel doesn't support sandboxing file access")
+        }
+        Err(e) => {
+            error!("Failed to sandbox file access: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sandbox(_: &Config, _: &Path) {
+    warn!("Sandboxing file access is only supported on Linux");
+}
+
+/// The command line, parsed on first use.
+#[cfg(not(test))]
+static ARGS: LazyLock<cli::Args> = LazyLock::new(<cli::Args as clap::Parser>::parse);
+/// Tests are run with the test harness's arguments, and have no config file.
+#[cfg(test)]
+static ARGS: LazyLock<cli::Args> = LazyLock::new(|| {
+    clap::Parser::parse_from(["notes", "--config", "/nonexistent/notes.toml"])
+});
+
+fn config_path() -> PathBuf {
+    ARGS.config.clone().unwrap_or_else(|| {
+        dirs::config_dir()
+            .expect("config directory")
+            .join("notes/notes.toml")
+    })
 }
 
+/// Reads the config file, with what [`overrides::PREFIX`] environment variables
+/// say layered over it.
 fn load_config(config_path: impl AsRef<Path>) -> Config {
+    overrides::apply(read_config(config_path), std::env::vars())
+}
+
+fn read_config(config_path: impl AsRef<Path>) -> Config {
     let config_path = config_path.as_ref();
     let config_dir = config_path
         .parent()
@@ -149,98 +916,3502 @@ fn load_config(config_path: impl AsRef<Path>) -> Config {
     config
 }
 
+/// A rendered note page, with the modification time of the note it was rendered
+/// from and the headers the note asks for.
+type CachedPage = (SystemTime, String, Vec<Header>);
+
 #[derive(Default)]
 struct SrvState {
+    config:       Config,
     content_path: PathBuf,
     index:        Index,
+    /// The first page of the index as most visitors see it.
     index_html:   String,
+    search:       search::SearchIndex,
+    semantic:     semantic::Index,
+    /// What `[[wikilinks]]` can point at.
+    links:        Arc<wikilink::Links>,
+    filters:      Arc<filter::Filters>,
+    plugins:      Arc<plugin::Plugins>,
+    /// The Lua script next to the config file, [`hooks::FILE`].
+    hooks:        Arc<hooks::Hooks>,
+    theme:        theme::Theme,
+    /// The HTML every page ends with.
+    footer:       String,
+    store:        Mutex<store::Store>,
+    /// Rendered note pages as visitors see them, by path.
+    pages:        Mutex<cache::Lru<String, CachedPage>>,
+    /// Rules from the content directory's [`redirects::FILE`].
+    redirects:    Vec<redirects::Redirect>,
+    /// Clients listening on `/events` for changes.
+    events:       Arc<Mutex<events::Broadcast>>,
+    /// Files in the content directory that no note uses, and missing ones notes do.
+    assets:       assets::Report,
+    /// Notes dated in the future, by path, with when they're published.
+    scheduled:    std::collections::HashMap<String, NaiveDateTime>,
+    /// Notes still to be summarized, and to be embedded with when they were
+    /// modified, which [`SrvState::enrich`] asks the models about.
+    unsummarized: Vec<summary::Missing>,
+    unembedded:   Vec<(String, SystemTime)>,
+    /// How many requests clients have made lately, for `rate_limit`.
+    limiter:      Mutex<ratelimit::Limiter>,
+    /// What the content and data directories took when loaded, and what's been
+    /// written since.
+    usage:        Mutex<usage::Usage>,
 }
 
 impl SrvState {
-    fn load(content_path: PathBuf) -> io::Result<Self> {
-        let index = generate_index(&content_path)?;
+    fn load(config: Config) -> io::Result<Self> {
+        let mut search = search::SearchIndex::open(config.data_path.join("search.json"));
+        let mut semantic = semantic::Index::open(
+            config.data_path.join("embeddings.json"),
+            &config.semantic.model,
+        );
+        let mut state = Self::load_with(config, &Vec::new(), &mut search, &mut semantic)?;
+        state.plugins = Arc::new(plugin::Plugins::load(state.config.plugins.clone()));
+        Ok(state)
+    }
+
+    /// Loads the state, bringing an existing index of the notes and existing search
+    /// indexes up to date instead of building new ones.
+    fn load_with(
+        config: Config,
+        previous: &Index,
+        search: &mut search::SearchIndex,
+        semantic: &mut semantic::Index,
+    ) -> io::Result<Self> {
+        let content_path = fs::canonicalize(&config.content_path)?;
+        let mut summaries =
+            summary::Summaries::open(config.data_path.join("summaries.json"));
+        let filters =
+            Arc::new(filter::Filters::new(config.filters.clone(), &config.cache));
+        let (index, scheduled) = generate_index(
+            &content_path,
+            previous,
+            &filters,
+            search,
+            &config.search,
+            &mut summaries,
+            &config.summaries,
+        )?;
+        if let Err(e) = search.save() {
+            error!("Failed to save search index: {e}");
+        }
+        let unsummarized = summaries.take_missing();
+        if config.summaries.endpoint.is_some() {
+            let descs: HashSet<_> =
+                index.iter().filter_map(|x| x.desc.as_deref()).collect();
+            summaries.retain(|summary| descs.contains(summary));
+            if let Err(e) = summaries.save() {
+                error!("Failed to save summaries: {e}");
+            }
+        }
+        let mut unembedded = Vec::new();
+        if config.semantic.endpoint.is_some() {
+            unembedded = stale_embeddings(&index, semantic);
+            if let Err(e) = semantic.save() {
+                error!("Failed to save embeddings: {e}");
+            }
+        }
         if index.is_empty() {
             warn!("Index is empty!");
         }
-        let (index_html, _) = mdtodoc(
-            &generate_index_html(&index),
-            Meta {
-                title: String::from("Index"),
-                date:  NaiveDate::default().into(),
-                lang:  None,
-                desc:  None,
-            },
+        let config_path = config_path();
+        let config_dir = config_path.parent().expect("config file has a parent dir");
+        let hooks = hooks::Hooks::load(&config_dir.join(hooks::FILE));
+        let mut theme = theme::Theme::load(&config.theme, config_dir).map_err(|e| {
+            let message = format!("failed to load theme \"{}\": {e}", config.theme);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        theme.styles.push_str(&appearance::css(&config.appearance));
+        let footer = footer::render(&config.footer, config_dir, chrono::Utc::now());
+        let index_html = render_page(
+            &config,
+            &theme,
+            &footer,
+            &Meta::inferred(String::from("Index"), NaiveDate::default()),
+            &index_page_html(&hooks.index(&index), 1, &config, "", false)
+                .unwrap_or_default(),
+            false,
+        );
+        let mut store = store::Store::open(config.data_path.join("store.json"))?;
+        let checksums_changed = integrity::update(&mut store.checksums, &content_path)
+            .unwrap_or_else(|e| {
+                error!("Failed to update checksums: {e}");
+                false
+            });
+        let usage = usage::Usage::measure(&content_path, &config.data_path)
+            .unwrap_or_else(|e| {
+                error!("Failed to measure disk usage: {e}");
+                usage::Usage::default()
+            });
+        let today = chrono::Local::now().date_naive();
+        let usage_changed = usage::record(&mut store.usage, today, usage);
+        if checksums_changed || usage_changed {
+            store
+                .save()
+                .unwrap_or_else(|e| error!("Failed to save the store: {e}"));
+        }
+        let pages = cache::Lru::new(
+            config.cache.html_entries,
+            config.cache.html_bytes,
+            |(_, page, _): &CachedPage| page.len(),
+        );
+        let redirects = match fs::read_to_string(content_path.join(redirects::FILE)) {
+            Ok(text) => redirects::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let links = wikilink::Links::new(
+            index
+                .iter()
+                .map(|doc| (doc.title.as_str(), doc.rel_path.as_str())),
         );
+        let assets = asset_report(&content_path, &index).unwrap_or_else(|e| {
+            error!("Failed to look for unused files: {e}");
+            assets::Report::default()
+        });
         Ok(Self {
+            config,
             content_path,
+            links: Arc::new(links),
             index,
             index_html,
+            search: std::mem::take(search),
+            semantic: std::mem::take(semantic),
+            filters,
+            // Compiling plugins is slow, so it's left to the callers, which may
+            // already have them.
+            plugins: Arc::default(),
+            hooks: Arc::new(hooks),
+            theme,
+            footer,
+            store: Mutex::new(store),
+            pages: Mutex::new(pages),
+            redirects,
+            events: Arc::default(),
+            assets,
+            scheduled,
+            unsummarized,
+            unembedded,
+            limiter: Mutex::default(),
+            usage: Mutex::new(usage),
         })
     }
 
-    fn serve(state: Arc<Mutex<Self>>, server: Server) {
-        loop {
-            let request = match server.recv() {
-                Ok(rq) => rq,
-                Err(e) => {
-                    error!("{e}");
-                    break;
-                }
-            };
+    /// Responds with a 500 and the incident ID [`server_error`] logs `error` under.
+    fn server_error(
+        &self,
+        message: &str,
+        error: &dyn std::error::Error,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        server_error(&self.config, &self.theme, &self.footer, 500, message, error)
+    }
 
-            let state = state.lock().unwrap();
+    /// A page saying there's nothing at `path`, suggesting the listed notes whose
+    /// title or file name is closest to what it ends with.
+    fn not_found(
+        &self,
+        path: &str,
+        encoder: compress::Encoder,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let wanted = path.rsplit('/').find(|x| !x.is_empty()).unwrap_or_default();
+        let wanted = wanted.strip_suffix(".md").unwrap_or(wanted);
+        let listed: Vec<_> = self.index.iter().filter(|doc| !doc.unlisted).collect();
+        let suggestions = suggest::closest(
+            wanted,
+            &listed,
+            |doc| {
+                let stem = Path::new(&doc.rel_path)
+                    .file_stem()
+                    .and_then(|x| x.to_str());
+                [Some(doc.title.as_str()), stem].into_iter().flatten()
+            },
+            5,
+        );
+        let suggestions: Vec<_> = suggestions.into_iter().copied().collect();
+        let body = NotFoundTemplate {
+            path,
+            suggestions: &suggestions,
+        }
+        .render()
+        .unwrap();
+        let mut meta = Meta::inferred(String::from("Not found"), NaiveDate::default());
+        meta.noindex = true;
+        let page =
+            render_page(&self.config, &self.theme, &self.footer, &meta, &body, false);
+        html_response(encoder, page).with_status_code(404)
+    }
 
-            let method = request.method();
-            let Some(path) = uri::percent_decode(request.url()) else {
-                respond_or_log(request, Response::empty(400));
-                continue;
-            };
+    /// Answers a request that writes to the site when its client isn't authorized,
+    /// or when it would take the site over its quota.
+    fn refuse_write(&self, request: &Request) -> Option<Response<io::Cursor<Vec<u8>>>> {
+        if !self.is_authorized(request) {
+            return Some(unauthorized());
+        }
+        self.reserve(request)
+    }
 
-            match (path.as_str(), method) {
-                ("/", Method::Get) => respond_or_log(
-                    request,
-                    Response::from_string(&state.index_html).with_header(
-                        Header::from_bytes(b"Content-Type", b"text/html").unwrap(),
-                    ),
-                ),
-                _ if path.starts_with("/note/") => {
-                    let path = path.strip_prefix("/note/").unwrap();
-                    let Some(entry) =
-                        state.index.iter().find(|entry| entry.rel_path == path)
-                    else {
-                        respond_or_log(request, Response::empty(404));
-                        continue;
-                    };
-                    let data_path = state.content_path.join(entry.rel_path.as_str());
-                    let data = std::fs::read_to_string(&data_path).unwrap();
-                    let (document, _) = mdtodoc(
-                        &data,
-                        Meta::inferred(entry.title.clone(), entry.created),
-                    );
-                    respond_or_log(
-                        request,
-                        Response::from_string(document).with_header(
-                            Header::from_bytes(b"Content-Type", b"text/html").unwrap(),
-                        ),
-                    )
+    /// Counts what `request` would write as written, or answers 507 when that would
+    /// take the site over its quota.
+    fn reserve(&self, request: &Request) -> Option<Response<io::Cursor<Vec<u8>>>> {
+        let len = request.body_length() as u64;
+        let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        if self
+            .config
+            .quota
+            .is_some_and(|quota| usage.total().saturating_add(len) > quota)
+        {
+            return Some(
+                Response::from_string("Insufficient Storage").with_status_code(507),
+            );
+        }
+        usage.content = usage.content.saturating_add(len);
+        None
+    }
+
+    /// Whether a note scheduled to be published is due, and a reload would list it.
+    fn is_publishing_due(&self) -> bool {
+        let now = chrono::Local::now().naive_local();
+        self.scheduled.values().any(|&date| date <= now)
+    }
+
+    fn needs_enriching(&self) -> bool {
+        !self.unsummarized.is_empty() || !self.unembedded.is_empty()
+    }
+
+    /// Asks the models for the summaries and embeddings the last reload found
+    /// missing, without holding up requests while they answer, then reloads to show
+    /// the summaries.
+    fn enrich(lock: &RwLock<Self>) {
+        let (config, content_path, unsummarized, unembedded) = {
+            let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+            let state = &mut *state;
+            // A reload since the last time may have found the same notes missing.
+            let described: HashSet<_> = state
+                .index
+                .iter()
+                .filter(|doc| doc.desc.is_some())
+                .map(|doc| (doc.rel_path.as_str(), doc.modified))
+                .collect();
+            let unsummarized: Vec<_> = std::mem::take(&mut state.unsummarized)
+                .into_iter()
+                .filter(|x| !described.contains(&(x.rel_path.as_str(), x.modified)))
+                .collect();
+            let unembedded: Vec<_> = std::mem::take(&mut state.unembedded)
+                .into_iter()
+                .filter(|(rel_path, modified)| {
+                    !state.semantic.is_fresh(rel_path, *modified)
+                })
+                .collect();
+            (
+                state.config.clone(),
+                state.content_path.clone(),
+                unsummarized,
+                unembedded,
+            )
+        };
+        let summaries = summary::fetch(&config.summaries, &unsummarized);
+        let embeddings = embed_notes(&config.semantic, &content_path, &unembedded);
+        if summaries.is_empty() && embeddings.is_empty() {
+            return;
+        }
+
+        let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+        // The models or prompt may have changed since.
+        if state.semantic.model() == config.semantic.model {
+            for (rel_path, modified, vectors) in embeddings {
+                state.semantic.insert(rel_path, vectors, modified);
+            }
+            if let Err(e) = state.semantic.save() {
+                error!("Failed to save embeddings: {e}");
+            }
+        }
+        if summaries.is_empty() || state.config.summaries != config.summaries {
+            return;
+        }
+        let mut saved = summary::Summaries::open(config.data_path.join("summaries.json"));
+        for (note, summary) in summaries {
+            let doc = state.index.iter_mut().find(|doc| {
+                doc.rel_path == note.rel_path && doc.modified == note.modified
+            });
+            if let Some(doc) = doc {
+                doc.desc = Some(summary.clone());
+            }
+            saved.insert(note, summary);
+        }
+        if let Err(e) = saved.save() {
+            error!("Failed to save summaries: {e}");
+        }
+        // The notes keep their summaries through the reload, which renders them.
+        let config = state.config.clone();
+        if let Err(e) = state.reload(config) {
+            error!("Failed to reload state (retaining previous state): {e}");
+        }
+    }
+
+    fn reload(&mut self, config: Config) -> io::Result<()> {
+        use std::collections::HashMap;
+
+        let mut search = std::mem::take(&mut self.search);
+        let mut semantic = std::mem::take(&mut self.semantic);
+        if semantic.model() != config.semantic.model {
+            semantic = semantic::Index::open(
+                config.data_path.join("embeddings.json"),
+                &config.semantic.model,
+            );
+        }
+        // Summaries from another model or prompt would differ.
+        let empty = Vec::new();
+        let previous = match self.config.summaries == config.summaries {
+            true => &self.index,
+            false => &empty,
+        };
+        match Self::load_with(config, previous, &mut search, &mut semantic) {
+            Ok(mut state) => {
+                let before: HashMap<_, _> = self
+                    .index
+                    .iter()
+                    .map(|doc| (doc.rel_path.as_str(), doc.modified))
+                    .collect();
+                let changed = changed_notes(&self.index, &state.index);
+                // Notes that weren't in the previous index, unless it was empty and
+                // every note would be.
+                let base = state.config.base_url.as_ref();
+                let base = base.map_or("", |x| x.as_str().trim_end_matches('/'));
+                let published: Vec<_> = state
+                    .index
+                    .iter()
+                    .filter(|doc| !doc.unlisted && !before.is_empty())
+                    .filter(|doc| !before.contains_key(doc.rel_path.as_str()))
+                    .map(|doc| notify::Note {
+                        title:    doc.title.clone(),
+                        url:      format!("{base}/note/{}", doc.rel_path),
+                        rel_path: doc.rel_path.clone(),
+                        tags:     doc.tags.clone(),
+                    })
+                    .collect();
+                notify::notify(&state.config.notify, &published);
+                state.events = std::mem::take(&mut self.events);
+                state.limiter = std::mem::take(&mut self.limiter);
+                // Keep the filters' outputs unless the filters changed.
+                if state.config.filters == self.config.filters {
+                    state.filters = Arc::clone(&self.filters);
                 }
-                _ => {
-                    respond_or_log(request, Response::empty(404));
+                state.plugins = match state.config.plugins == self.config.plugins {
+                    true => Arc::clone(&self.plugins),
+                    false => {
+                        Arc::new(plugin::Plugins::load(state.config.plugins.clone()))
+                    }
+                };
+                *self = state;
+                let mut events = self.events.lock().unwrap();
+                events.send("index-changed", "");
+                for rel_path in changed {
+                    events.send("note-changed", &rel_path);
                 }
+                Ok(())
+            }
+            Err(e) => {
+                self.search = search;
+                self.semantic = semantic;
+                Err(e)
+            }
+        }
+    }
+
+    /// Renders page `page` of the index for a visitor reading `languages`, with a
+    /// toggle between only their languages and everything, and for the owner, with
+    /// buttons to read notes later. Borrows the rendered first page when that's what
+    /// it would be anyway. `None` when there's no such page.
+    fn index_in(
+        &self,
+        languages: &[String],
+        show_all: bool,
+        owner: bool,
+        page: usize,
+    ) -> Option<Cow<'_, str>> {
+        let readable = |doc: &&IndexedDocument| {
+            doc.lang.as_deref().is_none_or(|lang| {
+                let primary = lang.split(['-', '_']).next().unwrap_or(lang);
+                languages.iter().any(|x| x.eq_ignore_ascii_case(primary))
+            })
+        };
+        let filtered =
+            !languages.is_empty() && !self.index.iter().all(|doc| readable(&doc));
+        if !filtered && !owner && page == 1 {
+            return Some(Cow::Borrowed(&self.index_html));
+        }
+        let listed = self.hooks.index(&self.index);
+        let page = if !filtered {
+            index_page_html(&listed, page, &self.config, "", owner)?
+        } else if show_all {
+            r#"<p class="languages"><a href="/">Only show notes in my languages</a></p>"#
+                .to_string()
+                + &index_page_html(&listed, page, &self.config, "lang=all", owner)?
+        } else {
+            let listed: Vec<_> = listed.into_iter().filter(readable).collect();
+            r#"<p class="languages">Showing notes in your languages. <a href="/?lang=all">Show all notes</a></p>"#
+                .to_string()
+                + &index_page_html(&listed, page, &self.config, "", owner)?
+        };
+        let meta = Meta::inferred(String::from("Index"), NaiveDate::default());
+        Some(Cow::Owned(render_page(
+            &self.config,
+            &self.theme,
+            &self.footer,
+            &meta,
+            &page,
+            false,
+        )))
+    }
+
+    /// When the newest change to any note was made.
+    fn modified(&self) -> SystemTime {
+        self.index
+            .iter()
+            .map(|doc| doc.modified)
+            .max()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    /// The notes matching `query`, best first: those with its words, and with
+    /// semantic search, those about the same thing.
+    fn find(&self, query: &search::Query) -> Vec<search::Hit<'_>> {
+        let hits = self.search.search(query, &self.config.search);
+        let text = query.text();
+        if self.config.semantic.endpoint.is_none() || text.is_empty() {
+            return hits;
+        }
+        match self.semantic.search(&self.config.semantic, &text) {
+            Ok(similar) => {
+                let weight = self.config.semantic.weight;
+                self.search.merge(hits, &similar, query, weight)
+            }
+            Err(e) => {
+                warn!("Falling back to keyword search: {e}");
+                hits
+            }
+        }
+    }
+
+    /// The notes matching the saved search `name`, newest first.
+    fn view(&self, name: &str) -> Option<Vec<&IndexedDocument>> {
+        let query = search::Query::parse(self.config.views.get(name)?);
+        let hits = self.search.search(&query, &self.config.search);
+        let paths: HashSet<_> =
+            hits.iter().map(|hit| hit.doc.rel_path.as_str()).collect();
+        Some(
+            self.index
+                .iter()
+                .filter(|doc| paths.contains(doc.rel_path.as_str()))
+                .collect(),
+        )
+    }
+
+    /// Who wrote the notes, for feeds: the profile's name, if there is one.
+    fn author(&self) -> String {
+        match self.profile() {
+            Some((profile, ..)) => profile.name,
+            None => String::from("Unknown"),
+        }
+    }
+
+    /// Reads the profile from [`profile::NOTE`], along with the note's meta and
+    /// rendered body.
+    fn profile(&self) -> Option<(profile::Profile, Meta, String)> {
+        let md = fs::read_to_string(self.content_path.join(profile::NOTE)).ok()?;
+        let inferred = Meta::inferred(String::from("About"), NaiveDate::default());
+        let (body, mut meta) = render_markdown(&md, inferred, &self.filters, &self.links);
+        Some((meta.profile.take()?, meta, body))
+    }
+
+    /// Finds a non-hidden file inside the content directory.
+    fn resolve_file(&self, rel_path: &str) -> Option<PathBuf> {
+        let path = fs::canonicalize(self.content_path.join(rel_path)).ok()?;
+        let is_hidden = path
+            .strip_prefix(&self.content_path)
+            .ok()?
+            .components()
+            .any(|c| c.as_os_str().as_encoded_bytes().starts_with(b"."));
+        (!is_hidden && path.is_file()).then_some(path)
+    }
+
+    /// Whether to strip metadata from the image at `rel_path`, letting notes in the
+    /// same directory override the site-wide setting.
+    fn should_strip_exif(&self, rel_path: &str) -> bool {
+        let dir = Path::new(rel_path).parent();
+        self.index
+            .iter()
+            .filter(|doc| Path::new(&doc.rel_path).parent() == dir)
+            .find_map(|doc| doc.strip_exif)
+            .unwrap_or(self.config.strip_exif)
+    }
+
+    /// Checks the request's bearer token, or the cookie set by `/login`, against
+    /// [`Config::api_token`].
+    fn is_authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.config.api_token else {
+            return false;
+        };
+        let bearer =
+            header(request, "Authorization").and_then(|x| x.strip_prefix("Bearer "));
+        let cookie = header(request, "Cookie").and_then(|cookies| {
+            cookies
+                .split(';')
+                .find_map(|x| x.trim().strip_prefix(TOKEN_COOKIE)?.strip_prefix('='))
+        });
+        bearer.or(cookie).is_some_and(|x| x == token)
+    }
+
+    /// Whether the request carries the API token, accepting it as the password of a
+    /// `Basic` login too, which is how WebDAV clients send it.
+    fn is_dav_authorized(&self, request: &Request) -> bool {
+        self.is_authorized(request)
+            || header(request, "Authorization")
+                .and_then(dav::basic_password)
+                .is_some_and(|x| self.config.api_token.as_ref() == Some(&x))
+    }
+
+    /// Whether the request may see the members-only part of notes: it's the owner's
+    /// or carries one of [`Config::share_tokens`] as `share`.
+    fn is_member(&self, request: &Request, share: Option<&str>) -> bool {
+        self.is_authorized(request)
+            || share.is_some_and(|x| self.config.share_tokens.iter().any(|t| t == x))
+    }
+
+    /// Sets the token cookie so the owner is authorized in their browser.
+    fn login(&self, request: &mut Request) -> Response<io::Cursor<Vec<u8>>> {
+        let mut form = String::new();
+        if request
+            .as_reader()
+            .take(4096)
+            .read_to_string(&mut form)
+            .is_err()
+        {
+            return Response::from_string("Expected a form").with_status_code(400);
+        }
+        let token = uri::parse_query(&form)
+            .into_iter()
+            .find_map(|(key, value)| (key == "token").then_some(value));
+        if self.config.api_token.is_none() || token != self.config.api_token {
+            return unauthorized();
+        }
+        let cookie = format!(
+            "{TOKEN_COOKIE}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age=31536000",
+            token.unwrap()
+        );
+        Response::from_string("")
+            .with_status_code(303)
+            .with_header(Header::from_bytes(b"Location", b"/").unwrap())
+            .with_header(Header::from_bytes(b"Set-Cookie", cookie).unwrap())
+    }
+
+    /// Logs a reader's search, so `/stats` can show what people look for. The
+    /// owner's own searches aren't counted.
+    fn log_search(&self, request: &Request, q: &str, results: usize) {
+        if q.trim().is_empty() || self.is_authorized(request) {
+            return;
+        }
+        let today = chrono::Local::now().date_naive();
+        let mut store = self.store.lock().unwrap();
+        store.record_query(q, results, today);
+        if let Err(e) = store.save() {
+            error!("Failed to save query log: {e}");
+        }
+    }
+
+    /// The site's absolute URL without a trailing slash. Without a configured one,
+    /// or for clients of the onion service, it's guessed from how the client reached
+    /// the site.
+    fn base(&self, client: &forwarded::Client) -> String {
+        match (&self.config.base_url, client.onion) {
+            (Some(url), false) => url.as_str().trim_end_matches('/').to_string(),
+            _ => client.origin(),
+        }
+    }
+
+    /// Memory usage of the caches, in the Prometheus text format.
+    fn metrics(&self) -> String {
+        let (documents, search_bytes) = self.search.usage();
+        let pages = self.pages.lock().unwrap();
+        let highlighted = highlight::cache().lock().unwrap();
+        let metrics = [
+            (
+                "notes_indexed",
+                "gauge",
+                "Notes in the index.",
+                self.index.len() as u64,
+            ),
+            (
+                "notes_html_cache_entries",
+                "gauge",
+                "Rendered pages in the cache.",
+                pages.len() as u64,
+            ),
+            (
+                "notes_html_cache_bytes",
+                "gauge",
+                "Size of the rendered pages in the cache.",
+                pages.bytes() as u64,
+            ),
+            (
+                "notes_html_cache_hits_total",
+                "counter",
+                "Pages served from the cache.",
+                pages.hits,
+            ),
+            (
+                "notes_html_cache_misses_total",
+                "counter",
+                "Pages that had to be rendered.",
+                pages.misses,
+            ),
+            (
+                "notes_search_documents",
+                "gauge",
+                "Notes in the search index.",
+                documents as u64,
+            ),
+            (
+                "notes_search_bytes",
+                "gauge",
+                "Approximate size of the search index.",
+                search_bytes as u64,
+            ),
+            (
+                "notes_highlight_cache_entries",
+                "gauge",
+                "Highlighted code blocks in the cache.",
+                highlighted.len() as u64,
+            ),
+            (
+                "notes_highlight_cache_bytes",
+                "gauge",
+                "Size of the highlighted code blocks in the cache.",
+                highlighted.bytes() as u64,
+            ),
+            (
+                "notes_highlight_cache_hits_total",
+                "counter",
+                "Code blocks highlighted from the cache.",
+                highlighted.hits,
+            ),
+            (
+                "notes_highlight_cache_misses_total",
+                "counter",
+                "Code blocks that had to be highlighted.",
+                highlighted.misses,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+            ));
+        }
+        text
+    }
+
+    /// Moves the note at `rel_path` to the column posted in the form, rewriting the
+    /// note's meta, and sends the browser back to the board.
+    fn set_status(
+        &mut self,
+        rel_path: &str,
+        request: &mut Request,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let mut form = String::new();
+        if request
+            .as_reader()
+            .take(4096)
+            .read_to_string(&mut form)
+            .is_err()
+        {
+            return Response::from_string("Expected a form").with_status_code(400);
+        }
+        let form = uri::parse_query(&form);
+        let field = |name: &str| {
+            form.iter()
+                .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+        };
+        let Some(status) = field("status").filter(|x| !x.trim().is_empty()) else {
+            return Response::from_string("Expected a status").with_status_code(400);
+        };
+        let Some(doc) = self.index.iter().find(|doc| doc.rel_path == rel_path) else {
+            return Response::from_string("No such note").with_status_code(404);
+        };
+        let created = doc.created;
+
+        let path = self.content_path.join(rel_path);
+        let result = fs::read_to_string(&path).and_then(|md| {
+            let value = toml::Value::String(status.trim().to_string());
+            fs::write(&path, set_meta(&md, "status", &value, created))
+        });
+        if let Err(e) = result {
+            let message = format!("Failed to set status of \"{rel_path}\"");
+            return self.server_error(&message, &e);
+        }
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after writing \"{rel_path}\": {e}");
+        }
+        let back = match field("tag") {
+            Some(tag) => format!("/board/{}", uri::percent_encode(tag)),
+            None => format!("/note/{rel_path}"),
+        };
+        Response::from_string("")
+            .with_status_code(303)
+            .with_header(Header::from_bytes(b"Location", back).unwrap())
+    }
+
+    /// Describes the file or directory at `rel_path` for WebDAV, and unless `depth`
+    /// is 0, what's in it. Deeper listings are limited to one level.
+    fn propfind(
+        &self,
+        rel_path: &str,
+        depth: Option<&str>,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let path = self.content_path.join(rel_path);
+        let result = fs::metadata(&path).and_then(|metadata| {
+            let mut resources = vec![dav_resource(rel_path.to_string(), &metadata)];
+            if metadata.is_dir() && depth != Some("0") {
+                for entry in fs::read_dir(&path)? {
+                    let entry = entry?;
+                    let Some(name) = entry.file_name().to_str().map(str::to_string)
+                    else {
+                        continue;
+                    };
+                    if name.starts_with('.') {
+                        continue;
+                    }
+                    let rel_path = match rel_path.is_empty() {
+                        true => name,
+                        false => format!("{rel_path}/{name}"),
+                    };
+                    resources.push(dav_resource(rel_path, &entry.metadata()?));
+                }
+            }
+            Ok(resources)
+        });
+        match result {
+            Ok(resources) => Response::from_string(dav::multistatus(&resources))
+                .with_status_code(207)
+                .with_header(
+                    Header::from_bytes(
+                        b"Content-Type",
+                        b"application/xml; charset=utf-8",
+                    )
+                    .unwrap(),
+                ),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Response::from_string("").with_status_code(404)
+            }
+            Err(e) => {
+                let message = format!("Failed to list \"{rel_path}\"");
+                self.server_error(&message, &e)
+            }
+        }
+    }
+
+    /// The file at `rel_path`, as it is, for WebDAV.
+    fn dav_get(&self, rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let path = self.content_path.join(rel_path);
+        if path.is_dir() {
+            return Response::from_string("Directories can't be downloaded")
+                .with_status_code(405)
+                .with_header(Header::from_bytes(b"Allow", dav::ALLOW).unwrap());
+        }
+        match fs::read(&path) {
+            Ok(data) => {
+                let mime = mime_guess::from_path(rel_path).first_or_octet_stream();
+                Response::from_data(data).with_header(
+                    Header::from_bytes(b"Content-Type", mime.to_string()).unwrap(),
+                )
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Response::from_string("").with_status_code(404)
+            }
+            Err(e) => {
+                let message = format!("Failed to read \"{rel_path}\"");
+                self.server_error(&message, &e)
+            }
+        }
+    }
+
+    /// Writes the request's body to the file at `rel_path` for WebDAV, and reloads
+    /// the state, which it may have changed a note of.
+    fn dav_put(
+        &mut self,
+        rel_path: &str,
+        request: &mut Request,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let path = self.content_path.join(rel_path);
+        if rel_path.is_empty() || path.is_dir() {
+            return Response::from_string("Can't write a directory")
+                .with_status_code(405)
+                .with_header(Header::from_bytes(b"Allow", dav::ALLOW).unwrap());
+        }
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Response::from_string("The directory doesn't exist")
+                .with_status_code(409);
+        }
+        let limit = self.config.max_upload_size;
+        let mut body = Vec::new();
+        if let Err(e) = request.as_reader().take(limit + 1).read_to_end(&mut body) {
+            error!("Failed to read WebDAV upload: {e}");
+            return Response::from_string("Failed to read the body")
+                .with_status_code(400);
+        }
+        if body.len() as u64 > limit {
+            return Response::from_string("Too large").with_status_code(413);
+        }
+        let existed = path.exists();
+        if let Err(e) = fs::write(&path, body) {
+            let message = format!("Failed to write \"{rel_path}\"");
+            return self.server_error(&message, &e);
+        }
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after writing \"{rel_path}\": {e}");
+        }
+        Response::from_string("").with_status_code(if existed { 204 } else { 201 })
+    }
+
+    /// The editor for the file at `rel_path`, empty if there's none yet.
+    fn editor(&self, rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let text = match fs::read_to_string(self.content_path.join(rel_path)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                return Response::from_string("Only text files can be edited")
+                    .with_status_code(415);
+            }
+            Err(e) => {
+                let message = format!("Failed to read \"{rel_path}\"");
+                return self.server_error(&message, &e);
+            }
+        };
+        let editor = pages::editor(rel_path, &text);
+        let meta = Meta::inferred(format!("Editing {rel_path}"), NaiveDate::default());
+        let page = render_page(
+            &self.config,
+            &self.theme,
+            &self.footer,
+            &meta,
+            &editor,
+            false,
+        );
+        Response::from_string(page).with_header(
+            Header::from_bytes(b"Content-Type", b"text/html; charset=utf-8").unwrap(),
+        )
+    }
+
+    /// Saves the posted form's `text` as the file at `rel_path`, creating it and the
+    /// directories it's in if needed, and sends the browser to the note rendered
+    /// anew.
+    fn save_edit(
+        &mut self,
+        rel_path: &str,
+        request: &mut Request,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let limit = self.config.max_upload_size;
+        let mut form = String::new();
+        if request
+            .as_reader()
+            .take(limit + 1)
+            .read_to_string(&mut form)
+            .is_err()
+        {
+            return Response::from_string("Expected a form").with_status_code(400);
+        }
+        if form.len() as u64 > limit {
+            return Response::from_string("Too large").with_status_code(413);
+        }
+        let Some(text) = uri::parse_query(&form)
+            .into_iter()
+            .find_map(|(key, value)| (key == "text").then_some(value))
+        else {
+            return Response::from_string("Expected a text").with_status_code(400);
+        };
+        // Forms send line breaks as CRLF.
+        let text = text.replace("\r\n", "\n");
+
+        let path = self.content_path.join(rel_path);
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, text));
+        if let Err(e) = result {
+            let message = format!("Failed to save \"{rel_path}\"");
+            return self.server_error(&message, &e);
+        }
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after writing \"{rel_path}\": {e}");
+        }
+        Response::from_string("").with_status_code(303).with_header(
+            Header::from_bytes(b"Location", format!("/note/{rel_path}")).unwrap(),
+        )
+    }
+
+    /// Creates the directory at `rel_path` for WebDAV.
+    fn dav_mkcol(&self, rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let path = self.content_path.join(rel_path);
+        if rel_path.is_empty() || path.exists() {
+            return Response::from_string("Already exists")
+                .with_status_code(405)
+                .with_header(Header::from_bytes(b"Allow", dav::ALLOW).unwrap());
+        }
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Response::from_string("The directory doesn't exist")
+                .with_status_code(409);
+        }
+        match fs::create_dir(&path) {
+            Ok(()) => Response::from_string("").with_status_code(201),
+            Err(e) => {
+                let message = format!("Failed to create \"{rel_path}\"");
+                self.server_error(&message, &e)
+            }
+        }
+    }
+
+    /// The markdown of `doc` as a reader sees it: without the members-only part,
+    /// unless they're a `member`.
+    fn visible_markdown(
+        &self,
+        doc: &IndexedDocument,
+        member: bool,
+    ) -> io::Result<String> {
+        let data = fs::read_to_string(self.content_path.join(&doc.rel_path))?;
+        let md = note_markdown(&self.filters, &doc.rel_path, data);
+        Ok(match member {
+            true => md,
+            false => members::public(&md).to_string(),
+        })
+    }
+
+    /// The token a request deleting `doc` has to repeat to go through. It changes
+    /// whenever the note does, so a stale confirmation can't delete newer changes.
+    fn delete_token(&self, doc: &IndexedDocument) -> String {
+        use sha2::{Digest, Sha256};
+
+        let modified = doc
+            .modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let secret = self.config.api_token.as_deref().unwrap_or_default();
+        let digest = Sha256::digest(format!("{secret}\n{}\n{modified}", doc.rel_path));
+        hex(&digest)[..32].to_string()
+    }
+
+    /// Moves the note at `rel_path` to the trash in the data directory, if `confirm`
+    /// is the token an earlier request to delete it was answered with. Without it,
+    /// answers with that token.
+    fn delete(
+        &mut self,
+        rel_path: &str,
+        confirm: Option<&str>,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let Some(doc) = self.index.iter().find(|doc| doc.rel_path == rel_path) else {
+            return Response::from_string("No such note").with_status_code(404);
+        };
+        let token = self.delete_token(doc);
+        match confirm {
+            None => {
+                let body =
+                    serde_json::to_vec(&BTreeMap::from([("confirm", token)])).unwrap();
+                return Response::from_data(body).with_header(
+                    Header::from_bytes(b"Content-Type", b"application/json").unwrap(),
+                );
+            }
+            Some(confirm) if confirm != token => {
+                return Response::from_string(
+                    "The confirmation doesn't match, the note may have changed since",
+                )
+                .with_status_code(409);
+            }
+            Some(_) => {}
+        }
+
+        let from = self.content_path.join(rel_path);
+        let to = self.config.data_path.join("trash").join(rel_path);
+        let result = fs::create_dir_all(to.parent().unwrap_or(&self.config.data_path))
+            .and_then(|_| fs::rename(&from, &to))
+            // Renaming doesn't work across file systems.
+            .or_else(|_| fs::copy(&from, &to).and_then(|_| fs::remove_file(&from)));
+        if let Err(e) = result {
+            let message = format!("Failed to delete \"{rel_path}\"");
+            return self.server_error(&message, &e);
+        }
+        info!("Moved \"{rel_path}\" to the trash");
+        self.store.get_mut().unwrap().dequeue(rel_path);
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after deleting \"{rel_path}\": {e}");
+        }
+        Response::from_string("").with_status_code(204)
+    }
+
+    /// Records in the meta block of the note at `rel_path` that it was reviewed
+    /// today, and sends the browser back to the review queue.
+    fn mark_reviewed(&mut self, rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let Some(doc) = self.index.iter().find(|doc| doc.rel_path == rel_path) else {
+            return Response::from_string("No such note").with_status_code(404);
+        };
+        let created = doc.created;
+
+        let path = self.content_path.join(rel_path);
+        let today = chrono::Local::now().date_naive();
+        let result = fs::read_to_string(&path).and_then(|md| {
+            let value = toml::Value::String(today.to_string());
+            fs::write(&path, set_meta(&md, "last_reviewed", &value, created))
+        });
+        if let Err(e) = result {
+            let message = format!("Failed to mark \"{rel_path}\" reviewed");
+            return self.server_error(&message, &e);
+        }
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after writing \"{rel_path}\": {e}");
+        }
+        Response::from_string("")
+            .with_status_code(303)
+            .with_header(Header::from_bytes(b"Location", "/review").unwrap())
+    }
+
+    /// The flashcards due for review on `today`, with their notes, in the order
+    /// their notes are listed.
+    fn due_cards(&self, today: NaiveDate) -> Vec<(&IndexedDocument, &cards::Card)> {
+        let store = self.store.lock().unwrap();
+        self.index
+            .iter()
+            .flat_map(|doc| doc.cards.iter().map(move |card| (doc, card)))
+            .filter(|(doc, card)| {
+                let review = store.cards.get(&card.key(&doc.rel_path));
+                review.is_none_or(|x| x.is_due(today))
+            })
+            .collect()
+    }
+
+    /// Schedules the next review of the posted form's `card` after an answer
+    /// graded `grade`, from 0 to 5, and sends the browser on to the next card.
+    fn review_card(&self, request: &mut Request) -> Response<io::Cursor<Vec<u8>>> {
+        let mut form = String::new();
+        if request
+            .as_reader()
+            .take(16 * 1024)
+            .read_to_string(&mut form)
+            .is_err()
+        {
+            return Response::from_string("Expected a form").with_status_code(400);
+        }
+        let form = uri::parse_query(&form);
+        let field = |name: &str| {
+            form.iter()
+                .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+        };
+        let Some(grade) = field("grade")
+            .and_then(|x| x.parse::<u8>().ok())
+            .filter(|x| *x <= 5)
+        else {
+            return Response::from_string("Expected a grade from 0 to 5")
+                .with_status_code(400);
+        };
+        let Some(key) = field("card") else {
+            return Response::from_string("Expected a card").with_status_code(400);
+        };
+        let exists = self
+            .index
+            .iter()
+            .any(|doc| doc.cards.iter().any(|card| card.key(&doc.rel_path) == key));
+        if !exists {
+            return Response::from_string("No such card").with_status_code(404);
+        }
+        let mut store = self.store.lock().unwrap();
+        let today = chrono::Local::now().date_naive();
+        store
+            .cards
+            .entry(key.to_string())
+            .or_default()
+            .grade(grade, today);
+        if let Err(e) = store.save() {
+            return self.server_error("Failed to save review", &e);
+        }
+        Response::from_string("")
+            .with_status_code(303)
+            .with_header(Header::from_bytes(b"Location", "/cards/review").unwrap())
+    }
+
+    /// Changes the note at `rel_path` on the reading list as the posted form's
+    /// `action` says: `add`, `remove`, `up` or `down`. Sends the browser back to the
+    /// index after adding, and to the list otherwise.
+    fn queue(
+        &self,
+        rel_path: &str,
+        request: &mut Request,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let mut form = String::new();
+        if request
+            .as_reader()
+            .take(4096)
+            .read_to_string(&mut form)
+            .is_err()
+        {
+            return Response::from_string("Expected a form").with_status_code(400);
+        }
+        let action = uri::parse_query(&form)
+            .into_iter()
+            .find_map(|(key, value)| (key == "action").then_some(value));
+        if !self.index.iter().any(|doc| doc.rel_path == rel_path) {
+            return Response::from_string("No such note").with_status_code(404);
+        }
+        let mut store = self.store.lock().unwrap();
+        let back = match action.as_deref() {
+            Some("add") => {
+                store.enqueue(rel_path);
+                "/"
+            }
+            Some("remove") => {
+                store.dequeue(rel_path);
+                "/queue"
+            }
+            Some("up") => {
+                store.move_queued(rel_path, -1);
+                "/queue"
+            }
+            Some("down") => {
+                store.move_queued(rel_path, 1);
+                "/queue"
+            }
+            _ => {
+                return Response::from_string("Expected an action").with_status_code(400);
+            }
+        };
+        if let Err(e) = store.save() {
+            return self.server_error("Failed to save reading list", &e);
+        }
+        Response::from_string("")
+            .with_status_code(303)
+            .with_header(Header::from_bytes(b"Location", back).unwrap())
+    }
+
+    /// Saves a highlight posted as JSON for the note at `rel_path`.
+    fn annotate(
+        &self,
+        rel_path: &str,
+        request: &mut Request,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        #[derive(Deserialize)]
+        struct NewAnnotation {
+            quote:   String,
+            comment: Option<String>,
+        }
+
+        if !self.index.iter().any(|doc| doc.rel_path == rel_path) {
+            return Response::from_string("No such note").with_status_code(404);
+        }
+        let new: NewAnnotation =
+            match serde_json::from_reader(request.as_reader().take(64 * 1024)) {
+                Ok(new) => new,
+                Err(e) => {
+                    return Response::from_string(e.to_string()).with_status_code(400);
+                }
+            };
+        if new.quote.trim().is_empty() {
+            return Response::from_string("Empty quote").with_status_code(400);
+        }
+        let mut store = self.store.lock().unwrap();
+        store
+            .annotations
+            .entry(rel_path.to_string())
+            .or_default()
+            .push(store::Annotation {
+                quote:   new.quote.trim().to_string(),
+                comment: new.comment.filter(|x| !x.trim().is_empty()),
+                created: chrono::Local::now().naive_local(),
+            });
+        if let Err(e) = store.save() {
+            return self.server_error("Failed to save annotation", &e);
+        }
+        Response::from_string("").with_status_code(204)
+    }
+
+    /// The note at `rel_path` as a page of its own, without anything only the owner
+    /// sees.
+    fn standalone_page(&self, doc: &IndexedDocument) -> io::Result<String> {
+        let markdown = fs::read_to_string(self.content_path.join(&doc.rel_path))?;
+        let markdown = note_markdown(&self.filters, &doc.rel_path, markdown);
+        let inferred = Meta::inferred(doc.title.clone(), doc.created);
+        let (html, mut meta) =
+            render_markdown(&markdown, inferred, &self.filters, &self.links);
+        let html = self.plugins.transform(&doc.rel_path, html);
+        let html = self.hooks.render(doc, html);
+        meta.kind = doc.kind;
+        meta.desc = meta.desc.or_else(|| doc.desc.clone());
+        let html = match &meta.warning {
+            Some(warning) => content_warning_html(warning, &html),
+            None => html,
+        };
+        let html = match meta.license.as_ref().or(self.config.license.as_ref()) {
+            Some(license) => html + &license::html(license, &meta.title),
+            None => html,
+        };
+        Ok(render_page(
+            &self.config,
+            &self.theme,
+            &self.footer,
+            &meta,
+            &html,
+            false,
+        ))
+    }
+
+    /// Packs the note at `rel_path`, the notes it links to up to `depth` links
+    /// away, and the files they embed or link to into a zip archive of pages that
+    /// link to each other. Links to anything else lead to the site at `base`.
+    fn bundle(&self, rel_path: &str, depth: usize, base: &str) -> io::Result<Vec<u8>> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        let mut queue = VecDeque::from([(rel_path.to_string(), 0)]);
+        let mut included = HashSet::from([rel_path.to_string()]);
+        while let Some((rel_path, distance)) = queue.pop_front() {
+            let Some(doc) = self.index.iter().find(|doc| doc.rel_path == rel_path) else {
+                // Not a note, so a file to include as it is.
+                if let Some(path) = self.resolve_file(&rel_path) {
+                    zip.start_file(rel_path.as_str(), options)?;
+                    zip.write_all(&fs::read(path)?)?;
+                }
+                continue;
+            };
+            let from = bundle::page_path(&rel_path);
+            let page = bundle::rewrite(&self.standalone_page(doc)?, |url| {
+                let Some((target, fragment)) = bundle::resolve(&rel_path, url) else {
+                    let site_relative = url.starts_with('/') && !url.starts_with("//");
+                    return site_relative.then(|| format!("{base}{url}"));
+                };
+                let is_note = self.index.iter().any(|doc| doc.rel_path == target);
+                let to = match is_note {
+                    true => bundle::page_path(&target),
+                    false if self.resolve_file(&target).is_some() => target.clone(),
+                    false => return None,
+                };
+                // Notes are visited breadth-first, so any note that's included has
+                // been by the time one `depth` links away links to it.
+                if !included.contains(&target) {
+                    if is_note && distance >= depth {
+                        let encoded: Vec<_> =
+                            target.split('/').map(uri::percent_encode).collect();
+                        return Some(format!(
+                            "{base}/note/{}{fragment}",
+                            encoded.join("/")
+                        ));
+                    }
+                    included.insert(target.clone());
+                    queue.push_back((target, distance + 1));
+                }
+                Some(format!("{}{fragment}", bundle::relative(&from, &to)))
+            });
+            zip.start_file(from.as_str(), options)?;
+            zip.write_all(page.as_bytes())?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// The note's markdown with its annotations appended.
+    fn export(&self, rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+        if !self.index.iter().any(|doc| doc.rel_path == rel_path) {
+            return Response::from_string("No such note").with_status_code(404);
+        }
+        let mut markdown = match fs::read_to_string(self.content_path.join(rel_path)) {
+            Ok(markdown) => note_markdown(&self.filters, rel_path, markdown),
+            Err(e) => {
+                let message = format!("Failed to read \"{rel_path}\"");
+                return self.server_error(&message, &e);
+            }
+        };
+        if let Some(annotations) = self.store.lock().unwrap().annotations.get(rel_path) {
+            markdown.push_str("\n\n## Annotations\n");
+            for annotation in annotations {
+                markdown.push_str(&format!("\n> {}\n", annotation.quote));
+                if let Some(comment) = &annotation.comment {
+                    markdown.push_str(&format!("\n{comment}\n"));
+                }
+            }
+        }
+        Response::from_string(markdown).with_header(
+            Header::from_bytes(b"Content-Type", b"text/markdown; charset=utf-8").unwrap(),
+        )
+    }
+
+    /// The `k` chunks of notes most relevant to `q`, for an assistant to quote from.
+    /// Chunks are ranked by how often they mention the query's words, weighted by
+    /// how well their note matched; a note that matched only by its title or tags
+    /// offers its first chunk.
+    fn context(&self, q: &str, k: usize) -> Vec<context::Chunk> {
+        let query = search::Query::parse(q);
+        let mut chunks = Vec::new();
+        for hit in self.find(&query) {
+            let path = self.content_path.join(&hit.doc.rel_path);
+            let markdown = match fs::read_to_string(&path) {
+                Ok(markdown) => note_markdown(&self.filters, &hit.doc.rel_path, markdown),
+                Err(e) => {
+                    warn!("Failed to read \"{path:?}\": {e}");
+                    continue;
+                }
+            };
+            let lang = hit.doc.lang.as_deref();
+            let mut found = false;
+            let mut first = None;
+            for (heading, markdown) in context::split(&markdown) {
+                let occurrences = query.occurrences(&markdown, lang);
+                let chunk = context::Chunk {
+                    title: hit.doc.title.clone(),
+                    url: format!("/note/{}", hit.doc.rel_path),
+                    heading,
+                    tokens: context::tokens(&markdown),
+                    markdown,
+                    score: hit.score * occurrences as f32,
+                };
+                if occurrences > 0 {
+                    found = true;
+                    chunks.push(chunk);
+                } else if first.is_none() {
+                    first = Some(chunk);
+                }
+            }
+            if let (false, Some(mut first)) = (found, first) {
+                first.score = hit.score;
+                chunks.push(first);
+            }
+        }
+        chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+        chunks.truncate(k.min(context::MAX_CHUNKS));
+        chunks
+    }
+
+    /// Stores the files in the `multipart/form-data` `body` of `request` and
+    /// responds with the markdown needed to link them.
+    fn upload(&self, request: &Request, body: &[u8]) -> Response<io::Cursor<Vec<u8>>> {
+        let Some(boundary) = header(request, "Content-Type")
+            .and_then(|x| multipart::boundary(x).ok())
+            .map(str::to_string)
+        else {
+            return Response::from_string("Expected multipart/form-data")
+                .with_status_code(400);
+        };
+        let parts = match multipart::parse(body, &boundary) {
+            Ok(parts) => parts,
+            Err(e) => return Response::from_string(e.to_string()).with_status_code(400),
+        };
+
+        let mut snippets = String::new();
+        for part in parts {
+            let Some(filename) = part.filename else {
+                continue;
+            };
+            let rel_path = match self.store_asset(filename, part.data) {
+                Ok(rel_path) => rel_path,
+                Err(e) => {
+                    let message = format!("Failed to store upload \"{filename}\"");
+                    return self.server_error(&message, &e);
+                }
+            };
+            let alt = Path::new(filename)
+                .file_prefix()
+                .and_then(|x| x.to_str())
+                .unwrap_or(filename);
+            let is_image = mime_guess::from_path(filename)
+                .first()
+                .is_some_and(|mime| mime.type_() == mime_guess::mime::IMAGE);
+            if is_image {
+                snippets.push('!');
+            }
+            snippets.push_str(&format!("[{alt}](/note/{rel_path})\n"));
+        }
+        Response::from_string(snippets).with_header(
+            Header::from_bytes(b"Content-Type", b"text/markdown; charset=utf-8").unwrap(),
+        )
+    }
+
+    /// Writes `body` into a new timestamped note in the inbox, titled by `title`,
+    /// and responds with the note's URL.
+    fn capture(
+        &mut self,
+        title: Option<String>,
+        body: Vec<u8>,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let Ok(body) = String::from_utf8(body) else {
+            return Response::from_string("Expected a UTF-8 body").with_status_code(400);
+        };
+
+        let dir = self.config.inbox_dir.clone();
+        let rel_path = match self.create_note(&dir, title, &body) {
+            Ok(rel_path) => rel_path,
+            Err(e) => {
+                let message = "Failed to write captured note";
+                return self.server_error(message, &e);
+            }
+        };
+        info!("Captured note \"{rel_path}\"");
+        created(&rel_path)
+    }
+
+    /// Renders the markdown in the request body to an HTML fragment, as the note at
+    /// `rel_path` would be, for editors to preview.
+    fn preview(
+        &self,
+        request: &mut Request,
+        rel_path: &str,
+        encoder: compress::Encoder,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let limit = self.config.max_upload_size;
+        let mut md = String::new();
+        if let Err(e) = request.as_reader().take(limit + 1).read_to_string(&mut md) {
+            error!("Failed to read preview: {e}");
+            return Response::from_string("Expected a UTF-8 body").with_status_code(400);
+        }
+        if md.len() as u64 > limit {
+            return Response::from_string("Note too large").with_status_code(413);
+        }
+        let today = chrono::Local::now().date_naive();
+        let inferred = Meta::inferred(String::from("Preview"), today);
+        match render_markdown_within(
+            rel_path,
+            md,
+            inferred,
+            Arc::clone(&self.filters),
+            Arc::clone(&self.links),
+            Duration::from_millis(self.config.render_timeout),
+        ) {
+            Ok((html, _)) => html_response(encoder, html),
+            Err(RecvTimeoutError::Timeout) => {
+                Response::from_string("Rendering took too long").with_status_code(503)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Response::from_string("Failed to render").with_status_code(500)
+            }
+        }
+    }
+
+    /// Archives `article` into a new note, with the `images` it embeds stored
+    /// alongside.
+    fn archive(
+        &mut self,
+        article: archive::Article,
+        images: Vec<archive::Image>,
+    ) -> Response<io::Cursor<Vec<u8>>> {
+        let mut markdown = article.markdown.clone();
+        for image in images {
+            let stored = image.data.map(|data| {
+                let name = image.url.path_segments().and_then(|mut x| x.next_back());
+                self.store_asset(name.unwrap_or_default(), &data)
+            });
+            match stored {
+                Ok(Ok(rel_path)) => {
+                    markdown =
+                        markdown.replace(&image.dest_url, &format!("/note/{rel_path}"))
+                }
+                Ok(Err(e)) => error!("Failed to store \"{}\": {e}", image.url),
+                Err(e) => warn!("Not mirroring image: {e}"),
+            }
+        }
+        let body = format!(
+            "> Archived from <{url}> on `{date}`.\n\n{markdown}",
+            url = article.url,
+            date = chrono::Local::now().date_naive(),
+        );
+
+        let dir = self.config.archive_dir.clone();
+        let rel_path = match self.create_note(&dir, Some(article.title), &body) {
+            Ok(rel_path) => rel_path,
+            Err(e) => {
+                let message = "Failed to write archived article";
+                return self.server_error(message, &e);
+            }
+        };
+        info!("Archived \"{}\" as \"{rel_path}\"", article.url);
+        created(&rel_path)
+    }
+
+    /// Writes a new timestamped note into `dir` and reloads the index so it shows
+    /// up. Returns the note's path relative to the content directory.
+    fn create_note(
+        &mut self,
+        dir: &Path,
+        title: Option<String>,
+        body: &str,
+    ) -> io::Result<String> {
+        let now = chrono::Local::now().naive_local();
+        let mut name = now.format("%Y-%m-%dT%H-%M-%S").to_string();
+        if let Some(title) = &title {
+            let slug = anchor_id(&title.to_lowercase());
+            name.push('-');
+            name.push_str(slug.trim_matches('-'));
+        }
+        if dir.to_str().is_none() {
+            let message = "note directory is not UTF-8";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        let mut note = String::from("```meta\n");
+        if let Some(title) = title {
+            note.push_str(&format!("title = {}\n", toml::Value::String(title)));
+        }
+        note.push_str(&format!(
+            "date = \"{}\"\n```\n\n",
+            now.format("%Y-%m-%dT%H:%M:%S")
+        ));
+        note.push_str(body);
+
+        fs::create_dir_all(self.content_path.join(dir))?;
+        // Notes made within the same second are told apart by a number.
+        let mut n = 1;
+        let (rel_path, mut file) = loop {
+            let suffix = match n {
+                1 => String::new(),
+                n => format!("-{n}"),
+            };
+            let rel_path = dir.join(format!("{name}{suffix}.md"));
+            match fs::File::create_new(self.content_path.join(&rel_path)) {
+                Ok(file) => break (rel_path, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        file.write_all(note.as_bytes())?;
+        let rel_path_str = rel_path.to_str().expect("UTF-8 name").to_string();
+        if let Err(e) = self.reload(self.config.clone()) {
+            error!("Failed to reload state after writing \"{rel_path_str}\": {e}");
+        }
+        Ok(rel_path_str)
+    }
+
+    /// Writes `data` into the assets directory, named after its hash, and returns its
+    /// path relative to the content directory.
+    fn store_asset(&self, name: &str, data: &[u8]) -> io::Result<String> {
+        use sha2::{Digest, Sha256};
+
+        let ext = Path::new(name)
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_ascii_lowercase())
+            .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or_else(|| String::from("bin"));
+        let rel_path = self
+            .config
+            .assets_dir
+            .join(format!("{}.{ext}", hex(&Sha256::digest(data))));
+        let path = self.content_path.join(&rel_path);
+        if !path.exists() {
+            fs::create_dir_all(path.parent().expect("joined a file name"))?;
+            fs::write(&path, data)?;
+        }
+        rel_path.to_str().map(str::to_string).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "assets_dir is not UTF-8")
+        })
+    }
+
+    /// Handles requests on `workers` threads, which share the state. Requests that
+    /// change it wait for the others to finish. The threads stop when the server
+    /// is unblocked, which is only expected once `shutdown` is set.
+    fn serve(
+        state: Arc<RwLock<Self>>,
+        server: Arc<Server>,
+        workers: usize,
+        shutdown: Arc<AtomicBool>,
+        onion: bool,
+    ) -> Vec<std::thread::JoinHandle<()>> {
+        (0..workers.max(1))
+            .map(|_| {
+                let (state, server) = (Arc::clone(&state), Arc::clone(&server));
+                let shutdown = Arc::clone(&shutdown);
+                std::thread::spawn(move || {
+                    loop {
+                        let request = match server.recv() {
+                            Ok(rq) => rq,
+                            Err(e) => {
+                                if !shutdown.load(Ordering::Relaxed) {
+                                    error!("{e}");
+                                }
+                                break;
+                            }
+                        };
+                        let url = request.url().to_string();
+                        // A panic drops the request, which is answered with a
+                        // 500. The state may have been partly updated, but it's still
+                        // consistent enough to serve the next request.
+                        let handled =
+                            std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+                                || Self::handle(&state, request, onion),
+                            ));
+                        if handled.is_err() {
+                            error!("Panicked while handling \"{url}\"");
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Answers `request`, which came through the onion service if `onion`. The state
+    /// isn't locked while the response is sent, which takes as long as the client
+    /// does to read it.
+    fn handle(lock: &RwLock<Self>, mut request: Request, onion: bool) {
+        match Self::respond(lock, &mut request, onion) {
+            Some(response) => respond_or_log(request, response),
+            None => Self::listen(lock, request),
+        }
+    }
+
+    /// Lets `request` listen on `/events` for changes, unless too many clients
+    /// already are.
+    fn listen(lock: &RwLock<Self>, request: Request) {
+        let events =
+            Arc::clone(&lock.read().unwrap_or_else(PoisonError::into_inner).events);
+        let mut events = events.lock().unwrap();
+        if events.is_full() {
+            drop(events);
+            warn!("Too many clients listening for events");
+            let response =
+                Response::from_string("Too many clients listening").with_status_code(503);
+            respond_or_log(request, response);
+        } else {
+            let response = Response::empty(200)
+                .with_header(
+                    Header::from_bytes(b"Content-Type", b"text/event-stream").unwrap(),
+                )
+                .with_header(Header::from_bytes(b"Cache-Control", b"no-cache").unwrap());
+            events.listen(request.into_writer(response));
+        }
+    }
+
+    /// The response to `request`, or `None` when it's to listen on `/events`.
+    fn respond(
+        lock: &RwLock<Self>,
+        request: &mut Request,
+        onion: bool,
+    ) -> Option<ResponseBox> {
+        let mut timings = timing::Timings::start();
+        let state = lock.read().unwrap_or_else(PoisonError::into_inner);
+
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        // Tor connects from nearby, but passes on whatever headers clients send, so
+        // only the onion address they asked for is believed.
+        let client = match onion {
+            true => forwarded::Client {
+                addr:  None,
+                proto: String::from("http"),
+                host:  header(request, "Host").map(str::to_string),
+                onion: true,
+            },
+            false => forwarded::client(
+                request.remote_addr().map(|x| x.ip()),
+                request.secure(),
+                |name| header(request, name),
+                &state.config.trusted_proxies,
+            ),
+        };
+        debug!(
+            "{} {method} {url}",
+            client.addr.map(|x| x.to_string()).unwrap_or_default()
+        );
+        accesslog::begin(client.addr);
+        let encoder = compress::Encoder::new(
+            &state.config.compression,
+            header(request, "Accept-Encoding"),
+        );
+        let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+        // Matched before decoding, so the location stays a valid header value.
+        if let Some((redirect, to)) = redirects::find(&state.redirects, path) {
+            let response = Response::empty(redirect.status)
+                .with_header(Header::from_bytes(b"Location", to).unwrap());
+            return Some(response.boxed());
+        }
+        let Some(path) = uri::percent_decode(path) else {
+            return Some(Response::empty(400).boxed());
+        };
+        let path = match state.config.rewrites.apply(&path) {
+            Ok(path) => path,
+            Err(e) => {
+                let response = server_error(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    508,
+                    "Failed to route",
+                    &e,
+                );
+                return Some(response.boxed());
+            }
+        };
+        // Checked on the path as routed, which encoding or rewriting can't hide.
+        if !state.config.access.allows(client.addr, &path) {
+            let response = Response::from_string("Forbidden").with_status_code(403);
+            return Some(response.boxed());
+        }
+        // Clients without an address, those of the onion service, go unlimited, since
+        // they can't be told apart.
+        if let (Some(limit), Some(addr)) = (&state.config.rate_limit, client.addr) {
+            let checked = state
+                .limiter
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .check(limit, addr, std::time::Instant::now());
+            if let Err(wait) = checked {
+                let retry = wait.as_secs_f64().ceil().min(86400.0) as u64;
+                let response = Response::from_string("Too Many Requests")
+                    .with_status_code(429)
+                    .with_header(
+                        Header::from_bytes(b"Retry-After", retry.to_string()).unwrap(),
+                    );
+                return Some(response.boxed());
+            }
+        }
+        let query = uri::parse_query(query);
+        let param = |name: &str| {
+            query
+                .iter()
+                .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+        };
+
+        let cors = match path.starts_with("/api/") {
+            true => state
+                .config
+                .cors
+                .headers(header(request, "Origin"), method == Method::OPTIONS),
+            false => Vec::new(),
+        };
+
+        let owner = state.is_authorized(request);
+        if let Some(response) = state.hooks.request(method.as_str(), &path, &query, owner)
+        {
+            return Some(with_headers(response, &cors).boxed());
+        }
+
+        let response = match (path.as_str(), method.clone()) {
+            (_, Method::OPTIONS) if path.starts_with("/api/") => {
+                with_headers(Response::empty(204), &cors).boxed()
+            }
+            _ if path == "/dav" || path.starts_with(dav::PREFIX) => {
+                let method = method.as_str().to_string();
+                if !state.is_dav_authorized(request) {
+                    let challenge = r#"Basic realm="notes", charset="UTF-8""#;
+                    let response = Response::from_string("Unauthorized")
+                        .with_status_code(401)
+                        .with_header(
+                            Header::from_bytes(b"WWW-Authenticate", challenge).unwrap(),
+                        );
+                    return Some(response.boxed());
+                }
+                let Some(rel_path) = dav::rel_path(path.strip_prefix("/dav").unwrap())
+                else {
+                    return Some(Response::empty(404).boxed());
+                };
+                let response = match method.as_str() {
+                    "OPTIONS" => Response::from_string("")
+                        .with_header(Header::from_bytes(b"DAV", b"1").unwrap())
+                        .with_header(Header::from_bytes(b"Allow", dav::ALLOW).unwrap()),
+                    "PROPFIND" => state.propfind(&rel_path, header(request, "Depth")),
+                    "GET" | "HEAD" => state.dav_get(&rel_path),
+                    "PUT" => {
+                        drop(state);
+                        let mut state =
+                            lock.write().unwrap_or_else(PoisonError::into_inner);
+                        match state.reserve(request) {
+                            Some(full) => full,
+                            None => state.dav_put(&rel_path, request),
+                        }
+                    }
+                    "MKCOL" => state.dav_mkcol(&rel_path),
+                    _ => Response::from_string("")
+                        .with_status_code(405)
+                        .with_header(Header::from_bytes(b"Allow", dav::ALLOW).unwrap()),
+                };
+                response.boxed()
+            }
+            ("/", Method::GET) => {
+                let languages = header(request, "Accept-Language")
+                    .filter(|_| state.config.filter_languages)
+                    .map(accepted_languages)
+                    .unwrap_or_default();
+                let show_all = param("lang") == Some("all");
+                let owner = state.is_authorized(request);
+                let page = match param("page").map(str::parse) {
+                    Some(Ok(page)) => page,
+                    Some(Err(_)) => {
+                        let response =
+                            Response::from_string("Invalid page").with_status_code(400);
+                        return Some(response.boxed());
+                    }
+                    None => 1,
+                };
+                let response = match state.index_in(&languages, show_all, owner, page) {
+                    Some(page) => {
+                        page_response(request, encoder, page.into_owned(), Vec::new())
+                    }
+                    None => Response::from_string("No such page").with_status_code(404),
+                };
+                response.boxed()
+            }
+            (_, Method::GET) if path.starts_with("/index/") => {
+                let shard = path.strip_prefix("/index/").unwrap().to_lowercase();
+                let listed = state.hooks.index(&state.index);
+                let mut notes: Vec<_> = listed
+                    .iter()
+                    .copied()
+                    .filter(|doc| shards::shard(&doc.title) == shard)
+                    .collect();
+                if notes.is_empty() {
+                    return Some(state.not_found(&path, encoder).boxed());
+                }
+                notes.sort_by_cached_key(|doc| doc.title.to_lowercase());
+                let meta = Meta::inferred(
+                    format!("Index: {}", shard.to_uppercase()),
+                    NaiveDate::default(),
+                );
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &(letters_html(listed)
+                        + &generate_index_html(notes, state.config.stale_after, false)),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/api/upload", Method::POST) => {
+                if let Some(response) = state.refuse_write(request) {
+                    return Some(with_headers(response, &cors).boxed());
+                }
+                // A slow client mustn't hold the lock while it sends the body.
+                let limit = state.config.max_upload_size;
+                drop(state);
+                let response = match read_body(request, limit) {
+                    Ok(body) => lock
+                        .read()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .upload(request, &body),
+                    Err(response) => response,
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/capture", Method::POST) => {
+                if let Some(response) = state.refuse_write(request) {
+                    return Some(with_headers(response, &cors).boxed());
+                }
+                let limit = state.config.max_upload_size;
+                drop(state);
+                let title = header(request, "Title").map(str::to_string);
+                let response = match read_body(request, limit) {
+                    // Adding a note reloads the index.
+                    Ok(body) => lock
+                        .write()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .capture(title, body),
+                    Err(response) => response,
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/reload", Method::POST) => {
+                drop(state);
+                let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+                if !state.is_authorized(request) {
+                    return Some(with_headers(unauthorized(), &cors).boxed());
+                }
+                let mut config = load_config(config_path());
+                ARGS.apply(&mut config);
+                info!("Reloading state...");
+                let response = match state.reload(config) {
+                    Ok(()) => Response::from_string("").with_status_code(204),
+                    Err(e) => state.server_error("Failed to reload", &e),
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/preview", Method::POST) => {
+                let response = if state.is_authorized(request) {
+                    // Which note it is decides which file filter, if any, applies.
+                    let rel_path = param("path").unwrap_or("preview.md").to_string();
+                    state.preview(request, &rel_path, encoder)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/archive", Method::POST) => {
+                if let Some(response) = state.refuse_write(request) {
+                    return Some(with_headers(response, &cors).boxed());
+                }
+                // Fetching the article and its images can take minutes, which other
+                // requests mustn't wait on.
+                let limit = state.config.max_upload_size;
+                drop(state);
+                let response = match fetch_archive(request, limit) {
+                    Ok(article) => {
+                        let images = article.fetch_images(limit);
+                        // Adding a note reloads the index.
+                        lock.write()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .archive(article, images)
+                    }
+                    Err(response) => response,
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/search", Method::GET) => {
+                let q = param("q").unwrap_or_default();
+                let query = search::Query::parse(q);
+                let hits = state.find(&query);
+                let count = hits.len();
+                let mut page = format!(
+                    r#"<form action="/search"><input type="search" name="q" value="{}" autofocus> <button type="submit">Search</button></form>"#,
+                    escape_html(q)
+                );
+                if !q.trim().is_empty() {
+                    page.push_str(&format!("<p>{count} results</p>"));
+                    page.push_str(r#"<ol class="search-results">"#);
+                    for hit in hits {
+                        page.push_str(&format!(
+                            r#"<li> <time datetime="{time}+0:0">{time}</time> - <a href="/note/{path}">{title}</a><p class="snippet">{snippet}</p></li>"#,
+                            time = hit.doc.date, path = hit.doc.rel_path, title = escape_html(&hit.doc.title),
+                            snippet = state.search.snippet(&hit, &query)
+                        ));
+                    }
+                    page.push_str("</ol>");
+                }
+                state.log_search(request, q, count);
+                let meta = Meta::inferred(String::from("Search"), NaiveDate::default());
+                html_response(
+                    encoder,
+                    render_page(
+                        &state.config,
+                        &state.theme,
+                        &state.footer,
+                        &meta,
+                        &page,
+                        false,
+                    ),
+                )
+                .boxed()
+            }
+            ("/sitemap.xml", Method::GET) => {
+                // Sitemaps list absolute URLs.
+                let base = state.base(&client);
+                Response::from_string(sitemap_xml(&base, &state.index))
+                    .with_header(
+                        Header::from_bytes(b"Content-Type", b"application/xml").unwrap(),
+                    )
+                    .boxed()
+            }
+            ("/robots.txt", Method::GET) => {
+                // The sitemap's URL is absolute.
+                let txt = state.config.robots.robots_txt(&state.base(&client));
+                let response = Response::from_string(txt).with_header(
+                    Header::from_bytes(b"Content-Type", b"text/plain; charset=utf-8")
+                        .unwrap(),
+                );
+                response.boxed()
+            }
+            ("/humans.txt", Method::GET) => {
+                let response = match &state.config.robots.humans {
+                    Some(humans) => Response::from_string(humans.as_str()).with_header(
+                        Header::from_bytes(b"Content-Type", b"text/plain; charset=utf-8")
+                            .unwrap(),
+                    ),
+                    None => Response::from_string("").with_status_code(404),
+                };
+                response.boxed()
+            }
+            ("/favicon.ico", Method::GET) => {
+                let configured = state.config.favicon.as_ref().map(|favicon| {
+                    let file = favicon.to_str().and_then(|x| state.resolve_file(x));
+                    let file =
+                        file.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound));
+                    (file.and_then(fs::read), favicon)
+                });
+                let built_in = || (FAVICON.to_vec(), String::from("image/x-icon"));
+                let (data, mime) = match configured {
+                    Some((Ok(data), favicon)) => {
+                        let mime = mime_guess::from_path(favicon).first_or_octet_stream();
+                        (data, mime.to_string())
+                    }
+                    Some((Err(e), favicon)) => {
+                        error!("Failed to read favicon {favicon:?}: {e}");
+                        built_in()
+                    }
+                    None => built_in(),
+                };
+                let etag = etag(&data);
+                let fresh = header(request, "If-None-Match")
+                    .is_some_and(|x| etag_matches(x, &etag));
+                let response = match fresh {
+                    true => Response::from_data(Vec::new()).with_status_code(304),
+                    false => Response::from_data(data)
+                        .with_header(Header::from_bytes(b"Content-Type", mime).unwrap()),
+                };
+                let response = response
+                    .with_header(Header::from_bytes(b"ETag", etag).unwrap())
+                    .with_header(
+                        Header::from_bytes(b"Cache-Control", "public, max-age=604800")
+                            .unwrap(),
+                    );
+                response.boxed()
+            }
+            ("/opensearch.xml", Method::GET) => {
+                // Browsers want absolute URLs.
+                let base = state.base(&client);
+                Response::from_string(opensearch_xml(&base))
+                    .with_header(
+                        Header::from_bytes(
+                            b"Content-Type",
+                            b"application/opensearchdescription+xml",
+                        )
+                        .unwrap(),
+                    )
+                    .boxed()
+            }
+            (_, Method::GET) if path.starts_with("/theme/") => {
+                let asset = path.strip_prefix("/theme/").unwrap();
+                let response = match state.theme.asset(asset) {
+                    Some(data) => {
+                        let mime = mime_guess::from_path(asset).first_or_octet_stream();
+                        Response::from_data(data.to_vec()).with_header(
+                            Header::from_bytes(b"Content-Type", mime.to_string())
+                                .unwrap(),
+                        )
+                    }
+                    None => Response::from_data(Vec::new()).with_status_code(404),
+                };
+                response.boxed()
+            }
+            ("/api/search", Method::GET) => {
+                #[derive(Serialize)]
+                struct SearchResult<'a> {
+                    title:   &'a str,
+                    url:     String,
+                    date:    NaiveDate,
+                    score:   f32,
+                    /// HTML, with the matching words in `<mark>`.
+                    snippet: String,
+                }
+
+                let q = param("q").unwrap_or_default();
+                let query = search::Query::parse(q);
+                let results: Vec<_> = state
+                    .find(&query)
+                    .into_iter()
+                    .map(|hit| SearchResult {
+                        title:   &hit.doc.title,
+                        url:     format!("/note/{}", hit.doc.rel_path),
+                        date:    hit.doc.date,
+                        score:   hit.score,
+                        snippet: state.search.snippet(&hit, &query),
+                    })
+                    .collect();
+                let body = serde_json::to_vec(&results).unwrap();
+                let count = results.len();
+                state.log_search(request, q, count);
+                let response = Response::from_data(body).with_header(
+                    Header::from_bytes(b"Content-Type", b"application/json").unwrap(),
+                );
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/replicate", Method::GET) => {
+                let response = if state.is_authorized(request) {
+                    let since = param("since").and_then(|x| x.parse().ok()).unwrap_or(0);
+                    let store = state.config.data_path.join(replicate::STORE);
+                    match replicate::changes(&state.content_path, &store, since) {
+                        Ok(changes) => {
+                            let body = serde_json::to_vec(&changes).unwrap();
+                            Response::from_data(body).with_header(
+                                Header::from_bytes(b"Content-Type", b"application/json")
+                                    .unwrap(),
+                            )
+                        }
+                        Err(e) => state.server_error("Failed to list changes", &e),
+                    }
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/api/context", Method::GET) => {
+                let response = if state.is_authorized(request) {
+                    let q = param("q").unwrap_or_default();
+                    let k = param("k").and_then(|x| x.parse().ok()).unwrap_or(5);
+                    let body = serde_json::to_vec(&state.context(q, k)).unwrap();
+                    Response::from_data(body).with_header(
+                        Header::from_bytes(b"Content-Type", b"application/json").unwrap(),
+                    )
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            #[cfg(feature = "graphql")]
+            ("/api/graphql", Method::GET | Method::POST) => {
+                let graphql_request = if method == Method::POST {
+                    let body = request.as_reader().take(state.config.max_upload_size);
+                    serde_json::from_reader(body)
+                } else {
+                    param("variables")
+                        .map(serde_json::from_str)
+                        .transpose()
+                        .map(|variables| {
+                            juniper::http::GraphQLRequest::new(
+                                param("query").unwrap_or_default().to_string(),
+                                param("operationName").map(str::to_string),
+                                variables,
+                            )
+                        })
+                };
+                let response = match graphql_request {
+                    Ok(graphql_request) => {
+                        let result =
+                            graphql_request.execute_sync(&graphql::schema(), &state);
+                        let status = if result.is_ok() { 200 } else { 400 };
+                        Response::from_data(serde_json::to_vec(&result).unwrap())
+                            .with_status_code(status)
+                            .with_header(
+                                Header::from_bytes(b"Content-Type", b"application/json")
+                                    .unwrap(),
+                            )
+                    }
+                    Err(e) => {
+                        Response::from_string(format!("Invalid GraphQL request: {e}"))
+                            .with_status_code(400)
+                    }
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/stats", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let meta = Meta::inferred(String::from("Stats"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &pages::stats(
+                        &state.store.lock().unwrap(),
+                        *state.usage.lock().unwrap(),
+                        state.config.quota,
+                    ),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/health" | "/healthz", Method::GET) => Response::from_string("ok").boxed(),
+            // Requests are only handled once the state has loaded.
+            ("/readyz", Method::GET) => Response::from_string("ready").boxed(),
+            ("/status", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let meta = Meta::inferred(String::from("Status"), NaiveDate::default());
+                let checksums = state.store.lock().unwrap().checksums.clone();
+                let integrity = integrity::verify(&checksums, &state.content_path);
+                let body =
+                    asset_report_html(&state.assets) + &integrity_report_html(integrity);
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &body,
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/queue", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let meta =
+                    Meta::inferred(String::from("Read later"), NaiveDate::default());
+                let queue =
+                    pages::queue(&state.index, &state.store.lock().unwrap().queue);
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &queue,
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/cards", Method::GET) => {
+                let owner = state.is_authorized(request);
+                let meta =
+                    Meta::inferred(String::from("Flashcards"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &cards::html(&state.index, owner),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/cards.tsv", Method::GET) => {
+                let response = Response::from_string(cards::listed_tsv(&state.index))
+                    .with_header(
+                        Header::from_bytes(
+                            b"Content-Type",
+                            b"text/tab-separated-values; charset=utf-8",
+                        )
+                        .unwrap(),
+                    )
+                    .with_header(
+                        Header::from_bytes(
+                            b"Content-Disposition",
+                            b"attachment; filename=\"cards.tsv\"",
+                        )
+                        .unwrap(),
+                    );
+                response.boxed()
+            }
+            ("/cards/review", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let due = state.due_cards(chrono::Local::now().date_naive());
+                let meta =
+                    Meta::inferred(String::from("Review cards"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &cards::review_html(&due),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/api/cards/review", Method::POST) => {
+                let response = if state.is_authorized(request) {
+                    state.review_card(request)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            ("/review", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let meta = Meta::inferred(String::from("Review"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &pages::review(&state.index),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/styleguide", Method::GET)
+                if cfg!(debug_assertions) || state.config.styleguide =>
+            {
+                let inferred =
+                    Meta::inferred(String::from("Style guide"), NaiveDate::default());
+                let (html, meta) = render_markdown(
+                    styleguide::FIXTURE,
+                    inferred,
+                    &state.filters,
+                    &state.links,
+                );
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &html,
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/metrics", Method::GET) => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                Response::from_string(state.metrics())
+                    .with_header(
+                        Header::from_bytes(b"Content-Type", b"text/plain; version=0.0.4")
+                            .unwrap(),
+                    )
+                    .boxed()
+            }
+            ("/todos", Method::GET) => {
+                let meta = Meta::inferred(String::from("Todos"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &todos_html(&state.index),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            // Anyone may listen, so there's nothing to listen to unless live reload
+            // is on.
+            ("/events", Method::GET) if !state.config.live_reload => {
+                Response::empty(404).boxed()
+            }
+            ("/events", Method::GET) => return None,
+            ("/feed.xml" | "/atom.xml", Method::GET) => {
+                let format = feed::Format::from_file(&path[1..]).expect("routed by file");
+                let base = state.base(&client);
+                let author = state.author();
+                let license = state.config.license.as_deref();
+                let feed =
+                    feed(&base, "Notes", &author, "", format, license, &state.index);
+                let archive = param("archive");
+                let response = feed_page(request, &state.config, &feed, format, archive);
+                response.boxed()
+            }
+            ("/calendar.ics", Method::GET) => {
+                let listed = state.index.iter().filter(|doc| !doc.unlisted);
+                let events = listed.flat_map(|doc| {
+                    doc.events
+                        .iter()
+                        .map(|event| (doc.rel_path.as_str(), event))
+                });
+                let modified = state.modified();
+                let ics = calendar::ics(events, modified.into());
+                let response = feed_response(
+                    request,
+                    ics.into_bytes(),
+                    "text/calendar; charset=utf-8",
+                    modified,
+                    state.config.feed_max_age,
+                );
+                response.boxed()
+            }
+            ("/about", Method::GET) => {
+                let Some((mut profile, mut meta, body)) = state.profile() else {
+                    return Some(state.not_found(&path, encoder).boxed());
+                };
+                for identity in &state.config.identities {
+                    if !profile.links.contains(identity) {
+                        profile.links.push(identity.clone());
+                    }
+                }
+                let page = profile.h_card(meta.desc.as_deref()) + &body;
+                meta.title = profile.name;
+                html_response(
+                    encoder,
+                    render_page(
+                        &state.config,
+                        &state.theme,
+                        &state.footer,
+                        &meta,
+                        &page,
+                        false,
+                    ),
+                )
+                .boxed()
+            }
+            ("/vcard.vcf", Method::GET) => {
+                let Some((profile, meta, _)) = state.profile() else {
+                    return Some(Response::empty(404).boxed());
+                };
+                Response::from_string(profile.vcard(meta.desc.as_deref()))
+                    .with_header(
+                        Header::from_bytes(b"Content-Type", b"text/vcard; charset=utf-8")
+                            .unwrap(),
+                    )
+                    .boxed()
+            }
+            ("/login", Method::GET) => {
+                let meta = Meta::inferred(String::from("Login"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    LOGIN_FORM,
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            ("/login", Method::POST) => {
+                let response = state.login(request);
+                response.boxed()
+            }
+            (_, Method::GET) if path.starts_with("/view/") => {
+                let name = path.strip_prefix("/view/").unwrap();
+                let feed_file = name.rsplit_once('/').and_then(|(name, file)| {
+                    Some((name, feed::Format::from_file(file)?))
+                });
+                if let Some((name, format)) = feed_file {
+                    let Some(notes) = state.view(name) else {
+                        return Some(Response::empty(404).boxed());
+                    };
+                    let base = state.base(&client);
+                    let author = state.author();
+                    let license = state.config.license.as_deref();
+                    let path = format!("/view/{name}");
+                    let feed = feed(&base, name, &author, &path, format, license, notes);
+                    let archive = param("archive");
+                    let response =
+                        feed_page(request, &state.config, &feed, format, archive);
+                    return Some(response.boxed());
+                }
+                let Some(notes) = state.view(name) else {
+                    return Some(state.not_found(&path, encoder).boxed());
+                };
+                let meta = Meta::inferred(name.to_string(), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &generate_index_html(notes, state.config.stale_after, false),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            (_, Method::GET) if path.starts_with("/tag/") => {
+                let tag = path.strip_prefix("/tag/").unwrap();
+                let notes: Vec<_> = state
+                    .index
+                    .iter()
+                    .filter(|doc| !doc.unlisted)
+                    .filter(|doc| doc.tags.iter().any(|x| x.eq_ignore_ascii_case(tag)))
+                    .collect();
+                if notes.is_empty() {
+                    return Some(state.not_found(&path, encoder).boxed());
+                }
+                let meta = Meta::inferred(format!("#{tag}"), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &generate_index_html(notes, state.config.stale_after, false),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            (_, Method::GET) if path.starts_with("/board/") => {
+                let tag = path.strip_prefix("/board/").unwrap();
+                let owner = state.is_authorized(request);
+                let meta = Meta::inferred(tag.to_string(), NaiveDate::default());
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &pages::board(&state.index, tag, owner),
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            (_, Method::POST) if path.starts_with("/api/status/") => {
+                let rel_path = path.strip_prefix("/api/status/").unwrap();
+                drop(state);
+                let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+                let response = if state.is_authorized(request) {
+                    state.set_status(rel_path, request)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::POST) if path.starts_with("/api/review/") => {
+                let rel_path = path.strip_prefix("/api/review/").unwrap();
+                drop(state);
+                let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+                let response = if state.is_authorized(request) {
+                    state.mark_reviewed(rel_path)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::POST) if path.starts_with("/api/queue/") => {
+                let rel_path = path.strip_prefix("/api/queue/").unwrap();
+                let response = if state.is_authorized(request) {
+                    state.queue(rel_path, request)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::POST) if path.starts_with("/api/annotate/") => {
+                let rel_path = path.strip_prefix("/api/annotate/").unwrap();
+                let response = if state.is_authorized(request) {
+                    state.annotate(rel_path, request)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::GET)
+                if path.starts_with("/export/") && path.ends_with(".zip") =>
+            {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let rel_path = &path["/export/".len()..path.len() - ".zip".len()];
+                if !state.index.iter().any(|doc| doc.rel_path == rel_path) {
+                    return Some(Response::empty(404).boxed());
+                }
+                let depth = param("depth")
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(state.config.export_depth);
+                let base = state.base(&client);
+                let response = match state.bundle(rel_path, depth, &base) {
+                    Ok(zip) => {
+                        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+                        let disposition = format!(
+                            "attachment; filename=\"{}.zip\"",
+                            name.replace(
+                                |c: char| !c.is_ascii() || c == '"' || c == '\\',
+                                "_"
+                            )
+                        );
+                        Response::from_data(zip)
+                            .with_header(
+                                Header::from_bytes(b"Content-Type", b"application/zip")
+                                    .unwrap(),
+                            )
+                            .with_header(
+                                Header::from_bytes(b"Content-Disposition", disposition)
+                                    .unwrap(),
+                            )
+                    }
+                    Err(e) => {
+                        let message = format!("Failed to export \"{rel_path}\"");
+                        state.server_error(&message, &e)
+                    }
+                };
+                response.boxed()
+            }
+            (_, Method::GET)
+                if path.starts_with("/api/note/") && path.contains("/section/") =>
+            {
+                let path = path.strip_prefix("/api/note/").unwrap();
+                let Some((rel_path, n)) = path.rsplit_once("/section/") else {
+                    return Some(Response::empty(404).boxed());
+                };
+                let Ok(n) = n.parse::<usize>() else {
+                    let response = Response::from_string("Invalid section");
+                    return Some(response.with_status_code(400).boxed());
+                };
+                let Some(doc) = state.index.iter().find(|doc| doc.rel_path == rel_path)
+                else {
+                    let response = Response::from_string("No such note");
+                    return Some(response.with_status_code(404).boxed());
+                };
+                let member = state.is_member(request, param("share"));
+                let md = match state.visible_markdown(doc, member) {
+                    Ok(md) => md,
+                    Err(e) => {
+                        let message = format!("Failed to read \"{rel_path}\"");
+                        let response = state.server_error(&message, &e);
+                        return Some(response.boxed());
+                    }
+                };
+                let Some(section) = outline::sections(&md).into_iter().nth(n) else {
+                    let response = Response::from_string("No such section");
+                    return Some(response.with_status_code(404).boxed());
+                };
+                let inferred = Meta::inferred(doc.title.clone(), doc.created);
+                let (html, _) =
+                    render_markdown(section.body, inferred, &state.filters, &state.links);
+                with_headers(html_response(encoder, html), &cors).boxed()
+            }
+            (_, Method::GET) if path.starts_with("/outline/") => {
+                let rel_path = path.strip_prefix("/outline/").unwrap();
+                let Some(doc) = state.index.iter().find(|doc| doc.rel_path == rel_path)
+                else {
+                    return Some(state.not_found(&path, encoder).boxed());
+                };
+                let share = param("share");
+                let member = state.is_member(request, share);
+                let md = match state.visible_markdown(doc, member) {
+                    Ok(md) => md,
+                    Err(e) => {
+                        let message = format!("Failed to read \"{rel_path}\"");
+                        let response = state.server_error(&message, &e);
+                        return Some(response.boxed());
+                    }
+                };
+                // Sections are fetched with the same access to the members-only part.
+                let query = match share.filter(|_| member) {
+                    Some(share) => format!("?share={}", uri::percent_encode(share)),
+                    None => String::new(),
+                };
+                let meta = Meta::inferred(
+                    format!("Outline: {}", doc.title),
+                    NaiveDate::default(),
+                );
+                let outline = outline::html(rel_path, &outline::sections(&md), &query);
+                let page = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &outline,
+                    false,
+                );
+                html_response(encoder, page).boxed()
+            }
+            (_, Method::GET)
+                if path.starts_with("/api/note/") && path.ends_with("/meta") =>
+            {
+                #[derive(Serialize)]
+                struct NoteMeta<'a> {
+                    title: &'a str,
+                    date:  NaiveDate,
+                    lang:  Option<&'a str>,
+                    desc:  Option<&'a str>,
+                }
+
+                let rel_path = path
+                    .strip_prefix("/api/note/")
+                    .and_then(|x| x.strip_suffix("/meta"))
+                    .unwrap_or_default();
+                // Read from the index, which has them already, instead of the note.
+                let response = match state.index.iter().find(|x| x.rel_path == rel_path) {
+                    Some(doc) => {
+                        let meta = NoteMeta {
+                            title: &doc.title,
+                            date:  doc.created,
+                            lang:  doc.lang.as_deref(),
+                            desc:  doc.desc.as_deref(),
+                        };
+                        Response::from_data(serde_json::to_vec(&meta).unwrap())
+                            .with_header(
+                                Header::from_bytes(b"Content-Type", b"application/json")
+                                    .unwrap(),
+                            )
+                    }
+                    None => Response::from_string("No such note").with_status_code(404),
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::GET) if path.starts_with("/api/export/") => {
+                let rel_path = path.strip_prefix("/api/export/").unwrap();
+                let response = if state.is_authorized(request) {
+                    state.export(rel_path)
+                } else {
+                    unauthorized()
+                };
+                with_headers(response, &cors).boxed()
+            }
+            (_, Method::GET) if path.starts_with("/edit/") => {
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                // Edited files have to be in the content directory, like WebDAV's.
+                let rel_path = dav::rel_path(path.strip_prefix("/edit/").unwrap());
+                let response = match rel_path.filter(|x| !x.is_empty()) {
+                    Some(rel_path) => state.editor(&rel_path),
+                    None => Response::from_string("").with_status_code(404),
+                };
+                response.boxed()
+            }
+            (_, Method::POST) if path.starts_with("/edit/") => {
+                drop(state);
+                let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+                if !state.is_authorized(request) {
+                    return Some(unauthorized().boxed());
+                }
+                let rel_path = dav::rel_path(path.strip_prefix("/edit/").unwrap());
+                if let Some(full) = state.reserve(request) {
+                    return Some(full.boxed());
+                }
+                let response = match rel_path.filter(|x| !x.is_empty()) {
+                    Some(rel_path) => state.save_edit(&rel_path, request),
+                    None => Response::from_string("").with_status_code(404),
+                };
+                response.boxed()
+            }
+            (_, Method::DELETE) if path.starts_with("/note/") => {
+                let rel_path = path.strip_prefix("/note/").unwrap();
+                drop(state);
+                let mut state = lock.write().unwrap_or_else(PoisonError::into_inner);
+                let response = if state.is_authorized(request) {
+                    state.delete(rel_path, param("confirm"))
+                } else {
+                    unauthorized()
+                };
+                response.boxed()
+            }
+            _ if path.starts_with("/note/") => {
+                timings.stage("routing");
+                let path = path.strip_prefix("/note/").unwrap();
+                let Some(entry) = state
+                    .index
+                    .iter()
+                    .find(|entry| entry.rel_path == path)
+                    .cloned()
+                else {
+                    // Notes scheduled for later aren't there yet.
+                    let file_path = state
+                        .resolve_file(path)
+                        .filter(|_| !state.scheduled.contains_key(path));
+                    let Some(file_path) = file_path else {
+                        let path = format!("/note/{path}");
+                        return Some(state.not_found(&path, encoder).boxed());
+                    };
+                    let mime = mime_guess::from_path(path).first_or_octet_stream();
+                    let content_type =
+                        Header::from_bytes(b"Content-Type", mime.to_string()).unwrap();
+                    if mime.type_() == mime_guess::mime::IMAGE
+                        && state.should_strip_exif(path)
+                    {
+                        let response = match fs::read(&file_path) {
+                            Ok(data) => Response::from_data(exif::strip(data))
+                                .with_header(content_type)
+                                .boxed(),
+                            Err(e) => {
+                                let message = format!("Failed to read \"{file_path:?}\"");
+                                let response = state.server_error(&message, &e);
+                                response.boxed()
+                            }
+                        };
+                        return Some(response);
+                    }
+                    let response = match fs::File::open(&file_path) {
+                        Ok(file) => {
+                            Response::from_file(file).with_header(content_type).boxed()
+                        }
+                        Err(e) => {
+                            let message = format!("Failed to open \"{file_path:?}\"");
+                            let response = state.server_error(&message, &e);
+                            response.boxed()
+                        }
+                    };
+                    return Some(response);
+                };
+                let data_path = state.content_path.join(entry.rel_path.as_str());
+                let owner = state.is_authorized(request);
+                let member = state.is_member(request, param("share"));
+                let find = param("find").filter(|x| !x.trim().is_empty());
+                // Galleries list their directory, which can change without the
+                // note changing, the owner sees annotations, and finding marks what
+                // it finds. Only what everyone sees is cached.
+                let modified = fs::metadata(&data_path)
+                    .and_then(|x| x.modified())
+                    .ok()
+                    .filter(|_| {
+                        !member && find.is_none() && entry.kind != NoteKind::Gallery
+                    });
+                let cached = modified.and_then(|modified| {
+                    state
+                        .pages
+                        .lock()
+                        .unwrap()
+                        .get(&entry.rel_path)
+                        .filter(|(at, ..)| *at == modified)
+                        .map(|(_, page, headers)| (page.clone(), headers.clone()))
+                });
+                if let Some((page, headers)) = cached {
+                    let response = page_response(request, encoder, page, headers);
+                    return Some(response.boxed());
+                }
+                let data = match fs::read_to_string(&data_path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        let message = format!("Failed to read \"{}\"", entry.rel_path);
+                        let response = state.server_error(&message, &e);
+                        return Some(response.boxed());
+                    }
+                };
+                timings.stage("read");
+                let timeout = Duration::from_millis(state.config.render_timeout);
+                let inferred = Meta::inferred(entry.title.clone(), entry.created);
+                let filters = Arc::clone(&state.filters);
+                let links = Arc::clone(&state.links);
+                let (markdown, mut meta) = match render_markdown_within(
+                    &entry.rel_path,
+                    data,
+                    inferred,
+                    filters,
+                    links,
+                    timeout,
+                ) {
+                    Ok(rendered) => rendered,
+                    Err(e) => {
+                        let status = match e {
+                            RecvTimeoutError::Timeout => 503,
+                            RecvTimeoutError::Disconnected => 500,
+                        };
+                        let message = format!("Failed to render \"{}\"", entry.rel_path);
+                        let response = server_error(
+                            &state.config,
+                            &state.theme,
+                            &state.footer,
+                            status,
+                            &message,
+                            &e,
+                        );
+                        return Some(response.boxed());
+                    }
+                };
+                timings.stage("parse");
+                timings.split("parse", "highlight");
+                timings.split("parse", "filter");
+                let markdown = match member {
+                    true => markdown,
+                    false => members::gate(markdown),
+                };
+                let markdown = state.plugins.transform(&entry.rel_path, markdown);
+                timings.stage("plugins");
+                let markdown = state.hooks.render(&entry, markdown);
+                timings.stage("hooks");
+                // The kind may come from the note's location rather than its meta,
+                // and the description from the summary made when indexing.
+                meta.kind = entry.kind;
+                meta.desc = meta.desc.or(entry.desc);
+                let markdown = match meta.kind {
+                    NoteKind::Gallery => {
+                        let dir = data_path.parent().unwrap_or(&state.content_path);
+                        match gallery_html(&state.content_path, dir) {
+                            Ok(gallery) => markdown + &gallery,
+                            Err(e) => {
+                                error!("Failed to list gallery \"{dir:?}\": {e}");
+                                markdown
+                            }
+                        }
+                    }
+                    _ => markdown,
+                };
+                let markdown =
+                    match state.store.lock().unwrap().annotations.get(&entry.rel_path) {
+                        Some(annotations) if owner => {
+                            annotate_html(&markdown, annotations)
+                        }
+                        _ => markdown,
+                    };
+                let (markdown, found) = match find {
+                    Some(term) => find::highlight(&markdown, term),
+                    None => (markdown, 0),
+                };
+                let markdown = match &meta.warning {
+                    Some(warning) => content_warning_html(warning, &markdown),
+                    None => markdown,
+                };
+                let today = chrono::Local::now().date_naive();
+                let markdown = match state
+                    .config
+                    .stale_after
+                    .filter(|age| age.exceeded(entry.created, today))
+                {
+                    Some(age) => stale_html(age) + &markdown,
+                    None => markdown,
+                };
+                let share = param("share").filter(|_| member);
+                let markdown = find_form(find, found, share) + &markdown;
+                let markdown =
+                    match meta.license.as_ref().or(state.config.license.as_ref()) {
+                        Some(license) => markdown + &license::html(license, &meta.title),
+                        None => markdown,
+                    };
+                let markdown = match owner {
+                    true => {
+                        markdown
+                            + &format!(
+                                r#"<p class="edit"><a href="/edit/{}">Edit</a></p>"#,
+                                entry.rel_path
+                            )
+                    }
+                    false => markdown,
+                };
+                let document = render_page(
+                    &state.config,
+                    &state.theme,
+                    &state.footer,
+                    &meta,
+                    &markdown,
+                    owner,
+                );
+                timings.stage("template");
+                let headers = meta.headers();
+                if let Some(modified) = modified {
+                    let page = (modified, document.clone(), headers.clone());
+                    state
+                        .pages
+                        .lock()
+                        .unwrap()
+                        .insert(entry.rel_path.clone(), page);
+                }
+                let mut response = page_response(request, encoder, document, headers);
+                // Only the owner gets to see how long things take.
+                if owner && param("__timing") == Some("1") {
+                    response = response.with_header(
+                        Header::from_bytes(b"Server-Timing", timings.header()).unwrap(),
+                    );
+                }
+                response.boxed()
+            }
+            // Pages people visit get a page saying so, the rest nothing.
+            (_, Method::GET) if !path.starts_with("/api/") => {
+                state.not_found(&path, encoder).boxed()
+            }
+            _ => Response::empty(404).boxed(),
+        };
+        Some(response)
+    }
+}
+
+/// The primary language subtags accepted by an `Accept-Language` header, or none
+/// when it accepts any language.
+fn accepted_languages(header: &str) -> Vec<String> {
+    let mut languages = Vec::new();
+    for range in header.split(',') {
+        let mut params = range.split(';');
+        let tag = params.next().unwrap_or_default().trim();
+        let rejected = params.any(|x| {
+            x.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+        if tag == "*" && !rejected {
+            return Vec::new();
+        }
+        let primary = tag
+            .split('-')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !primary.is_empty() && !rejected && !languages.contains(&primary) {
+            languages.push(primary);
+        }
+    }
+    languages
+}
+
+/// A sitemap of the index and every note, for search engines.
+fn sitemap_xml(base: &str, index: &Index) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+    );
+    xml.push_str(&format!("<url><loc>{}/</loc></url>\n", escape_html(base)));
+    for doc in index.iter().filter(|doc| !doc.unlisted) {
+        let path: Vec<_> = doc.rel_path.split('/').map(uri::percent_encode).collect();
+        let modified = DateTime::<chrono::Utc>::from(doc.modified);
+        xml.push_str(&format!(
+            "<url><loc>{}/note/{}</loc><lastmod>{}</lastmod></url>\n",
+            escape_html(base),
+            path.join("/"),
+            modified.format("%Y-%m-%d"),
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// An OpenSearch description, letting browsers search the site from the address
+/// bar.
+fn opensearch_xml(base: &str) -> String {
+    let base = escape_html(base);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+<ShortName>Notes</ShortName>
+<Description>Search the notes</Description>
+<InputEncoding>UTF-8</InputEncoding>
+<Url type="text/html" method="get" template="{base}/search?q={{searchTerms}}"/>
+<Url type="application/json" method="get" template="{base}/api/search?q={{searchTerms}}"/>
+<Url type="application/opensearchdescription+xml" rel="self" template="{base}/opensearch.xml"/>
+</OpenSearchDescription>
+"#
+    )
+}
+
+/// Name of the cookie holding the API token in the owner's browser.
+const TOKEN_COOKIE: &str = "notes_token";
+
+const LOGIN_FORM: &str = r#"<form method="post" action="/login">
+<input type="password" name="token" placeholder="API token" autofocus>
+<button type="submit">Log in</button>
+</form>"#;
+
+/// A file or directory at `rel_path` in a WebDAV listing.
+fn dav_resource(rel_path: String, metadata: &fs::Metadata) -> dav::Resource {
+    dav::Resource {
+        rel_path,
+        collection: metadata.is_dir(),
+        len: metadata.len(),
+        modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+    }
+}
+
+/// `html` in a `<details>` showing only `warning` until it's opened.
+fn content_warning_html(warning: &str, html: &str) -> String {
+    format!(
+        r#"<details class="content-warning"><summary>{}</summary>{html}</details>"#,
+        escape_html(warning)
+    )
+}
+
+/// A folded form finding words in the note, passing `share` along. Once it's been
+/// used to `find` something, it's open and links to the first of what was `found`.
+fn find_form(find: Option<&str>, found: usize, share: Option<&str>) -> String {
+    let share = share
+        .map(|x| {
+            format!(
+                r#"<input type="hidden" name="share" value="{}">"#,
+                escape_html(x)
+            )
+        })
+        .unwrap_or_default();
+    let (open, term, result) = match find {
+        Some(term) => {
+            let result = match found {
+                0 => String::from("<p>No matches.</p>"),
+                1 => String::from(r##"<p><a href="#find-0">1 match</a></p>"##),
+                n => format!(r##"<p><a href="#find-0">{n} matches</a></p>"##),
+            };
+            (" open", escape_html(term), result)
+        }
+        None => ("", String::new(), String::new()),
+    };
+    format!(
+        r#"<details class="find"{open}><summary>Find in this note</summary><form method="get">{share}<input type="search" name="find" value="{term}"> <button>Find</button></form>{result}</details>"#
+    )
+}
+
+/// A banner the reader can dismiss, saying the note is over `age` old.
+fn stale_html(age: age::Age) -> String {
+    format!(
+        r#"<div class="stale" role="note"><p>This note is over {age} old.</p><button type="button" onclick="this.parentElement.remove()">Dismiss</button></div>"#
+    )
+}
+
+/// Marks the first occurrence of each annotation's quote in rendered `html`, and
+/// lists the annotations after it.
+fn annotate_html(html: &str, annotations: &[store::Annotation]) -> String {
+    let mut html = html.to_string();
+    let mut notes = String::from(r#"<aside class="annotations"><ol>"#);
+    for (i, annotation) in annotations.iter().enumerate() {
+        let quote = escape_html(&annotation.quote);
+        let comment = annotation.comment.as_deref().map(escape_html);
+        // The markdown renderer leaves quotes in text as they are.
+        let rendered = quote.replace("&quot;", "\"").replace("&#39;", "'");
+        // Skip matches inside of tags, such as in attribute values.
+        let found = html.match_indices(&rendered).map(|(at, _)| at).find(|&at| {
+            let before = &html[..at];
+            before.rfind('<') <= before.rfind('>')
+        });
+        if let Some(at) = found {
+            html.insert_str(at + rendered.len(), "</mark>");
+            html.insert_str(
+                at,
+                &format!(
+                    r#"<mark class="highlight" id="hl-{i}" title="{}">"#,
+                    comment.as_deref().unwrap_or_default()
+                ),
+            );
+        }
+        notes.push_str(&format!(r##"<li><a href="#hl-{i}">{quote}</a>"##));
+        if let Some(comment) = comment {
+            notes.push_str(&format!("<p>{comment}</p>"));
+        }
+        notes.push_str("</li>");
+    }
+    notes.push_str("</ol></aside>");
+    html + &notes
+}
+
+/// Responds with the URL of a newly written note.
+fn created(rel_path: &str) -> Response<io::Cursor<Vec<u8>>> {
+    let url = format!("/note/{rel_path}");
+    Response::from_string(format!("{url}\n"))
+        .with_status_code(201)
+        .with_header(Header::from_bytes(b"Location", url).unwrap())
+}
+
+/// Logs `error` and its causes under a new incident ID, and responds with a page
+/// showing the ID, so that whoever ran into the problem can report it and the
+/// owner can find it in the log.
+fn server_error(
+    config: &Config,
+    theme: &theme::Theme,
+    footer: &str,
+    status: u16,
+    message: &str,
+    error: &dyn std::error::Error,
+) -> Response<io::Cursor<Vec<u8>>> {
+    use std::hash::{BuildHasher, RandomState};
+
+    let incident = format!(
+        "{:08x}",
+        RandomState::new().hash_one(SystemTime::now()) as u32
+    );
+    let mut chain = error.to_string();
+    for source in std::iter::successors(error.source(), |x| x.source()) {
+        chain.push_str(&format!(": {source}"));
+    }
+    error!("[incident {incident}] {message}: {chain}");
+
+    let title = match status {
+        503 => "Unavailable",
+        _ => "Something went wrong",
+    };
+    let meta = Meta::inferred(String::from(title), NaiveDate::default());
+    let body = format!(
+        "<p>This page couldn't be shown. If it keeps happening, mention incident \
+         <code>{incident}</code> when reporting it.</p>"
+    );
+    Response::from_string(render_page(config, theme, footer, &meta, &body, false))
+        .with_status_code(status)
+        .with_header(Header::from_bytes(b"Content-Type", b"text/html").unwrap())
+}
+
+fn unauthorized() -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string("Unauthorized")
+        .with_status_code(401)
+        .with_header(Header::from_bytes(b"WWW-Authenticate", b"Bearer").unwrap())
+}
+
+/// A strong entity tag for a response with `body`.
+fn etag(body: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    format!("\"{}\"", &hex(&Sha256::digest(body))[..32])
+}
+
+/// Whether an `If-None-Match` header's `tags` include `etag`.
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(|x| x.trim().trim_start_matches("W/"))
+        .any(|x| x == etag || x == "*")
+}
+
+/// An HTML response, compressed when the client accepts that and it's worth it.
+fn html_response(
+    encoder: compress::Encoder,
+    html: String,
+) -> Response<io::Cursor<Vec<u8>>> {
+    let (body, encoding) = encoder.encode(html.into_bytes());
+    let mut response = Response::from_data(body)
+        .with_header(Header::from_bytes(b"Content-Type", b"text/html").unwrap())
+        .with_header(Header::from_bytes(b"Vary", b"Accept-Encoding").unwrap());
+    if let Some(encoding) = encoding {
+        response.add_header(
+            Header::from_bytes(b"Content-Encoding", encoding.name()).unwrap(),
+        );
+    }
+    response
+}
+
+/// Responds with a rendered page, or with 304 when the client already has it.
+fn page_response(
+    request: &Request,
+    encoder: compress::Encoder,
+    page: String,
+    headers: Vec<Header>,
+) -> Response<io::Cursor<Vec<u8>>> {
+    let mut etag = etag(page.as_bytes());
+    // Each encoding of the page is a representation with a tag of its own.
+    if let Some(encoding) = encoder.encoding(page.len()) {
+        etag = format!("{}-{}\"", etag.trim_end_matches('"'), encoding.name());
+    }
+    let fresh = header(request, "If-None-Match").is_some_and(|x| etag_matches(x, &etag));
+    let mut response = if fresh {
+        Response::from_data(Vec::new())
+            .with_status_code(304)
+            .with_header(Header::from_bytes(b"Vary", b"Accept-Encoding").unwrap())
+    } else {
+        html_response(encoder, page)
+    };
+    for header in headers {
+        response.add_header(header);
+    }
+    response.with_header(Header::from_bytes(b"ETag", etag).unwrap())
+}
+
+/// Responds with a feed that clients poll, letting them skip downloading it again
+/// when it hasn't changed since they last did.
+fn feed_response(
+    request: &Request,
+    body: Vec<u8>,
+    content_type: &str,
+    modified: SystemTime,
+    max_age: u64,
+) -> Response<io::Cursor<Vec<u8>>> {
+    let etag = etag(&body);
+    let modified = DateTime::<chrono::Utc>::from(modified);
+    let last_modified = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    // If-None-Match takes precedence when both are sent.
+    let fresh = match header(request, "If-None-Match") {
+        Some(tags) => etag_matches(tags, &etag),
+        None => header(request, "If-Modified-Since")
+            .and_then(|x| DateTime::parse_from_rfc2822(x).ok())
+            .is_some_and(|since| modified.timestamp() <= since.timestamp()),
+    };
+
+    let response = if fresh {
+        Response::from_data(Vec::new()).with_status_code(304)
+    } else {
+        Response::from_data(body)
+            .with_header(Header::from_bytes(b"Content-Type", content_type).unwrap())
+    };
+    response
+        .with_header(Header::from_bytes(b"ETag", etag).unwrap())
+        .with_header(Header::from_bytes(b"Last-Modified", last_modified).unwrap())
+        .with_header(
+            Header::from_bytes(b"Cache-Control", format!("public, max-age={max_age}"))
+                .unwrap(),
+        )
+}
+
+/// Builds a feed of `notes` for the site at `base`, listed on the page at `path`
+/// and served in `format` under it.
+fn feed<'a>(
+    base: &str,
+    title: &str,
+    author: &str,
+    path: &str,
+    format: feed::Format,
+    license: Option<&str>,
+    notes: impl IntoIterator<Item = &'a IndexedDocument>,
+) -> feed::Feed {
+    let host = url::Url::parse(base)
+        .ok()
+        .and_then(|x| x.host_str().map(str::to_string))
+        .unwrap_or_else(|| String::from("localhost"));
+    let entries = notes
+        .into_iter()
+        .filter(|doc| !doc.unlisted)
+        .map(|doc| {
+            let encoded: Vec<_> =
+                doc.rel_path.split('/').map(uri::percent_encode).collect();
+            let id = format!("{base}/note/{}", encoded.join("/"));
+            feed::Entry {
+                tag: format!("tag:{host},{}:{}", doc.created, encoded.join("/")),
+                title: (doc.kind != NoteKind::Micro).then(|| doc.title.clone()),
+                // Bookmarks lead to what they point at, like on the index.
+                link: match (doc.kind, &doc.url) {
+                    (NoteKind::Bookmark, Some(url)) => url.clone(),
+                    _ => id.clone(),
+                },
+                id,
+                published: doc.created.and_time(chrono::NaiveTime::MIN).and_utc(),
+                updated: doc.modified.into(),
+                summary: doc.desc.clone(),
+                content: doc.content.clone(),
+                license: doc.license.as_deref().or(license).map(license::url),
+                warning: doc.warning.clone(),
+            }
+        })
+        .collect();
+    feed::Feed {
+        title: title.to_string(),
+        author: author.to_string(),
+        url: format!("{base}{path}/{}", format.file()),
+        site: match path {
+            "" => format!("{base}/"),
+            path => format!("{base}{path}"),
+        },
+        entries,
+    }
+}
+
+/// Responds with the current document of `feed`, or the archive asked for.
+fn feed_page(
+    request: &Request,
+    config: &Config,
+    feed: &feed::Feed,
+    format: feed::Format,
+    archive: Option<&str>,
+) -> Response<io::Cursor<Vec<u8>>> {
+    let archive = match archive.map(str::parse) {
+        Some(Ok(archive)) => Some(archive),
+        Some(Err(_)) => {
+            return Response::from_string("Invalid archive").with_status_code(400);
+        }
+        None => None,
+    };
+    let Some(page) = feed::Page::new(feed.entries.len(), config.feed_size, archive)
+    else {
+        return Response::from_string("No such archive").with_status_code(404);
+    };
+    let modified = feed.entries[page.entries.clone()]
+        .iter()
+        .map(|x| x.updated)
+        .max()
+        .unwrap_or_default();
+    feed_response(
+        request,
+        format.render(feed, &page).into_bytes(),
+        format.content_type(),
+        modified.into(),
+        config.feed_max_age,
+    )
+}
+
+/// Reads the request body, answering 413 when it's longer than `limit` bytes.
+fn read_body(
+    request: &mut Request,
+    limit: u64,
+) -> Result<Vec<u8>, Response<io::Cursor<Vec<u8>>>> {
+    let mut body = Vec::new();
+    if let Err(e) = request.as_reader().take(limit + 1).read_to_end(&mut body) {
+        error!("Failed to read request body: {e}");
+        return Err(Response::from_string("Failed to read body").with_status_code(400));
+    }
+    if body.len() as u64 > limit {
+        return Err(Response::from_string("Body too large").with_status_code(413));
+    }
+    Ok(body)
+}
+
+/// Downloads the article at the URL in the request body, for [`SrvState::archive`].
+fn fetch_archive(
+    request: &mut Request,
+    limit: u64,
+) -> Result<archive::Article, Response<io::Cursor<Vec<u8>>>> {
+    let url = read_body(request, 4096)?;
+    let Ok(url) = String::from_utf8(url) else {
+        return Err(Response::from_string("Expected a URL").with_status_code(400));
+    };
+    archive::article(&url, limit).map_err(|e| {
+        error!("Failed to archive \"{url}\": {e}");
+        Response::from_string(e.to_string()).with_status_code(502)
+    })
+}
+
+/// Finds the value of the first header named `name`.
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn with_headers<R: io::Read>(
+    mut response: Response<R>,
+    headers: &[Header],
+) -> Response<R> {
+    for header in headers {
+        response.add_header(header.clone());
+    }
+    response
+}
+
+fn respond_or_log<R: io::Read + Send + 'static>(request: Request, response: Response<R>) {
+    let entry =
+        accesslog::Entry::new(&request, response.status_code(), response.data_length());
+    if let Err(e) = request.respond(response) {
+        error!("Failed to respond to request: {e}");
+    }
+    if let Some(entry) = entry {
+        entry.write();
+    }
+}
+
+/// The listed notes in `index` that are new or modified since `before`. Anyone can
+/// listen for them on `/events`, so unlisted notes are left out.
+fn changed_notes(before: &Index, index: &Index) -> Vec<String> {
+    use std::collections::HashMap;
+
+    let before: HashMap<_, _> = before
+        .iter()
+        .map(|doc| (doc.rel_path.as_str(), doc.modified))
+        .collect();
+    index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .filter(|doc| before.get(doc.rel_path.as_str()) != Some(&doc.modified))
+        .map(|doc| doc.rel_path.clone())
+        .collect()
+}
+
+/// The notes in `index` whose embeddings are missing or out of date, with when they
+/// were modified, after forgetting those of notes that are gone.
+fn stale_embeddings(
+    index: &Index,
+    semantic: &mut semantic::Index,
+) -> Vec<(String, SystemTime)> {
+    let paths: HashSet<_> = index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .map(|doc| doc.rel_path.as_str())
+        .collect();
+    semantic.retain(|rel_path| paths.contains(rel_path));
+    index
+        .iter()
+        .filter(|doc| !doc.unlisted && !semantic.is_fresh(&doc.rel_path, doc.modified))
+        .map(|doc| (doc.rel_path.clone(), doc.modified))
+        .collect()
+}
+
+/// Embeds the chunks of the notes in `notes`, giving up on the rest if the model
+/// can't be reached.
+fn embed_notes<'a>(
+    config: &semantic::Config,
+    content_path: &Path,
+    notes: &'a [(String, SystemTime)],
+) -> Vec<(&'a str, SystemTime, Vec<Vec<f32>>)> {
+    let mut embeddings = Vec::new();
+    for (rel_path, modified) in notes {
+        let markdown = match fs::read_to_string(content_path.join(rel_path)) {
+            Ok(markdown) => markdown,
+            Err(e) => {
+                error!("Failed to read \"{rel_path}\": {e}");
+                continue;
+            }
+        };
+        // Semantic search is public, so what's for members isn't embedded.
+        let chunks = context::split(members::public(&markdown));
+        let chunks: Vec<_> = chunks.iter().map(|(_, x)| x.as_str()).collect();
+        match semantic::embed_note(config, &chunks) {
+            Ok(vectors) => embeddings.push((rel_path.as_str(), *modified, vectors)),
+            Err(e) => {
+                error!("Failed to embed \"{rel_path}\": {e}");
+                break;
             }
-            std::mem::drop(state);
         }
     }
+    embeddings
 }
 
-fn respond_or_log<R: io::Read>(request: Request, response: Response<R>) {
-    if let Err(e) = request.respond(response) {
-        error!("Failed to respond to request: {e}");
-    }
-}
+/// Indexes the notes in `content_path`, only reading the files that changed since
+/// `previous` was generated. Notes dated in the future are left out until then, and
+/// returned with when that is.
+fn generate_index(
+    content_path: &Path,
+    previous: &Index,
+    filters: &filter::Filters,
+    search: &mut search::SearchIndex,
+    search_config: &search::Config,
+    summaries: &mut summary::Summaries,
+    summary_config: &summary::Config,
+) -> std::io::Result<(Index, std::collections::HashMap<String, NaiveDateTime>)> {
+    use std::collections::HashMap;
 
-fn generate_index(content_path: &Path) -> std::io::Result<Index> {
+    // Notes added since can't be linked to until the next reload.
+    let links = wikilink::Links::new(
+        previous
+            .iter()
+            .map(|doc| (doc.title.as_str(), doc.rel_path.as_str())),
+    );
+    let previous: HashMap<_, _> = previous
+        .iter()
+        .map(|doc| (doc.rel_path.as_str(), doc))
+        .collect();
     let mut index = Vec::new();
+    let mut scheduled = HashMap::new();
+    // Notes to keep in the search index.
+    let mut seen = HashSet::new();
     let mut contents = String::new();
     walk(content_path, &mut |is_dir, path| {
         if path
@@ -252,10 +4423,34 @@ fn generate_index(content_path: &Path) -> std::io::Result<Index> {
         }
         if !is_dir {
             let guess = mime_guess::from_path(path).first();
-            if guess.is_none_or(|guess| guess != "text/markdown") {
+            let filtered = path
+                .extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| filters.handles_file(x));
+            if guess.is_none_or(|guess| guess != "text/markdown") && !filtered {
                 return Ok(true);
             }
             let metadata = fs::metadata(path)?;
+            let Some(rel_path) = path
+                .strip_prefix(content_path)
+                .ok()
+                .and_then(Path::to_str)
+                .map(str::to_string)
+            else {
+                error!("Skipping document due to invalid path: \"{path:?}\"");
+                return Ok(true);
+            };
+            let modified = metadata.modified()?;
+            if let Some(&doc) = previous
+                .get(rel_path.as_str())
+                .filter(|doc| doc.modified == modified)
+            {
+                if !doc.unlisted {
+                    seen.insert(rel_path);
+                }
+                index.push(doc.clone());
+                return Ok(true);
+            }
             let created = DateTime::<chrono::offset::Local>::from(
                 metadata
                     .created()
@@ -278,49 +4473,547 @@ fn generate_index(content_path: &Path) -> std::io::Result<Index> {
 
             let mut f = fs::File::open(path)?;
             f.read_to_string(&mut contents)?;
-            let (_, meta) = mdtodoc(&contents, Meta::inferred(title, created));
-            contents.clear();
-            let Some(rel_path) = path
-                .strip_prefix(content_path)
-                .ok()
-                .and_then(Path::to_str)
-                .map(str::to_string)
-            else {
-                error!("Skipping document due to invalid path: \"{path:?}\"");
+            if filtered {
+                contents =
+                    note_markdown(filters, &rel_path, std::mem::take(&mut contents));
+            }
+            let (body, mut meta) = render_markdown(
+                &contents,
+                Meta::inferred(title, created),
+                filters,
+                &links,
+            );
+            if Path::new(&rel_path).starts_with("micro") {
+                meta.kind = NoteKind::Micro;
+            }
+            if meta.date > chrono::Local::now().naive_local() {
+                scheduled.insert(rel_path, meta.date);
+                contents.clear();
                 return Ok(true);
+            }
+            let references = bundle::urls(&body)
+                .filter_map(|url| Some(bundle::resolve(&rel_path, url)?.0))
+                .collect();
+            // Readers can't search for, or read summaries, todos, cards or dates of,
+            // what's for members.
+            let public = members::public(&contents);
+            if !meta.unlisted && !search.is_fresh(&rel_path, modified) {
+                let (headings, text) = search::plaintext(public);
+                let document = search::Document {
+                    rel_path: rel_path.clone(),
+                    title: meta.title.clone(),
+                    date: meta.date.into(),
+                    tags: meta.tags.clone(),
+                    lang: meta.lang.clone(),
+                    headings,
+                    body: search_config.truncate(text),
+                };
+                search.update(document, modified);
+            }
+            // Long notes without a description get one from the model.
+            let desc = meta.desc.or_else(|| {
+                summary_config.endpoint.as_ref()?;
+                let text = search::plaintext(public).1;
+                summaries.get(summary_config, &rel_path, modified, &text)
+            });
+            let open_todos = todos::find(public);
+            let flashcards = cards::find(public);
+            let mut events = calendar::markers(public);
+            contents.clear();
+            if let Some(when) = meta.event_date {
+                let summary = meta.title.clone();
+                events.insert(0, calendar::Event { when, summary });
+            }
+            if let Some(when) = meta.due {
+                let summary = format!("Due: {}", meta.title);
+                events.insert(0, calendar::Event { when, summary });
+            }
+            if !meta.unlisted {
+                seen.insert(rel_path.clone());
+            }
+            let body = members::gate(body);
+            let body = match &meta.warning {
+                Some(warning) => content_warning_html(warning, &body),
+                None => body,
             };
 
             index.push(IndexedDocument {
                 title: meta.title,
                 created: meta.date.into(),
                 rel_path,
+                kind: meta.kind,
+                url: meta.url,
+                content: (meta.kind == NoteKind::Micro).then_some(body),
+                strip_exif: meta.strip_exif,
+                todos: open_todos,
+                tags: meta.tags,
+                status: meta.status,
+                events,
+                lang: meta.lang,
+                license: meta.license,
+                warning: meta.warning,
+                reviewed: meta.last_reviewed,
+                icon: meta.icon,
+                unlisted: meta.unlisted,
+                cards: flashcards,
+                references,
+                desc,
+                modified,
             });
         }
         Ok(true)
     })?;
+    search.retain(|rel_path| seen.contains(rel_path));
     index.sort_by(|left, right| right.created.cmp(&left.created));
-    Ok(index)
+    Ok((index, scheduled))
+}
+
+/// Page `page` of `listed`, counting from 1, with links to the alphabetical
+/// shards and to the pages around it, which keep `query`, such as `lang=all`.
+/// `None` when there's no such page.
+fn index_page_html(
+    listed: &[&IndexedDocument],
+    page: usize,
+    config: &Config,
+    query: &str,
+    owner: bool,
+) -> Option<String> {
+    let size = config.index_page_size;
+    let range = shards::page(listed.len(), size, page)?;
+    let pages = shards::pages(listed.len(), size);
+    let link = |page: usize| match (page, query) {
+        (1, "") => String::from("/"),
+        (1, query) => format!("/?{query}"),
+        (page, "") => format!("/?page={page}"),
+        (page, query) => format!("/?{query}&amp;page={page}"),
+    };
+    let mut html = letters_html(listed.iter().copied());
+    let notes = listed[range].iter().copied();
+    html.push_str(&generate_index_html(notes, config.stale_after, owner));
+    if pages > 1 {
+        html.push_str(r#"<nav class="pages">"#);
+        if page > 1 {
+            let newer = link(page - 1);
+            html.push_str(&format!(r#"<a rel="prev" href="{newer}">Newer</a> "#));
+        }
+        html.push_str(&format!("Page {page} of {pages}"));
+        if page < pages {
+            let older = link(page + 1);
+            html.push_str(&format!(r#" <a rel="next" href="{older}">Older</a>"#));
+        }
+        html.push_str("</nav>");
+    }
+    Some(html)
+}
+
+/// Links to the alphabetical shards, `/index/<shard>`, that `notes` are in.
+fn letters_html<'a>(notes: impl IntoIterator<Item = &'a IndexedDocument>) -> String {
+    let shards: BTreeSet<_> = notes
+        .into_iter()
+        .map(|doc| shards::shard(&doc.title))
+        .collect();
+    let links: Vec<_> = shards
+        .iter()
+        .map(|shard| {
+            let label = match shard.as_str() {
+                shards::OTHER => String::from("Other"),
+                letter => letter.to_uppercase(),
+            };
+            format!(
+                r#"<a href="/index/{}">{label}</a>"#,
+                uri::percent_encode(shard)
+            )
+        })
+        .collect();
+    format!(r#"<nav class="letters">{}</nav>"#, links.join(" "))
 }
 
-fn generate_index_html(index: &[IndexedDocument]) -> String {
+/// Lists `index`, marking notes older than `stale_after`, with buttons to add each
+/// note to the reading list for the `owner`.
+fn generate_index_html<'a>(
+    index: impl IntoIterator<Item = &'a IndexedDocument>,
+    stale_after: Option<age::Age>,
+    owner: bool,
+) -> String {
+    let today = chrono::Local::now().date_naive();
     let mut page = String::new();
     page.push_str(r#"<ol style="list-style-type: none">"#);
     for doc in index {
+        let stale = match stale_after.filter(|age| age.exceeded(doc.created, today)) {
+            Some(age) => {
+                format!(r#" <span class="stale" title="Over {age} old">⌛</span>"#)
+            }
+            None => String::new(),
+        };
+        let tags = tag_links(&doc.tags) + &stale + &pages::queue_form(doc, owner);
+        let icon = match &doc.icon {
+            Some(icon) => format!(r#"<span class="icon">{}</span> "#, escape_html(icon)),
+            None => String::new(),
+        };
+        match (doc.kind, &doc.url) {
+            (NoteKind::Micro, _) => page.push_str(&format!(
+                r#"<li class="micro" id="{anchor}"> <time datetime="{time}+0:0">{time}</time> <a class="permalink" href="/note/{path}">#</a><div class="micro-content">{content}</div>{tags}</li>"#,
+                anchor = anchor_id(&doc.rel_path), time = doc.created, path = doc.rel_path,
+                content = doc.content.as_deref().unwrap_or_default()
+            )),
+            // Bookmarks link out directly, the note itself is only a permalink.
+            (NoteKind::Bookmark, Some(url)) => page.push_str(&format!(
+                r#"<li class="bookmark"> <time datetime="{time}+0:0">{time}</time> - {icon}<a href="{url}">{title}</a> <a class="permalink" href="/note/{path}">#</a>{tags}</li>"#,
+                time = doc.created, url = escape_html(url), path = doc.rel_path, title = escape_html(&doc.title)
+            )),
+            _ => page.push_str(&format!(
+                r#"<li> <time datetime="{time}+0:0">{time}</time> - {icon}<a href="/note/{path}">{title}</a>{tags}{desc}</li>"#,
+                time = doc.created, path = doc.rel_path, title = doc.title,
+                desc = doc.desc.as_ref().map(|x| format!(r#"<p class="desc">{}</p>"#, escape_html(x))).unwrap_or_default()
+            )),
+        }
+    }
+    page.push_str(r#"</ol>"#);
+    page
+}
+
+/// Links to the page of each of a note's tags.
+fn tag_links(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let links: Vec<_> = tags
+        .iter()
+        .map(|tag| {
+            format!(
+                r#"<a class="tag" href="/tag/{url}">#{tag}</a>"#,
+                url = uri::percent_encode(tag),
+                tag = escape_html(tag),
+            )
+        })
+        .collect();
+    format!(r#" <span class="tags">{}</span>"#, links.join(" "))
+}
+
+/// Finds the files in `content_path` that no note in `index` links to or embeds, and
+/// the ones notes do that are missing. Galleries use every file in their directory.
+fn asset_report(content_path: &Path, index: &Index) -> io::Result<assets::Report> {
+    let notes: HashSet<_> = index.iter().map(|doc| doc.rel_path.as_str()).collect();
+    let mut files = BTreeSet::new();
+    walk(content_path, &mut |is_dir, path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') {
+            return Ok(false);
+        }
+        let rel_path = path
+            .strip_prefix(content_path)
+            .ok()
+            .and_then(Path::to_str)
+            .filter(|x| !notes.contains(x));
+        let special = name == redirects::FILE || name == GALLERY_CAPTIONS;
+        if let (false, false, Some(rel_path)) = (is_dir, special, rel_path) {
+            files.insert(rel_path.to_string());
+        }
+        Ok(true)
+    })?;
+    let dir = |rel_path: &str| Path::new(rel_path).parent().map(Path::to_path_buf);
+    let galleries: Vec<_> = index
+        .iter()
+        .filter(|doc| doc.kind == NoteKind::Gallery)
+        .map(|doc| (doc.rel_path.as_str(), dir(&doc.rel_path)))
+        .collect();
+    let in_galleries = files.iter().filter_map(|file| {
+        let (note, _) = galleries.iter().find(|(_, x)| *x == dir(file))?;
+        Some((*note, file.as_str()))
+    });
+    let references = index.iter().flat_map(|doc| {
+        doc.references
+            .iter()
+            .map(|x| (doc.rel_path.as_str(), x.as_str()))
+    });
+    Ok(assets::Report::new(
+        &files,
+        &notes,
+        references.chain(in_galleries),
+    ))
+}
+
+/// Lists unused files, and the notes missing files with what they're missing.
+fn asset_report_html(report: &assets::Report) -> String {
+    let mut page = String::from("<h2>Missing files</h2>");
+    if report.missing.is_empty() {
+        page.push_str("<p>None.</p>");
+    } else {
+        page.push_str("<ul>");
+        for (note, path) in &report.missing {
+            page.push_str(&format!(
+                r#"<li><a href="/note/{note}">{title}</a> uses <code>{path}</code></li>"#,
+                title = escape_html(note),
+                path = escape_html(path),
+            ));
+        }
+        page.push_str("</ul>");
+    }
+    page.push_str("<h2>Unused files</h2>");
+    if report.unused.is_empty() {
+        page.push_str("<p>None.</p>");
+    } else {
+        page.push_str("<ul>");
+        for path in &report.unused {
+            page.push_str(&format!(
+                r#"<li><a href="/note/{path}">{name}</a></li>"#,
+                name = escape_html(path),
+            ));
+        }
+        page.push_str("</ul>");
+    }
+    page
+}
+
+fn integrity_report_html(report: io::Result<integrity::Report>) -> String {
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            let e = escape_html(&e.to_string());
+            return format!("<h2>Integrity</h2><p>Failed to verify: {e}</p>");
+        }
+    };
+    let mut page = format!(
+        "<h2>Integrity</h2><p>Checked {} files against their checksums.</p>",
+        report.checked
+    );
+    if report.is_empty() {
+        page.push_str("<p>None changed without being modified.</p>");
+        return page;
+    }
+    page.push_str("<ul>");
+    for path in &report.changed {
         page.push_str(&format!(
-            r#"<li> <time datetime="{time}+0:0">{time}</time> - <a href="/note/{path}">{title}</a></li>"#,
-            time = doc.created, path = doc.rel_path, title = doc.title
+            "<li><code>{}</code> changed without being modified</li>",
+            escape_html(path)
         ));
     }
-    page.push_str(r#"</ol>"#);
+    for (path, e) in &report.unreadable {
+        page.push_str(&format!(
+            "<li><code>{}</code> can't be read: {}</li>",
+            escape_html(path),
+            escape_html(e)
+        ));
+    }
+    page.push_str("</ul>");
+    page
+}
+
+/// Lists the open todos of every note, grouped by note, newest note first.
+fn todos_html(index: &[IndexedDocument]) -> String {
+    let mut page = String::new();
+    for doc in index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .filter(|doc| !doc.todos.is_empty())
+    {
+        page.push_str(&format!(
+            r#"<h2><a href="/note/{path}">{title}</a></h2><ul class="todos">"#,
+            path = doc.rel_path,
+            title = escape_html(&doc.title),
+        ));
+        for todo in &doc.todos {
+            let marker = match todo.marker {
+                Some(marker) => format!(r#"<span class="marker">{marker}</span> "#),
+                None => String::from(r#"<input type="checkbox" disabled> "#),
+            };
+            page.push_str(&format!(
+                r#"<li>{marker}<a href="/note/{path}{fragment}">{text}</a></li>"#,
+                path = doc.rel_path,
+                fragment = todo.fragment(),
+                text = escape_html(&todo.text),
+            ));
+        }
+        page.push_str("</ul>");
+    }
+    if page.is_empty() {
+        page.push_str("<p>Nothing to do.</p>");
+    }
     page
 }
 
+/// Sets `key` in the meta block of the markdown document `md`, keeping the rest
+/// of the document as it was. Documents without a meta block get one, dated
+/// `created` since meta blocks need a date.
+fn set_meta(md: &str, key: &str, value: &toml::Value, created: NaiveDate) -> String {
+    let line = format!("{key} = {value}\n");
+    let mut out = String::with_capacity(md.len() + line.len());
+    let mut in_meta = false;
+    let mut done = false;
+    for current in md.split_inclusive('\n') {
+        let trimmed = current.trim();
+        if !done
+            && trimmed
+                .strip_prefix("```")
+                .is_some_and(|x| x.trim() == "meta")
+        {
+            in_meta = true;
+        } else if in_meta && trimmed.starts_with("```") {
+            in_meta = false;
+            done = true;
+            out.push_str(&line);
+        } else if in_meta
+            && current
+                .trim_start()
+                .strip_prefix(key)
+                .is_some_and(|x| x.trim_start().starts_with('='))
+        {
+            continue;
+        }
+        out.push_str(current);
+    }
+    if done {
+        return out;
+    }
+    format!(
+        "```meta\ndate = \"{}\"\n{line}```\n\n{md}",
+        NaiveDateTime::from(created).format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+/// Sidecar file mapping image file names to captions in a gallery directory.
+const GALLERY_CAPTIONS: &str = "captions.toml";
+
+/// Served as `/favicon.ico` when [`Config::favicon`] isn't set.
+const FAVICON: &[u8] = include_bytes!("favicon.ico");
+
+/// Renders the images in `dir` as a grid, oldest photo first. There are no
+/// thumbnails: the grid shows the original files scaled down by the stylesheet,
+/// each linking to itself.
+fn gallery_html(content_path: &Path, dir: &Path) -> io::Result<String> {
+    use std::collections::HashMap;
+
+    let captions: HashMap<String, String> =
+        match fs::read_to_string(dir.join(GALLERY_CAPTIONS)) {
+            Ok(contents) => toml::de::from_str(&contents).unwrap_or_else(|e| {
+                error!("Failed to parse gallery captions in \"{dir:?}\": {e}");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_image = mime_guess::from_path(&path)
+            .first()
+            .is_some_and(|mime| mime.type_() == mime_guess::mime::IMAGE);
+        if !is_image || !path.is_file() {
+            continue;
+        }
+        // EXIF lives at the start of the file, so there's no need to read all of it.
+        let mut head = Vec::new();
+        fs::File::open(&path)?
+            .take(128 * 1024)
+            .read_to_end(&mut head)?;
+        let taken = exif::date_taken(&head);
+        images.push((taken, path));
+    }
+    images.sort();
+
+    let mut html = String::from(r#"<div class="gallery">"#);
+    for (_, path) in images {
+        let (Some(name), Some(rel_path)) = (
+            path.file_name().and_then(|x| x.to_str()),
+            path.strip_prefix(content_path).ok().and_then(Path::to_str),
+        ) else {
+            continue;
+        };
+        let caption = captions.get(name).map(String::as_str).map(escape_html);
+        let url: Vec<_> = rel_path.split('/').map(uri::percent_encode).collect();
+        html.push_str(&format!(
+            r#"<figure><a href="/note/{url}"><img src="/note/{url}" alt="{alt}" loading="lazy"></a>"#,
+            url = url.join("/"),
+            alt = caption.clone().unwrap_or_else(|| escape_html(name)),
+        ));
+        if let Some(caption) = caption {
+            html.push_str(&format!("<figcaption>{caption}</figcaption>"));
+        }
+        html.push_str("</figure>");
+    }
+    html.push_str("</div>");
+    Ok(html)
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Turns a relative path into something usable as an HTML `id`.
+fn anchor_id(rel_path: &str) -> String {
+    rel_path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum NoteKind {
+    #[default]
+    Note,
+    /// A link-blog entry pointing at an external `url`.
+    Bookmark,
+    /// A tiny untitled post shown in full on the index.
+    Micro,
+    /// A note followed by a grid of the images in its directory.
+    Gallery,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct Meta {
-    title: String,
-    date:  NaiveDateTime,
-    lang:  Option<String>,
-    desc:  Option<String>,
+    #[serde(default)]
+    title:         String,
+    date:          NaiveDateTime,
+    lang:          Option<String>,
+    desc:          Option<String>,
+    #[serde(default, rename = "type")]
+    kind:          NoteKind,
+    url:           Option<String>,
+    /// Overrides [`Config::strip_exif`] for images next to this note.
+    strip_exif:    Option<bool>,
+    #[serde(default)]
+    tags:          Vec<String>,
+    /// Column on kanban boards, such as `todo`, `doing` or `done`.
+    status:        Option<String>,
+    /// When the event the note is about happens, for `/calendar.ics`.
+    event_date:    Option<calendar::When>,
+    /// When whatever the note tracks is due, for `/calendar.ics`.
+    due:           Option<calendar::When>,
+    /// The site owner's profile, only read from [`profile::NOTE`].
+    profile:       Option<profile::Profile>,
+    /// `Cache-Control` to send with the note, such as `no-store`.
+    cache_control: Option<String>,
+    /// Ask search engines not to index the note.
+    #[serde(default)]
+    noindex:       bool,
+    /// Reload the note, or go elsewhere, after a while, such as `5; url=/`.
+    refresh:       Option<String>,
+    /// Overrides [`Config::license`] for this note.
+    license:       Option<String>,
+    /// Hides the note behind this warning until the reader opens it, such as
+    /// `Spoilers` or `Eye contact`.
+    #[serde(rename = "content_warning")]
+    warning:       Option<String>,
+    /// When the note was last checked to still be right, for `/review`.
+    last_reviewed: Option<NaiveDate>,
+    /// An emoji shown before the note's title on the index, and as its favicon.
+    icon:          Option<String>,
+    /// Keep the note off the index, feeds and search, so only those given its URL
+    /// find it.
+    #[serde(default)]
+    unlisted:      bool,
 }
 
 impl Meta {
@@ -330,7 +5023,59 @@ impl Meta {
             date: NaiveDateTime::from(created),
             lang: None,
             desc: None,
+            kind: NoteKind::default(),
+            url: None,
+            strip_exif: None,
+            tags: Vec::new(),
+            status: None,
+            event_date: None,
+            due: None,
+            profile: None,
+            cache_control: None,
+            noindex: false,
+            refresh: None,
+            license: None,
+            warning: None,
+            last_reviewed: None,
+            icon: None,
+            unlisted: false,
+        }
+    }
+
+    /// Response headers the note asks for.
+    fn headers(&self) -> Vec<Header> {
+        let mut headers = Vec::new();
+        if let Some(cache_control) = &self.cache_control {
+            headers.push(Header::from_bytes(
+                b"Cache-Control",
+                cache_control.as_bytes(),
+            ));
+        }
+        if self.noindex {
+            headers.push(Header::from_bytes(b"X-Robots-Tag", b"noindex"));
+        }
+        if let Some(refresh) = &self.refresh {
+            headers.push(Header::from_bytes(b"Refresh", refresh.as_bytes()));
         }
+        headers
+            .into_iter()
+            .filter_map(|x| {
+                x.inspect_err(|_| warn!("Invalid header in meta of \"{}\"", self.title))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// The external link a bookmark points to, if this is one.
+    fn bookmark_url(&self) -> Option<&str> {
+        match self.kind {
+            NoteKind::Bookmark => self.url.as_deref(),
+            NoteKind::Note | NoteKind::Micro | NoteKind::Gallery => None,
+        }
+    }
+
+    fn is_micro(&self) -> bool {
+        self.kind == NoteKind::Micro
     }
 }
 
@@ -355,13 +5100,96 @@ impl Meta {
                     <meta property="og:description" content="{{ desc|e("html") }}" />
                 {% when None %}
             {% endmatch %}
+            {% if meta.noindex %}
+                <meta name="robots" content="noindex" />
+            {% endif %}
+            {% match meta.refresh %}
+                {% when Some with (refresh) %}
+                    <meta http-equiv="refresh" content="{{ refresh|e("html") }}" />
+                {% when None %}
+            {% endmatch %}
+            <link rel="alternate" type="application/rss+xml" title="Notes" href="/feed.xml" />
+            <link rel="alternate" type="application/atom+xml" title="Notes" href="/atom.xml" />
+            <link rel="search" type="application/opensearchdescription+xml" title="Notes" href="/opensearch.xml" />
+            {% match favicon %}
+                {% when Some with (favicon) %}
+                    <link rel="icon" href="{{ favicon|e("html") }}" />
+                {% when None %}
+            {% endmatch %}
+            {% for identity in identities %}
+                <link rel="me" href="{{ identity|e("html") }}" />
+            {% endfor %}
             <style> {{ styles }} </style>
         </head>
-        <body><main>
-        <h1> {{ meta.title|e("html") }}</h1>
+        <body>
+        {% if !menu.is_empty() %}
+        <header><nav>
+            {% for item in menu %}
+                <a href="{{ item.url|e("html") }}">{{ item.label|e("html") }}</a>
+            {% endfor %}
+        </nav></header>
+        {% endif %}
+        <main>
+        {% if !meta.is_micro() %}
+        {% match meta.bookmark_url() %}
+            {% when Some with (url) %}
+                <h1 class="bookmark"><a href="{{ url|e("html") }}">{{ meta.title|e("html") }}</a></h1>
+            {% when None %}
+                <h1> {{ meta.title|e("html") }}</h1>
+        {% endmatch %}
+        {% endif %}
         <article>{{ markdown }}</article>
-        </main></body>
+        </main>
+        {% if !footer.is_empty() %}
+        <footer>{{ footer }}</footer>
+        {% endif %}
+        </body>
+
+        {{ scripts }}
+        </html>
+        "#
+)]
+struct DocumentTemplate<'a> {
+    meta:       Meta,
+    /// A `data:` URL of the page's icon.
+    favicon:    Option<String>,
+    styles:     &'a str,
+    identities: &'a [String],
+    menu:       &'a [&'a MenuItem],
+    markdown:   &'a str,
+    footer:     &'a str,
+    scripts:    &'a str,
+}
+
+/// What a page that isn't there says, with notes that may be what was wanted.
+#[derive(Template)]
+#[template(
+    ext = "html",
+    escape = "none",
+    source = r#"
+        <p>There's nothing at <code>{{ path|e("html") }}</code>.</p>
+        {% if !suggestions.is_empty() %}
+        <p>Perhaps you meant:</p>
+        <ul class="suggestions">
+            {% for doc in suggestions %}
+                <li><a href="/note/{{ doc.rel_path|e("html") }}">{{ doc.title|e("html") }}</a></li>
+            {% endfor %}
+        </ul>
+        {% endif %}
+        <p><a href="/">Back to the index</a></p>
+        "#
+)]
+struct NotFoundTemplate<'a> {
+    path:        &'a str,
+    suggestions: &'a [&'a IndexedDocument],
+}
 
+/// The scripts every page ends with, whichever template it's rendered with.
+#[derive(Template)]
+#[template(
+    ext = "html",
+    escape = "none",
+    source = r#"
         <script>
         // Javascript is the worst thing ever. The idea that anyone uses this professionally is crazy.
         window.addEventListener("load", () => {
@@ -371,33 +5199,176 @@ impl Meta {
             });
         });
         </script>
-        </html>
+        {% if live_reload %}
+        <script>
+        const events = new EventSource("/events");
+        // Pages listing notes change along with the index.
+        events.addEventListener("index-changed", () => {
+            const path = window.location.pathname;
+            if (path === "/" || /^\/(tag|view)\//.test(path)) window.location.reload();
+        });
+        events.addEventListener("note-changed", (e) => {
+            const path = decodeURIComponent(window.location.pathname);
+            if (path === `/note/${e.data}`) window.location.reload();
+        });
+        </script>
+        {% endif %}
+        {% if owner %}
+        <script>
+        // Highlighting a selection shows a button to save it as an annotation.
+        const $annotate = document.createElement("button");
+        $annotate.className = "annotate";
+        $annotate.innerText = "Annotate";
+        $annotate.hidden = true;
+        document.body.append($annotate);
+        document.addEventListener("mouseup", (e) => {
+            if (e.target === $annotate) return;
+            $annotate.hidden = window.getSelection().toString().trim() === "";
+        });
+        $annotate.addEventListener("click", () => {
+            const quote = window.getSelection().toString().trim();
+            const comment = prompt(`Comment on "${quote}" (optional):`);
+            if (comment === null) return;
+            fetch(window.location.pathname.replace(/^\/note\//, "/api/annotate/"), {
+                method: "POST",
+                headers: {"Content-Type": "application/json"},
+                body: JSON.stringify({quote, comment}),
+            }).then(r => r.ok ? window.location.reload() : alert("Failed to save annotation"));
+        });
+        </script>
+        {% endif %}
         "#
 )]
-struct DocumentTemplate<'a> {
-    meta:     Meta,
-    styles:   &'a str,
-    markdown: &'a str,
+struct ScriptsTemplate {
+    /// Whether the page is being shown to the owner, enabling annotation.
+    owner:       bool,
+    live_reload: bool,
+}
+
+fn render_page(
+    config: &Config,
+    theme: &theme::Theme,
+    footer: &str,
+    meta: &Meta,
+    markdown: &str,
+    owner: bool,
+) -> String {
+    use minijinja::Value;
+
+    let scripts = ScriptsTemplate {
+        owner,
+        live_reload: config.live_reload,
+    }
+    .render()
+    .unwrap();
+    let mut menu: Vec<_> = config.menu.iter().collect();
+    menu.sort_by_key(|x| x.weight);
+    let favicon = meta
+        .icon
+        .as_ref()
+        .or(config.icon.as_ref())
+        .map(|x| favicon(x));
+    if let Some(template) = theme.template() {
+        let page = template.render(minijinja::context! {
+            title => meta.title,
+            lang => meta.lang,
+            desc => meta.desc,
+            noindex => meta.noindex,
+            refresh => meta.refresh,
+            bookmark_url => meta.bookmark_url(),
+            micro => meta.is_micro(),
+            identities => config.identities,
+            favicon,
+            menu,
+            owner,
+            styles => Value::from_safe_string(theme.styles.clone()),
+            content => Value::from_safe_string(markdown.to_string()),
+            footer => Value::from_safe_string(footer.to_string()),
+            scripts => Value::from_safe_string(scripts.clone()),
+        });
+        match page {
+            Ok(page) => return page,
+            Err(e) => error!("Failed to render page with theme \"{}\": {e}", theme.name),
+        }
+    }
+    let template = DocumentTemplate {
+        favicon,
+        styles: &theme.styles,
+        identities: &config.identities,
+        menu: &menu,
+        footer,
+        meta: meta.clone(),
+        markdown,
+        scripts: &scripts,
+    };
+    template.render().unwrap()
+}
+
+/// A `data:` URL of an SVG image showing `icon`, an emoji, so pages can have one
+/// without an image file.
+fn favicon(icon: &str) -> String {
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100"><text y=".9em" font-size="90">{}</text></svg>"#,
+        escape_html(icon)
+    );
+    format!("data:image/svg+xml,{}", uri::percent_encode(svg))
+}
+
+/// Renders `md` on its own thread, so a pathological note can't hold up the server
+/// for longer than `timeout`. The thread is left to finish on its own when it takes
+/// too long.
+fn render_markdown_within(
+    rel_path: &str,
+    md: String,
+    infered_meta: Meta,
+    filters: Arc<filter::Filters>,
+    links: Arc<wikilink::Links>,
+    timeout: Duration,
+) -> Result<(String, Meta), RecvTimeoutError> {
+    let (send, receive) = std::sync::mpsc::channel();
+    let rel_path = rel_path.to_string();
+    std::thread::spawn(move || {
+        let md = note_markdown(&filters, &rel_path, md);
+        let rendered = render_markdown(&md, infered_meta, &filters, &links);
+        // The time spent highlighting is recorded on this thread.
+        let _ = send.send((rendered, timing::take()));
+    });
+    let (rendered, spent) = receive.recv_timeout(timeout)?;
+    timing::add(spent);
+    Ok(rendered)
+}
+
+/// The markdown of the note at `rel_path`: the file's `contents`, or what a filter
+/// for its extension makes of them.
+fn note_markdown(filters: &filter::Filters, rel_path: &str, contents: String) -> String {
+    let extension = Path::new(rel_path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    match filters.file(extension, &contents) {
+        None => contents,
+        Some(Ok(markdown)) => markdown,
+        Some(Err(e)) => {
+            error!("Failed to convert \"{rel_path}\": {e}");
+            contents
+        }
+    }
 }
 
-fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
+/// Renders a markdown document to an HTML fragment, without the page template.
+fn render_markdown(
+    md: &str,
+    infered_meta: Meta,
+    filters: &filter::Filters,
+    links: &wikilink::Links,
+) -> (String, Meta) {
     use std::collections::HashMap;
     use std::fmt::Write as _;
 
     use pulldown_cmark::{
-        CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd, html,
+        CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag, TagEnd, html,
     };
 
-    use std::sync::LazyLock;
-    use syntect::highlighting::{Theme, ThemeSet};
-    use syntect::parsing::SyntaxSet;
-    static SYNTAX_SET: LazyLock<SyntaxSet> =
-        LazyLock::new(SyntaxSet::load_defaults_newlines);
-    static THEME: LazyLock<Theme> = LazyLock::new(|| {
-        let theme_set = ThemeSet::load_defaults();
-        theme_set.themes["base16-ocean.dark"].clone()
-    });
-
     #[derive(Default)]
     enum ParseState {
         #[default]
@@ -410,11 +5381,12 @@ fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
     options.insert(Options::ENABLE_GFM);
     options.insert(Options::ENABLE_FOOTNOTES);
     options.insert(Options::ENABLE_MATH);
+    options.insert(Options::ENABLE_WIKILINKS);
 
     let mut state = ParseState::default();
     let mut code = String::new();
     let mut meta = None;
-    let mut syntax = SYNTAX_SET.find_syntax_plain_text();
+    let mut lang = String::new();
 
     // To generate this style, you have to collect the footnotes at the end, while
     // parsing. You also need to count usages.
@@ -422,6 +5394,20 @@ fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
     let mut in_footnote = Vec::new();
     let mut footnote_numbers = HashMap::new();
     let parser = Parser::new_ext(md, options)
+        .map(|event| match event {
+            // Wikilinks lead to the note they name, or to a search for it.
+            Event::Start(Tag::Link { link_type: LinkType::WikiLink { .. }, dest_url, .. }) => {
+                let html = match links.resolve(&dest_url) {
+                    Some(url) => format!(r#"<a class="wikilink" href="{}">"#, escape_html(&url)),
+                    None => format!(
+                        r#"<a class="wikilink missing" href="/search?q={}">"#,
+                        uri::percent_encode(&*dest_url)
+                    ),
+                };
+                Event::Html(html.into())
+            }
+            event => event,
+        })
         .filter_map(|event| {
             match event {
                 Event::Code(code) => {
@@ -459,16 +5445,14 @@ fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
                     in_footnote.last_mut().unwrap().push(event);
                     None
                 }
-                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
-                    let lang = lang.trim();
-                    if lang == "meta" {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    let info = info.trim();
+                    if info == "meta" {
                         state = ParseState::Meta;
                         None
                     } else {
                         state = ParseState::Highlight;
-                        syntax = SYNTAX_SET
-                            .find_syntax_by_token(lang)
-                            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+                        lang = info.to_string();
                         None
                     }
                 }
@@ -493,13 +5477,15 @@ fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
                         None
                     }
                     ParseState::Highlight => {
-                        let html = syntect::html::highlighted_html_for_string(
-                            &code,
-                            &SYNTAX_SET,
-                            syntax,
-                            &THEME,
-                        )
-                        .unwrap_or(code.clone());
+                        let html = match filters.block(&lang, &code) {
+                            Some(Ok(html)) => html,
+                            filtered => {
+                                if let Some(Err(e)) = filtered {
+                                    error!("Failed to filter a \"{lang}\" block: {e}");
+                                }
+                                highlight::html(&lang, &code)
+                            }
+                        };
                         code.clear();
                         state = ParseState::Normal;
                         Some(Event::Html(html.into()))
@@ -638,14 +5624,18 @@ fn mdtodoc(md: &str, infered_meta: Meta) -> (String, Meta) {
         .unwrap();
         output.push_str("</ol>\n");
     }
-    let meta = meta.unwrap_or(infered_meta);
-    let template = DocumentTemplate {
-        styles:   STYLES,
-        meta:     meta.clone(),
-        markdown: &output,
+    let meta = match meta {
+        Some(mut meta) => {
+            // Untitled notes (such as micro-posts) keep their inferred title for
+            // the page head.
+            if meta.title.is_empty() {
+                meta.title = infered_meta.title;
+            }
+            meta
+        }
+        None => infered_meta,
     };
-    let html = template.render().unwrap();
-    (html, meta)
+    (output, meta)
 }
 
 fn walk<F: FnMut(bool, &Path) -> std::io::Result<bool>>(
@@ -671,3 +5661,244 @@ fn walk<F: FnMut(bool, &Path) -> std::io::Result<bool>>(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(rel_path: &str, unlisted: bool) -> IndexedDocument {
+        IndexedDocument {
+            title: rel_path.to_string(),
+            created: NaiveDate::default(),
+            rel_path: rel_path.to_string(),
+            kind: NoteKind::Note,
+            url: None,
+            desc: None,
+            content: None,
+            strip_exif: None,
+            todos: vec![todos::Todo {
+                marker: Some("TODO"),
+                text:   format!("finish {rel_path}"),
+            }],
+            tags: vec![String::from("project")],
+            status: None,
+            events: Vec::new(),
+            lang: None,
+            license: None,
+            warning: None,
+            reviewed: None,
+            icon: None,
+            unlisted,
+            cards: vec![cards::Card {
+                front: format!("What is {rel_path}?"),
+                back:  String::from("A note"),
+            }],
+            references: Vec::new(),
+            modified: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn unlisted_notes_arent_announced() {
+        let before = vec![note("old.md", false)];
+        let mut edited = note("old.md", false);
+        edited.modified += Duration::from_secs(1);
+        let index = vec![edited, note("new.md", false), note("secret.md", true)];
+        assert_eq!(changed_notes(&before, &index), ["old.md", "new.md"]);
+        assert!(changed_notes(&index, &index).is_empty());
+    }
+
+    #[test]
+    fn unlisted_notes_stay_off_listings() {
+        let index = [note("listed.md", false), note("secret.md", true)];
+        let pages = [
+            todos_html(&index),
+            cards::html(&index, true),
+            cards::listed_tsv(&index),
+            pages::board(&index, "project", true),
+        ];
+        for page in pages {
+            assert!(page.contains("listed.md"), "{page}");
+            assert!(!page.contains("secret.md"), "{page}");
+        }
+    }
+
+    /// An empty directory of its own for the test `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("notes-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn notes_made_at_once_get_names_of_their_own() {
+        let content_path = scratch("create-note");
+        let config = Config {
+            content_path: content_path.clone(),
+            data_path: content_path.join(".data"),
+            ..Config::default()
+        };
+        let mut state = SrvState {
+            config,
+            content_path,
+            ..SrvState::default()
+        };
+        let inbox = Path::new("inbox");
+        let paths: Vec<_> = ["First", "Second", "Third"]
+            .into_iter()
+            .map(|body| (state.create_note(inbox, None, body).unwrap(), body))
+            .collect();
+        let (first, _) = &paths[0];
+        let stem = first.strip_suffix(".md").unwrap();
+        for (i, (rel_path, body)) in paths.iter().enumerate() {
+            // Unless the second turned in between.
+            if rel_path.starts_with(stem) && i > 0 {
+                assert_eq!(rel_path, &format!("{stem}-{}.md", i + 1));
+            }
+            let note = fs::read_to_string(state.content_path.join(rel_path)).unwrap();
+            assert!(note.ends_with(body), "{note}");
+        }
+        assert!(state.index.iter().any(|doc| &doc.rel_path == first));
+        fs::remove_dir_all(&state.content_path).unwrap();
+    }
+
+    /// The state of a server for the notes in `files`, written to a directory of
+    /// their own for the test `name`.
+    fn serve(name: &str, files: &[(&str, &str)]) -> RwLock<SrvState> {
+        let content_path = scratch(name);
+        for (rel_path, contents) in files {
+            fs::write(content_path.join(rel_path), contents).unwrap();
+        }
+        let config = Config {
+            data_path: content_path.join(".data"),
+            content_path,
+            api_token: Some(String::from("secret")),
+            ..Config::default()
+        };
+        RwLock::new(SrvState::load(config).unwrap())
+    }
+
+    /// `request` as the owner makes it, with the API token.
+    fn owner(request: http::TestRequest) -> http::TestRequest {
+        request
+            .with_header(Header::from_bytes(b"Authorization", b"Bearer secret").unwrap())
+    }
+
+    /// The status of the response to `request`.
+    fn status(lock: &RwLock<SrvState>, request: http::TestRequest) -> u16 {
+        let mut request = Request::from(request);
+        let response = SrvState::respond(lock, &mut request, false).unwrap();
+        response.status_code()
+    }
+
+    fn get(path: &str) -> http::TestRequest {
+        http::TestRequest::new().with_path(path)
+    }
+
+    #[test]
+    fn notes_dated_later_are_published_then() {
+        let later = "```meta\ndate = \"2999-01-01T00:00:00\"\n```\n\nNot yet";
+        let lock = serve("scheduled", &[("now.md", "Published"), ("later.md", later)]);
+        {
+            let state = lock.read().unwrap();
+            let paths: Vec<_> = state
+                .index
+                .iter()
+                .map(|doc| doc.rel_path.as_str())
+                .collect();
+            assert_eq!(paths, ["now.md"]);
+            assert!(state.scheduled.contains_key("later.md"));
+            assert!(!state.is_publishing_due());
+        }
+        assert_eq!(status(&lock, get("/note/now.md")), 200);
+        assert_eq!(status(&lock, get("/note/later.md")), 404);
+
+        // Its time comes.
+        let mut state = lock.write().unwrap();
+        let now = "```meta\ndate = \"2000-01-01T00:00:00\"\n```\n\nNow";
+        fs::write(state.content_path.join("later.md"), now).unwrap();
+        let config = state.config.clone();
+        state.reload(config).unwrap();
+        assert!(state.scheduled.is_empty());
+        drop(state);
+        assert_eq!(status(&lock, get("/note/later.md")), 200);
+        fs::remove_dir_all(&lock.read().unwrap().content_path).unwrap();
+    }
+
+    #[test]
+    fn notes_are_only_deleted_when_confirmed() {
+        let lock = serve("delete", &[("note.md", "Keep me")]);
+        let content_path = lock.read().unwrap().content_path.clone();
+        let delete = |query: &str| {
+            let path = format!("/note/note.md{query}");
+            http::TestRequest::new()
+                .with_method(Method::DELETE)
+                .with_path(&path)
+        };
+        let stale = {
+            let state = lock.read().unwrap();
+            state.delete_token(&state.index[0])
+        };
+
+        assert_eq!(status(&lock, delete("")), 401);
+        assert_eq!(status(&lock, owner(delete(""))), 200);
+        assert_eq!(status(&lock, owner(delete("?confirm=wrong"))), 409);
+        assert_eq!(status(&lock, delete(&format!("?confirm={stale}"))), 401);
+        assert!(content_path.join("note.md").exists());
+
+        // A token from before the note changed is stale.
+        let mut state = lock.write().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(content_path.join("note.md"), "Changed").unwrap();
+        let config = state.config.clone();
+        state.reload(config).unwrap();
+        let token = state.delete_token(&state.index[0]);
+        assert_ne!(token, stale);
+        drop(state);
+        assert_eq!(
+            status(&lock, owner(delete(&format!("?confirm={stale}")))),
+            409
+        );
+        assert!(content_path.join("note.md").exists());
+
+        assert_eq!(
+            status(&lock, owner(delete(&format!("?confirm={token}")))),
+            204
+        );
+        assert!(!content_path.join("note.md").exists());
+        assert!(content_path.join(".data/trash/note.md").exists());
+        fs::remove_dir_all(&content_path).unwrap();
+    }
+
+    #[test]
+    fn edits_stay_in_the_content_directory() {
+        let lock = serve("edit", &[("note.md", "Before")]);
+        let content_path = lock.read().unwrap().content_path.clone();
+        let edit = |path: &str| {
+            owner(http::TestRequest::new())
+                .with_method(Method::POST)
+                .with_path(path)
+                .with_body("text=After")
+        };
+
+        let refused = [
+            "/edit/",
+            "/edit/%2E%2E/outside.md",
+            "/edit/sub/%2E%2E/%2E%2E/outside.md",
+            "/edit/.data/store.json",
+        ];
+        for path in refused {
+            assert_eq!(status(&lock, edit(path)), 404, "{path}");
+        }
+        assert!(!content_path.parent().unwrap().join("outside.md").exists());
+        let store = fs::read_to_string(content_path.join(".data/store.json"));
+        assert_ne!(store.ok().as_deref(), Some("After"));
+
+        assert_eq!(status(&lock, edit("/edit/note.md")), 303);
+        let note = fs::read_to_string(content_path.join("note.md")).unwrap();
+        assert_eq!(note, "After");
+        fs::remove_dir_all(&content_path).unwrap();
+    }
+}
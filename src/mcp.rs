@@ -0,0 +1,118 @@
+use crate::SrvState;
+use log::{error, info};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_notes",
+            "description": "Find the passages of the notes most relevant to a query. Supports quoted phrases, tag:name, before:YYYY-MM-DD and after:YYYY-MM-DD.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "k": { "type": "integer", "description": "How many passages to return." }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "read_note",
+            "description": "Read a whole note as markdown, by the path search_notes gives in its URL after /note/.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": { "type": "string" } },
+                "required": ["path"]
+            }
+        }
+    ])
+}
+
+/// Runs a tool, returning its text or what went wrong.
+fn call(state: &SrvState, name: &str, arguments: &Value) -> Result<String, String> {
+    let argument = |name| arguments.get(name).and_then(Value::as_str);
+    match name {
+        "search_notes" => {
+            let query = argument("query").ok_or("Missing query")?;
+            let k = arguments.get("k").and_then(Value::as_u64).unwrap_or(5);
+            let chunks = state.context(query, k as usize);
+            Ok(serde_json::to_string_pretty(&chunks).unwrap())
+        }
+        "read_note" => {
+            let path = argument("path").ok_or("Missing path")?;
+            if !state.index.iter().any(|doc| doc.rel_path == path) {
+                return Err(format!("No such note \"{path}\""));
+            }
+            fs::read_to_string(state.content_path.join(path))
+                .map_err(|e| format!("Failed to read \"{path}\": {e}"))
+        }
+        _ => Err(format!("No such tool \"{name}\"")),
+    }
+}
+
+/// The response to a JSON-RPC request, or `None` for a notification.
+fn handle(state: &SrvState, message: &Value) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let params = message.get("params").cloned().unwrap_or_default();
+    let result = match message.get("method").and_then(Value::as_str) {
+        Some("initialize") => json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "notes", "version": env!("CARGO_PKG_VERSION") },
+        }),
+        Some("ping") => json!({}),
+        Some("tools/list") => json!({ "tools": tools() }),
+        Some("tools/call") => {
+            let name = params["name"].as_str().unwrap_or_default();
+            let (text, is_error) = match call(state, name, &params["arguments"]) {
+                Ok(text) => (text, false),
+                Err(text) => (text, true),
+            };
+            json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+        }
+        method => {
+            return Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("No such method {method:?}") },
+            }));
+        }
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+/// Serves the Model Context Protocol, a message per line, until `input` ends, so
+/// an assistant the owner runs can search and read their notes.
+pub fn serve(
+    state: &SrvState,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    info!("Serving MCP on stdin");
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(state, &message),
+            Err(e) => {
+                error!("Failed to parse MCP message: {e}");
+                Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": -32700, "message": e.to_string() },
+                }))
+            }
+        };
+        if let Some(response) = response {
+            writeln!(output, "{response}")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
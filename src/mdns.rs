@@ -0,0 +1,73 @@
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::{Deserialize, Serialize};
+
+/// Advertising the server to the local network with multicast DNS, so browsers
+/// and file managers there list it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// What it's listed as.
+    #[serde(default = "Config::default_name")]
+    pub name: String,
+}
+
+impl Config {
+    fn default_name() -> String {
+        String::from("Notes")
+    }
+}
+
+/// A host name made of `name`, such as `my-notes.local.`, which the advertisement
+/// answers for with the machine's addresses.
+fn host_name(name: &str) -> String {
+    let mut label = String::new();
+    for c in name.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() => label.push(c.to_ascii_lowercase()),
+            _ if !label.is_empty() && !label.ends_with('-') => label.push('-'),
+            _ => {}
+        }
+    }
+    label.truncate(63);
+    let label = label.trim_end_matches('-');
+    match label.is_empty() {
+        true => String::from("notes.local."),
+        false => format!("{label}.local."),
+    }
+}
+
+/// Advertises the server listening on `port` until the returned daemon is shut
+/// down, as `_https._tcp` with `tls` and `_http._tcp` otherwise.
+pub fn advertise(
+    config: &Config,
+    port: u16,
+    tls: bool,
+) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let kind = match tls {
+        true => "_https._tcp.local.",
+        false => "_http._tcp.local.",
+    };
+    let daemon = ServiceDaemon::new()?;
+    let service = ServiceInfo::new(
+        kind,
+        &config.name,
+        &host_name(&config.name),
+        "",
+        port,
+        &[("path", "/")][..],
+    )?
+    .enable_addr_auto();
+    daemon.register(service)?;
+    Ok(daemon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_names() {
+        assert_eq!(host_name("My Notes!"), "my-notes.local.");
+        assert_eq!(host_name("  Café -- Notes "), "caf-notes.local.");
+        assert_eq!(host_name("日記"), "notes.local.");
+    }
+}
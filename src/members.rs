@@ -0,0 +1,34 @@
+/// Written on a line of its own in a note, hides the rest of it from readers who
+/// aren't signed in or given a share token.
+pub const MARKER: &str = "<!-- members -->";
+
+/// Where readers see the note end.
+const SIGN_IN: &str = r#"<div class="members-only"><p>The rest of this note is for members.</p><p><a href="/login">Sign in to continue</a></p></div>"#;
+
+/// The part of `text`, markdown or HTML, before the marker.
+pub fn public(text: &str) -> &str {
+    text.split_once(MARKER).map_or(text, |(public, _)| public)
+}
+
+/// Rendered `html`, cut at the marker with a way to sign in after it.
+pub fn gate(html: String) -> String {
+    match html.split_once(MARKER) {
+        Some((public, _)) => format!("{public}{SIGN_IN}"),
+        None => html,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gating() {
+        let html = format!("<p>Intro</p>\n{MARKER}\n<p>Secret</p>");
+        assert_eq!(public(&html), "<p>Intro</p>\n");
+        let gated = gate(html);
+        assert!(gated.starts_with("<p>Intro</p>\n<div class=\"members-only\">"));
+        assert!(!gated.contains("Secret"));
+        assert_eq!(gate(String::from("<p>All</p>")), "<p>All</p>");
+    }
+}
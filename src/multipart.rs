@@ -0,0 +1,116 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Content-Type has no multipart boundary")]
+    NoBoundary,
+    #[error("multipart body is malformed")]
+    Malformed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part<'a> {
+    pub name:         Option<&'a str>,
+    pub filename:     Option<&'a str>,
+    pub content_type: Option<&'a str>,
+    pub data:         &'a [u8],
+}
+
+/// Extracts the boundary from a `multipart/form-data` Content-Type header value.
+pub fn boundary(content_type: &str) -> Result<&str, Error> {
+    let (mime, params) = content_type.split_once(';').ok_or(Error::NoBoundary)?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return Err(Error::NoBoundary);
+    }
+    params
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"'))
+        .ok_or(Error::NoBoundary)
+}
+
+/// Parses a `multipart/form-data` body.
+pub fn parse<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<Part<'a>>, Error> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+
+    let mut parts = Vec::new();
+    let mut rest =
+        &body[find(body, delimiter).ok_or(Error::Malformed)? + delimiter.len()..];
+    loop {
+        if rest.starts_with(b"--") {
+            break;
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or(Error::Malformed)?;
+        let end = find(rest, delimiter).ok_or(Error::Malformed)?;
+        // The CRLF before the delimiter belongs to the delimiter.
+        let part = rest[..end].strip_suffix(b"\r\n").ok_or(Error::Malformed)?;
+        parts.push(parse_part(part)?);
+        rest = &rest[end + delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+fn parse_part(part: &[u8]) -> Result<Part<'_>, Error> {
+    let split = find(part, b"\r\n\r\n").ok_or(Error::Malformed)?;
+    let headers = std::str::from_utf8(&part[..split]).map_err(|_| Error::Malformed)?;
+    let mut parsed = Part {
+        name:         None,
+        filename:     None,
+        content_type: None,
+        data:         &part[split + 4..],
+    };
+    for header in headers.split("\r\n") {
+        let Some((key, value)) = header.split_once(':') else {
+            continue;
+        };
+        if key.eq_ignore_ascii_case("Content-Type") {
+            parsed.content_type = Some(value.trim());
+        } else if key.eq_ignore_ascii_case("Content-Disposition") {
+            for param in value.split(';').skip(1) {
+                match param.trim().split_once('=') {
+                    Some(("name", v)) => parsed.name = Some(v.trim_matches('"')),
+                    Some(("filename", v)) => parsed.filename = Some(v.trim_matches('"')),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_data() {
+        let content_type = "multipart/form-data; boundary=\"XyZ\"";
+        let body = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"cat.png\"\r\n\
+            Content-Type: image/png\r\n\
+            \r\n\
+            \x89PNG\r\n--not the end\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"alt\"\r\n\
+            \r\n\
+            A cat\r\n\
+            --XyZ--\r\n";
+        let parts = parse(body, boundary(content_type).unwrap()).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, Some("file"));
+        assert_eq!(parts[0].filename, Some("cat.png"));
+        assert_eq!(parts[0].content_type, Some("image/png"));
+        assert_eq!(parts[0].data, b"\x89PNG\r\n--not the end");
+        assert_eq!(parts[1].name, Some("alt"));
+        assert_eq!(parts[1].filename, None);
+        assert_eq!(parts[1].data, b"A cat");
+
+        assert!(boundary("text/plain").is_err());
+    }
+}
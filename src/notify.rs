@@ -0,0 +1,213 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Request(#[from] Box<ureq::Error>),
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+}
+
+/// Where notifications are posted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Target {
+    /// An ntfy topic, such as `https://ntfy.sh/my-notes`.
+    Ntfy {
+        url:   Url,
+        /// An access token, for protected topics.
+        token: Option<String>,
+    },
+    /// A Matrix room, posted to with the client API as the user `token` is for.
+    Matrix {
+        homeserver: Url,
+        /// The room's ID, such as `!abc123:matrix.org`.
+        room:       String,
+        token:      String,
+    },
+}
+
+/// Which topic or room it is, without the credentials.
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ntfy { url, .. } => write!(f, "{url}"),
+            Self::Matrix { room, .. } => write!(f, "{room}"),
+        }
+    }
+}
+
+/// A target, and which newly published notes it's told about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Notes with any of these tags. Every note is, without tags or dirs.
+    #[serde(default)]
+    pub tags:   Vec<String>,
+    /// Notes in any of these directories of the content directory.
+    #[serde(default)]
+    pub dirs:   Vec<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, rel_path: &str, tags: &[String]) -> bool {
+        let tagged = self
+            .tags
+            .iter()
+            .any(|x| tags.iter().any(|tag| tag.eq_ignore_ascii_case(x)));
+        let within = self.dirs.iter().any(|dir| {
+            let dir = dir.trim_matches('/');
+            rel_path
+                .strip_prefix(dir)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        (self.tags.is_empty() && self.dirs.is_empty()) || tagged || within
+    }
+}
+
+/// A newly published note.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub title:    String,
+    pub url:      String,
+    pub rel_path: String,
+    pub tags:     Vec<String>,
+}
+
+/// The message telling about `notes`: a line with each one's title and URL.
+fn digest(notes: &[&Note]) -> String {
+    let lines: Vec<_> = notes
+        .iter()
+        .map(|note| format!("{} {}", note.title, note.url))
+        .collect();
+    lines.join("\n")
+}
+
+/// Where a message with the transaction ID `txn` is sent to a Matrix `room`.
+fn matrix_url(homeserver: &Url, room: &str, txn: &str) -> Result<Url, url::ParseError> {
+    homeserver.join(&format!(
+        "/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+        crate::uri::percent_encode(room),
+        crate::uri::percent_encode(txn)
+    ))
+}
+
+fn send(target: &Target, title: &str, message: &str) -> Result<(), Error> {
+    let timeout = Duration::from_secs(30);
+    match target {
+        Target::Ntfy { url, token } => {
+            let mut request = ureq::post(url.as_str())
+                .timeout(timeout)
+                .set("Title", title);
+            if let Some(token) = token {
+                request = request.set("Authorization", &format!("Bearer {token}"));
+            }
+            request.send_string(message).map_err(Box::new)?;
+        }
+        Target::Matrix {
+            homeserver,
+            room,
+            token,
+        } => {
+            let txn = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .to_string();
+            let body =
+                json!({ "msgtype": "m.text", "body": format!("{title}\n{message}") });
+            ureq::put(matrix_url(homeserver, room, &txn)?.as_str())
+                .timeout(timeout)
+                .set("Authorization", &format!("Bearer {token}"))
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string())
+                .map_err(Box::new)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tells each of `rules` about the `notes` it matches, on a thread of its own.
+pub fn notify(rules: &[Rule], notes: &[Note]) {
+    let messages: Vec<_> = rules
+        .iter()
+        .filter_map(|rule| {
+            let matching: Vec<_> = notes
+                .iter()
+                .filter(|note| rule.matches(&note.rel_path, &note.tags))
+                .collect();
+            let title = match matching.len() {
+                0 => return None,
+                1 => String::from("New note"),
+                n => format!("{n} new notes"),
+            };
+            Some((rule.target.clone(), title, digest(&matching)))
+        })
+        .collect();
+    if messages.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || {
+        for (target, title, message) in messages {
+            match send(&target, &title, &message) {
+                Ok(()) => info!("Notified {target} of {title}"),
+                Err(e) => warn!("Failed to notify {target}: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let target = Target::Ntfy {
+            url:   Url::parse("https://ntfy.sh/notes").unwrap(),
+            token: None,
+        };
+        let rule = Rule {
+            target: target.clone(),
+            tags:   vec![String::from("Family")],
+            dirs:   vec![String::from("/blog/")],
+        };
+        let tags = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert!(rule.matches("a.md", &tags(&["family"])));
+        assert!(rule.matches("blog/a.md", &[]));
+        assert!(!rule.matches("blogging/a.md", &tags(&["work"])));
+        let everything = Rule {
+            target,
+            tags: Vec::new(),
+            dirs: Vec::new(),
+        };
+        assert!(everything.matches("a.md", &[]));
+
+        let note = Note {
+            title:    String::from("Trip"),
+            url:      String::from("https://notes.example.com/note/trip.md"),
+            rel_path: String::from("trip.md"),
+            tags:     Vec::new(),
+        };
+        assert_eq!(
+            digest(&[&note, &note]),
+            "Trip https://notes.example.com/note/trip.md\nTrip https://notes.example.com/note/trip.md"
+        );
+        assert_eq!(
+            matrix_url(
+                &Url::parse("https://matrix.org").unwrap(),
+                "!abc:matrix.org",
+                "1"
+            )
+            .unwrap()
+            .as_str(),
+            "https://matrix.org/_matrix/client/v3/rooms/%21abc%3Amatrix.org/send/m.room.message/1"
+        );
+    }
+}
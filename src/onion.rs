@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Serving the site as a Tor onion service, which Tor forwards to a listener of its
+/// own. Requests reaching it are answered with links to the onion address instead
+/// of `base_url`, and forwarding headers are never believed, since Tor passes on
+/// whatever the client sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Where Tor forwards requests to, such as `127.0.0.1:3001`. Nothing but Tor
+    /// should be able to reach it.
+    pub bind: SocketAddr,
+    /// Tor's `HiddenServiceDir` for the service, where it keeps its keys and writes
+    /// its address to `hostname`.
+    pub dir:  PathBuf,
+}
+
+impl Config {
+    /// The lines of a `torrc` publishing the service.
+    pub fn torrc(&self) -> String {
+        format!(
+            "HiddenServiceDir {}\nHiddenServicePort 80 {}\n",
+            self.dir.display(),
+            self.bind
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torrc() {
+        let config = Config {
+            bind: "127.0.0.1:3001".parse().unwrap(),
+            dir:  PathBuf::from("/var/lib/tor/notes"),
+        };
+        assert_eq!(
+            config.torrc(),
+            "HiddenServiceDir /var/lib/tor/notes\nHiddenServicePort 80 127.0.0.1:3001\n"
+        );
+    }
+}
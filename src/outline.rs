@@ -0,0 +1,148 @@
+use crate::escape_html;
+
+/// Part of a note: its heading and what follows it up to the next heading.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Section<'a> {
+    /// How many `#` the heading has, or 0 for the text before the first heading.
+    pub level: usize,
+    pub title: &'a str,
+    /// The markdown after the heading, without the sections nested in it.
+    pub body:  &'a str,
+}
+
+/// The level and title of an ATX heading, such as `## Setup ##`.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, title))
+}
+
+/// Splits a note's markdown at its headings, ignoring `#` lines in code blocks.
+/// Text before the first heading makes a section of level 0, unless there's none.
+pub fn sections(md: &str) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut current = (0, "", 0);
+    let mut fence = None;
+    let mut at = 0;
+    for line in md.split_inclusive('\n') {
+        let start = at;
+        at += line.len();
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        let Some((level, title)) = heading(line.trim_end()) else {
+            continue;
+        };
+        let (level_before, title_before, body_start) = current;
+        let body = &md[body_start..start];
+        if level_before > 0 || !body.trim().is_empty() {
+            sections.push(Section {
+                level: level_before,
+                title: title_before,
+                body,
+            });
+        }
+        current = (level, title, at);
+    }
+    let (level, title, body_start) = current;
+    let body = &md[body_start..];
+    if level > 0 || !body.trim().is_empty() {
+        sections.push(Section { level, title, body });
+    }
+    sections
+}
+
+/// Loads each section of an outline the first time it's opened.
+const SCRIPT: &str = r#"<script>
+document.querySelectorAll("details.section").forEach($section => {
+    $section.addEventListener("toggle", () => {
+        const $body = $section.querySelector(":scope > div");
+        if (!$section.open || $body.dataset.loaded) return;
+        $body.dataset.loaded = "true";
+        fetch($section.dataset.src)
+            .then(r => r.ok ? r.text() : Promise.reject(r.status))
+            .then(html => $body.innerHTML = html)
+            .catch(() => {
+                $body.textContent = "Failed to load this section.";
+                delete $body.dataset.loaded;
+            });
+    });
+});
+</script>"#;
+
+/// The headings of the note at `rel_path` as nested sections, folded, each loading
+/// its text from `/api/note/<path>/section/<n>` with `query` when it's opened.
+pub fn html(rel_path: &str, sections: &[Section], query: &str) -> String {
+    let mut html = format!(r#"<p><a href="/note/{rel_path}{query}">Read it all</a></p>"#);
+    let mut open = Vec::new();
+    for (n, section) in sections.iter().enumerate() {
+        while open.last().is_some_and(|&level| level >= section.level) {
+            html.push_str("</details>");
+            open.pop();
+        }
+        let title = match section.level {
+            0 => String::from("Introduction"),
+            _ => escape_html(section.title),
+        };
+        html.push_str(&format!(
+            r#"<details class="section" data-src="/api/note/{rel_path}/section/{n}{query}"><summary>{title}</summary><div></div>"#
+        ));
+        open.push(section.level);
+    }
+    html.push_str(&"</details>".repeat(open.len()));
+    html + SCRIPT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitting() {
+        let md = "Intro\n# Setup #\nInstall it.\n```sh\n# not a heading\n```\n\
+                  ## Linux\nUse a package.\n    # indented code\n#hashtag\n# Use\n";
+        assert_eq!(
+            sections(md),
+            [
+                Section {
+                    level: 0,
+                    title: "",
+                    body:  "Intro\n",
+                },
+                Section {
+                    level: 1,
+                    title: "Setup",
+                    body:  "Install it.\n```sh\n# not a heading\n```\n",
+                },
+                Section {
+                    level: 2,
+                    title: "Linux",
+                    body:  "Use a package.\n    # indented code\n#hashtag\n",
+                },
+                Section {
+                    level: 1,
+                    title: "Use",
+                    body:  "",
+                },
+            ]
+        );
+        assert_eq!(sections("# Only\n")[0].title, "Only");
+        assert!(sections("\n").is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use log::warn;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// What the names of environment variables overriding settings start with. The
+/// rest is the setting's name in upper case, with `__` between a table and a key
+/// in it, such as `NOTES_CONTENT_PATH` or `NOTES_COMPRESSION__GZIP`.
+pub const PREFIX: &str = "NOTES_";
+
+/// `config` with the settings `vars` name overridden by their values, read as TOML
+/// values or, failing that, as strings. Variables that don't make a valid config
+/// are ignored.
+pub fn apply<T: Serialize + DeserializeOwned>(
+    mut config: T,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> T {
+    let mut table = match toml::Value::try_from(&config) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => return config,
+    };
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(PREFIX) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let key: Vec<_> = key.split("__").collect();
+        let typed = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut x| x.remove("value"));
+        let applied = typed
+            .into_iter()
+            .chain([toml::Value::String(raw.clone())])
+            .find_map(|value| {
+                let mut overridden = table.clone();
+                set(&mut overridden, &key, value)?;
+                let parsed = toml::Value::Table(overridden.clone())
+                    .try_into::<T>()
+                    .ok()?;
+                Some((overridden, parsed))
+            });
+        match applied {
+            Some((overridden, parsed)) => (table, config) = (overridden, parsed),
+            None => warn!("Ignoring ${name}, which isn't a valid setting"),
+        }
+    }
+    config
+}
+
+/// Sets the value at `key`, a path through nested tables, creating the tables
+/// that are missing. Fails if something other than a table is in the way.
+fn set(table: &mut toml::Table, key: &[&str], value: toml::Value) -> Option<()> {
+    let (last, tables) = key.split_last()?;
+    let mut table = table;
+    for name in tables {
+        table = table
+            .entry(name.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()?;
+    }
+    table.insert(last.to_string(), value);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        bind:      String,
+        workers:   usize,
+        api_token: Option<String>,
+        #[serde(default)]
+        search:    Search,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Search {
+        stem: bool,
+    }
+
+    #[test]
+    fn overriding() {
+        let vars = [
+            ("NOTES_BIND", "0.0.0.0:80"),
+            ("NOTES_WORKERS", "4"),
+            ("NOTES_API_TOKEN", "123456"),
+            ("NOTES_SEARCH__STEM", "true"),
+            ("NOTES_WORKERS", "many"),
+            ("HOME", "/root"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        assert_eq!(
+            apply(Config::default(), vars),
+            Config {
+                bind:      String::from("0.0.0.0:80"),
+                workers:   4,
+                api_token: Some(String::from("123456")),
+                search:    Search { stem: true },
+            }
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{IndexedDocument, NoteKind, escape_html, store, uri, usage};
+use rinja::Template;
+
+/// Columns every board has, even when empty. Notes without a status are in the
+/// first one.
+const COLUMNS: [&str; 3] = ["todo", "doing", "done"];
+
+/// Renders the notes tagged `tag` as a kanban board with a column for each status.
+/// The owner gets buttons to move notes between columns.
+pub fn board(index: &[IndexedDocument], tag: &str, owner: bool) -> String {
+    let notes: Vec<_> = index
+        .iter()
+        .filter(|doc| !doc.unlisted)
+        .filter(|doc| doc.tags.iter().any(|x| x.eq_ignore_ascii_case(tag)))
+        .collect();
+    let status = |doc: &IndexedDocument| {
+        doc.status
+            .as_deref()
+            .map(str::to_lowercase)
+            .unwrap_or_else(|| COLUMNS[0].to_string())
+    };
+    let mut columns: Vec<String> = COLUMNS.map(String::from).to_vec();
+    for doc in &notes {
+        let status = status(doc);
+        if !columns.contains(&status) {
+            columns.push(status);
+        }
+    }
+
+    let mut html = String::from(r#"<div class="board">"#);
+    for column in &columns {
+        html.push_str(&format!("<section><h2>{}</h2><ul>", escape_html(column)));
+        for doc in notes.iter().filter(|doc| status(doc) == *column) {
+            html.push_str(&format!(
+                r#"<li><a href="/note/{path}">{title}</a>"#,
+                path = doc.rel_path,
+                title = escape_html(&doc.title),
+            ));
+            if owner {
+                html.push_str(&format!(
+                    r#"<form method="post" action="/api/status/{path}"><input type="hidden" name="tag" value="{tag}">"#,
+                    path = doc.rel_path,
+                    tag = escape_html(tag),
+                ));
+                for other in columns.iter().filter(|x| *x != column) {
+                    html.push_str(&format!(
+                        r#"<button name="status" value="{other}">→ {other}</button>"#,
+                        other = escape_html(other),
+                    ));
+                }
+                html.push_str("</form>");
+            }
+            html.push_str("</li>");
+        }
+        html.push_str("</ul></section>");
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// Lists the notes, except micro-posts, longest unreviewed first, counting from
+/// when they were written if they never were, with buttons to mark them reviewed.
+pub fn review(index: &[IndexedDocument]) -> String {
+    let mut notes: Vec<_> = index
+        .iter()
+        .filter(|doc| doc.kind != NoteKind::Micro)
+        .collect();
+    notes.sort_by_key(|doc| doc.reviewed.unwrap_or(doc.created));
+    let mut items = String::new();
+    for doc in notes {
+        let reviewed = match doc.reviewed {
+            Some(date) => format!(r#"reviewed <time datetime="{date}">{date}</time>"#),
+            None => format!(
+                r#"never reviewed, written <time datetime="{date}">{date}</time>"#,
+                date = doc.created
+            ),
+        };
+        items.push_str(&format!(
+            r#"<li><a href="/note/{path}">{title}</a> - {reviewed} <form method="post" action="/api/review/{path}"><button>Mark reviewed</button></form></li>"#,
+            path = doc.rel_path,
+            title = escape_html(&doc.title),
+        ));
+    }
+    match items.is_empty() {
+        true => String::from("<p>Nothing to review.</p>"),
+        false => format!(r#"<ol class="review">{items}</ol>"#),
+    }
+}
+
+/// A button adding the note to the reading list, for the `owner`.
+pub fn queue_form(doc: &IndexedDocument, owner: bool) -> String {
+    match owner {
+        true => format!(
+            r#" <form class="queue" method="post" action="/api/queue/{path}"><button name="action" value="add">Read later</button></form>"#,
+            path = doc.rel_path,
+        ),
+        false => String::new(),
+    }
+}
+
+/// Lists the notes on the reading list, in order, with buttons to move them up and
+/// down and to take them off it.
+pub fn queue(index: &[IndexedDocument], queue: &[String]) -> String {
+    let mut items = String::new();
+    for rel_path in queue {
+        // Notes deleted since they were added are left out.
+        let Some(doc) = index.iter().find(|doc| doc.rel_path == *rel_path) else {
+            continue;
+        };
+        items.push_str(&format!(
+            r#"<li><a href="/note/{path}">{title}</a> <form method="post" action="/api/queue/{path}"><button name="action" value="up">↑</button><button name="action" value="down">↓</button><button name="action" value="remove">Remove</button></form></li>"#,
+            path = doc.rel_path,
+            title = escape_html(&doc.title),
+        ));
+    }
+    match items.is_empty() {
+        true => String::from("<p>Nothing to read.</p>"),
+        false => format!(r#"<ol class="queue">{items}</ol>"#),
+    }
+}
+
+/// A form editing the text of the file at `rel_path`.
+#[derive(Template)]
+#[template(
+    ext = "html",
+    escape = "none",
+    source = r#"
+        <form class="editor" method="post" action="/edit/{{ rel_path|e("html") }}">
+            <textarea name="text" rows="30" autofocus>{{ text|e("html") }}</textarea>
+            <p><button>Save</button> <a href="/note/{{ rel_path|e("html") }}">Cancel</a></p>
+        </form>
+        "#
+)]
+struct EditorTemplate<'a> {
+    rel_path: &'a str,
+    text:     &'a str,
+}
+
+/// A form editing `text`, that of the file at `rel_path`.
+pub fn editor(rel_path: &str, text: &str) -> String {
+    EditorTemplate {
+        rel_path,
+        // Browsers drop a newline right after `<textarea>`, so one is added for them
+        // to drop instead of the text's own.
+        text: &format!("\n{text}"),
+    }
+    .render()
+    .unwrap()
+}
+
+/// Shows how much disk the notes and data take out of the `quota`, the most
+/// common searches, and the most common ones that found nothing.
+pub fn stats(store: &store::Store, usage: usage::Usage, quota: Option<u64>) -> String {
+    fn table<'a>(
+        queries: impl Iterator<Item = (&'a String, &'a store::QueryStats)>,
+    ) -> String {
+        let mut html = String::from(
+            "<table><tr><th>Query</th><th>Searches</th><th>Results</th><th>Last searched</th></tr>",
+        );
+        for (query, stats) in queries.take(50) {
+            html.push_str(&format!(
+                r#"<tr><td><a href="/search?q={url}">{query}</a></td><td>{count}</td><td>{results}</td><td><time datetime="{date}">{date}</time></td></tr>"#,
+                url = uri::percent_encode(query),
+                query = escape_html(query),
+                count = stats.count,
+                results = stats.results,
+                date = stats.last_seen,
+            ));
+        }
+        html.push_str("</table>");
+        html
+    }
+
+    let mut page = format!(
+        "<h2>Disk usage</h2><p>Notes take {}, and data {}",
+        usage::bytes(usage.content),
+        usage::bytes(usage.data)
+    );
+    if let Some(quota) = quota {
+        page.push_str(&format!(", of a quota of {}", usage::bytes(quota)));
+    }
+    page.push_str(".</p>");
+    page.push_str(&usage::chart(&store.usage, quota));
+    let mut queries: Vec<_> = store.queries.iter().collect();
+    queries.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+    page.push_str("<h2>Searches without results</h2>");
+    page.push_str(&table(
+        queries.iter().copied().filter(|(_, x)| x.results == 0),
+    ));
+    page.push_str("<h2>Frequent searches</h2>");
+    page.push_str(&table(queries.iter().copied()));
+    page
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// WebAssembly plugins transforming notes' HTML, built for WASI as commands: each
+/// reads a note's HTML on stdin and writes the changed HTML to stdout, getting the
+/// note's path as its first argument. Plugins run in order, with no access to
+/// files, the network, the environment or the clock beyond what WASI always has.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The plugins' `.wasm` files.
+    pub paths:  Vec<PathBuf>,
+    /// How much work a plugin may do for a note, in units of fuel, which is about
+    /// one per instruction.
+    pub fuel:   u64,
+    /// Most memory a plugin may use, and most output it may write, in bytes.
+    pub memory: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            paths:  Vec::new(),
+            fuel:   1_000_000_000,
+            memory: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+struct State {
+    wasi:   wasmtime_wasi::preview1::WasiP1Ctx,
+    limits: wasmtime::StoreLimits,
+}
+
+/// The plugins, compiled.
+#[derive(Default)]
+pub struct Plugins {
+    #[cfg(feature = "plugins")]
+    config:  Config,
+    #[cfg(feature = "plugins")]
+    engine:  wasmtime::Engine,
+    #[cfg(feature = "plugins")]
+    linker:  Option<wasmtime::Linker<State>>,
+    #[cfg(feature = "plugins")]
+    modules: Vec<(String, wasmtime::Module)>,
+}
+
+#[cfg(feature = "plugins")]
+impl Plugins {
+    /// Compiles the plugins, leaving out any that fail to.
+    pub fn load(config: Config) -> Self {
+        use log::{error, info};
+
+        if config.paths.is_empty() {
+            return Self::default();
+        }
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = match wasmtime::Engine::new(&engine_config) {
+            Ok(engine) => engine,
+            Err(e) => {
+                error!("Failed to start the plugin runtime: {e:#}");
+                return Self::default();
+            }
+        };
+        let mut linker = wasmtime::Linker::new(&engine);
+        if let Err(e) = wasmtime_wasi::preview1::add_to_linker_sync(
+            &mut linker,
+            |state: &mut State| &mut state.wasi,
+        ) {
+            error!("Failed to link WASI for plugins: {e:#}");
+            return Self::default();
+        }
+        let modules = config
+            .paths
+            .iter()
+            .filter_map(|path| match wasmtime::Module::from_file(&engine, path) {
+                Ok(module) => {
+                    info!("Loaded plugin \"{path:?}\"");
+                    Some((path.display().to_string(), module))
+                }
+                Err(e) => {
+                    error!("Failed to load plugin \"{path:?}\": {e:#}");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            config,
+            engine,
+            linker: Some(linker),
+            modules,
+        }
+    }
+
+    fn run(
+        &self,
+        module: &wasmtime::Module,
+        rel_path: &str,
+        html: String,
+    ) -> wasmtime::Result<String> {
+        use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+
+        let linker = self.linker.as_ref().expect("plugins are linked");
+        let stdout = MemoryOutputPipe::new(self.config.memory);
+        let wasi = wasmtime_wasi::WasiCtxBuilder::new()
+            .stdin(MemoryInputPipe::new(html))
+            .stdout(stdout.clone())
+            .args(&["plugin", rel_path])
+            .build_p1();
+        let limits = wasmtime::StoreLimitsBuilder::new()
+            .memory_size(self.config.memory)
+            .build();
+        let mut store = wasmtime::Store::new(&self.engine, State { wasi, limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.config.fuel)?;
+        let instance = linker.instantiate(&mut store, module)?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+        if let Err(e) = start.call(&mut store, ()) {
+            match e.downcast_ref::<wasmtime_wasi::I32Exit>() {
+                Some(wasmtime_wasi::I32Exit(0)) => {}
+                _ => return Err(e),
+            }
+        }
+        drop(store);
+        Ok(String::from_utf8(stdout.contents().to_vec())?)
+    }
+
+    /// Passes a note's HTML through every plugin, skipping those that fail.
+    pub fn transform(&self, rel_path: &str, mut html: String) -> String {
+        for (name, module) in &self.modules {
+            match self.run(module, rel_path, html.clone()) {
+                Ok(transformed) => html = transformed,
+                Err(e) => {
+                    log::error!("Plugin \"{name}\" failed on \"{rel_path}\": {e:#}")
+                }
+            }
+        }
+        html
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+impl Plugins {
+    pub fn load(config: Config) -> Self {
+        if !config.paths.is_empty() {
+            log::warn!("Ignoring plugins, which this build doesn't support");
+        }
+        Self {}
+    }
+
+    pub fn transform(&self, _: &str, html: String) -> String {
+        html
+    }
+}
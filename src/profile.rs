@@ -0,0 +1,101 @@
+use crate::calendar::{escape, push_line};
+use crate::escape_html;
+use serde::Deserialize;
+
+/// The note whose meta holds the site owner's profile, relative to the content
+/// directory.
+pub const NOTE: &str = "about.md";
+
+/// The `[profile]` table in the meta of [`NOTE`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub name:   String,
+    /// URL of a picture of the owner.
+    pub avatar: Option<String>,
+    pub email:  Option<String>,
+    /// Other places the owner can be found, such as a Mastodon or GitHub profile.
+    #[serde(default)]
+    pub links:  Vec<String>,
+}
+
+impl Profile {
+    /// Renders the profile as an h-card, with `bio` (the profile note's `desc`) as
+    /// its note.
+    pub fn h_card(&self, bio: Option<&str>) -> String {
+        let mut html = String::from(r#"<div class="h-card">"#);
+        if let Some(avatar) = &self.avatar {
+            html.push_str(&format!(
+                r#"<img class="u-photo" src="{}" alt="">"#,
+                escape_html(avatar)
+            ));
+        }
+        html.push_str(&format!(
+            r#"<a class="p-name u-url u-uid" href="/">{}</a>"#,
+            escape_html(&self.name)
+        ));
+        if let Some(bio) = bio {
+            html.push_str(&format!(r#"<p class="p-note">{}</p>"#, escape_html(bio)));
+        }
+        html.push_str("<ul>");
+        if let Some(email) = &self.email {
+            html.push_str(&format!(
+                r#"<li><a class="u-email" href="mailto:{email}">{email}</a></li>"#,
+                email = escape_html(email)
+            ));
+        }
+        for link in &self.links {
+            html.push_str(&format!(
+                r#"<li><a class="u-url" rel="me" href="{link}">{link}</a></li>"#,
+                link = escape_html(link)
+            ));
+        }
+        html.push_str("</ul></div>");
+        html
+    }
+
+    pub fn vcard(&self, bio: Option<&str>) -> String {
+        let mut vcard = String::new();
+        push_line(&mut vcard, "BEGIN:VCARD");
+        push_line(&mut vcard, "VERSION:4.0");
+        push_line(&mut vcard, &format!("FN:{}", escape(&self.name)));
+        if let Some(avatar) = &self.avatar {
+            push_line(&mut vcard, &format!("PHOTO:{avatar}"));
+        }
+        if let Some(email) = &self.email {
+            push_line(&mut vcard, &format!("EMAIL:{}", escape(email)));
+        }
+        for link in &self.links {
+            push_line(&mut vcard, &format!("URL:{link}"));
+        }
+        if let Some(bio) = bio {
+            push_line(&mut vcard, &format!("NOTE:{}", escape(bio)));
+        }
+        push_line(&mut vcard, "END:VCARD");
+        vcard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vcard() {
+        let profile = Profile {
+            name:   String::from("Ada, Countess"),
+            avatar: None,
+            email:  Some(String::from("ada@example.com")),
+            links:  vec![String::from("https://example.com/@ada")],
+        };
+        assert_eq!(
+            profile.vcard(Some("Writes notes")),
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ada\\, Countess\r\nEMAIL:ada@example.com\r\n\
+             URL:https://example.com/@ada\r\nNOTE:Writes notes\r\nEND:VCARD\r\n"
+        );
+        assert!(
+            profile.h_card(None).contains(
+                r#"<a class="u-url" rel="me" href="https://example.com/@ada">"#
+            )
+        );
+    }
+}
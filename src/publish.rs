@@ -0,0 +1,427 @@
+use chrono::Utc;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use url::Url;
+
+/// Most pages crawled, in case the links never end.
+const MAX_PAGES: usize = 10_000;
+/// Largest page read, in bytes.
+const LIMIT: u64 = 64 * 1024 * 1024;
+/// Where crawling starts, besides the pages these link to.
+const START: [&str; 4] = ["/", "/feed.xml", "/atom.xml", "/sitemap.xml"];
+/// Paths that only work on a server.
+const DYNAMIC: [&str; 7] = [
+    "/api/", "/dav/", "/edit/", "/events", "/login", "/search", "/review",
+];
+/// Sent with pages the server doesn't say how to cache.
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("\"{0}\" isn't a target such as s3://bucket/prefix")]
+    Target(String),
+    #[error("{0} isn't set")]
+    Credentials(&'static str),
+    #[error("{0}: {1}")]
+    Request(String, Box<ureq::Error>),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid published list: {0}")]
+    Published(#[from] serde_json::Error),
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+}
+
+/// Where the site is published: a bucket, and a prefix for its keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("s3://")
+            .ok_or_else(|| Error::Target(s.into()))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(Error::Target(s.into()));
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+}
+
+/// A page of the site, as the server sent it.
+#[derive(Debug)]
+pub struct Page {
+    /// Its URL's path, decoded.
+    pub path:          String,
+    pub content_type:  String,
+    pub cache_control: String,
+    pub body:          Vec<u8>,
+}
+
+impl Page {
+    /// Changes whenever anything uploaded with the page does.
+    fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.body);
+        hasher.update(format!("\n{}\n{}", self.content_type, self.cache_control));
+        crate::hex(&hasher.finalize())
+    }
+}
+
+/// An S3-compatible bucket, and the credentials to write to it.
+#[derive(Debug)]
+pub struct Bucket {
+    pub target:     Target,
+    /// Such as `https://s3.us-east-1.amazonaws.com`. Buckets are addressed by path.
+    pub endpoint:   Url,
+    pub region:     String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl Bucket {
+    /// The bucket of `target`, with credentials from `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`. The endpoint and region, unless given, are
+    /// `AWS_ENDPOINT_URL` and `AWS_REGION`, or AWS's and `us-east-1`.
+    pub fn from_env(
+        target: Target,
+        endpoint: Option<Url>,
+        region: Option<String>,
+    ) -> Result<Self, Error> {
+        let var = |name| std::env::var(name).ok().filter(|x| !x.is_empty());
+        let region = region
+            .or_else(|| var("AWS_REGION"))
+            .unwrap_or_else(|| String::from("us-east-1"));
+        let endpoint = match endpoint {
+            Some(endpoint) => endpoint,
+            None => match var("AWS_ENDPOINT_URL") {
+                Some(endpoint) => Url::parse(&endpoint)?,
+                None => Url::parse(&format!("https://s3.{region}.amazonaws.com"))?,
+            },
+        };
+        Ok(Self {
+            target,
+            endpoint,
+            region,
+            access_key: var("AWS_ACCESS_KEY_ID")
+                .ok_or(Error::Credentials("AWS_ACCESS_KEY_ID"))?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")
+                .ok_or(Error::Credentials("AWS_SECRET_ACCESS_KEY"))?,
+        })
+    }
+
+    /// Uploads `page` to `key`, or deletes `key` when there's no page.
+    fn send(
+        &self,
+        agent: &ureq::Agent,
+        key: &str,
+        page: Option<&Page>,
+    ) -> Result<(), Error> {
+        let path: Vec<_> = [self.target.bucket.as_str()]
+            .into_iter()
+            .chain(key.split('/'))
+            .map(crate::uri::percent_encode)
+            .collect();
+        let path = format!("/{}", path.join("/"));
+        let url = self.endpoint.join(&path)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (host, _) => host.unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let time = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let body = page.map_or(&[][..], |page| &page.body);
+        let payload = crate::hex(&Sha256::digest(body));
+
+        let mut headers = BTreeMap::from([
+            ("host", host),
+            ("x-amz-content-sha256", payload.clone()),
+            ("x-amz-date", time.clone()),
+        ]);
+        if let Some(page) = page {
+            headers.insert("content-type", page.content_type.clone());
+            headers.insert("cache-control", page.cache_control.clone());
+        }
+        let method = match page {
+            Some(_) => "PUT",
+            None => "DELETE",
+        };
+        let signed: Vec<_> = headers.keys().copied().collect();
+        let signed = signed.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let canonical =
+            format!("{method}\n{path}\n\n{canonical_headers}\n{signed}\n{payload}");
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+            crate::hex(&Sha256::digest(canonical))
+        );
+        let signing = signing_key(&self.secret_key, &date, &self.region, "s3");
+        let signature = crate::hex(&hmac(&signing, to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed}, Signature={signature}",
+            self.access_key
+        );
+
+        let mut request = agent
+            .request(method, url.as_str())
+            .set("Authorization", &authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| **name != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::Request(format!("{method} {path}"), Box::new(e))),
+        }
+    }
+}
+
+/// Crawls the site served at `server`, from [`START`], following the links within
+/// it. Pages that fail are skipped.
+pub fn crawl(agent: &ureq::Agent, server: &Url) -> Vec<Page> {
+    let mut pages = Vec::new();
+    let mut queue: VecDeque<String> = START.iter().map(|x| x.to_string()).collect();
+    let mut seen: HashSet<String> = queue.iter().cloned().collect();
+    while let Some(path) = queue.pop_front() {
+        if pages.len() >= MAX_PAGES {
+            warn!("Stopped crawling after {MAX_PAGES} pages");
+            break;
+        }
+        let page = match fetch(agent, server, &path) {
+            Ok(page) => page,
+            Err(e) => {
+                warn!("Skipping {path}: {e}");
+                continue;
+            }
+        };
+        let text = String::from_utf8_lossy(&page.body);
+        for link in links(&text, server) {
+            if seen.insert(link.clone()) {
+                queue.push_back(link);
+            }
+        }
+        pages.push(page);
+    }
+    pages
+}
+
+fn fetch(agent: &ureq::Agent, server: &Url, path: &str) -> Result<Page, Error> {
+    let url = server.join(path)?;
+    let response = agent
+        .get(url.as_str())
+        .call()
+        .map_err(|e| Error::Request(format!("GET {path}"), Box::new(e)))?;
+    let content_type = response
+        .header("Content-Type")
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let cache_control = response
+        .header("Cache-Control")
+        .unwrap_or(CACHE_CONTROL)
+        .to_string();
+    let mut body = Vec::new();
+    response.into_reader().take(LIMIT).read_to_end(&mut body)?;
+    Ok(Page {
+        path: crate::uri::percent_decode(url.path()).unwrap_or_else(|| path.to_string()),
+        content_type,
+        cache_control,
+        body,
+    })
+}
+
+/// The paths on `server` that `text`, a page or a sitemap, links to and that can be
+/// published: without a query, and not only working on a server.
+fn links(text: &str, server: &Url) -> Vec<String> {
+    let mut links = Vec::new();
+    for marker in [r#"href=""#, r#"src=""#, "<loc>"] {
+        for rest in text.split(marker).skip(1) {
+            let Some(link) = rest.split(['"', '<']).next() else {
+                continue;
+            };
+            let link = link.replace("&amp;", "&");
+            let Ok(url) = server.join(&link) else {
+                continue;
+            };
+            // Sitemaps link to the public site, which is where this is going.
+            let same_site = url.origin() == server.origin() || marker == "<loc>";
+            let path = url.path();
+            if same_site
+                && url.query().is_none()
+                && !DYNAMIC.iter().any(|x| path.starts_with(x))
+            {
+                links.push(path.to_string());
+            }
+        }
+    }
+    links
+}
+
+/// The key the page at `path` is stored under: `path` after `prefix`, with pages
+/// ending in `/` stored as their `index.html`.
+fn key(prefix: &str, path: &str) -> String {
+    let mut key = String::from(prefix);
+    if !key.is_empty() {
+        key.push('/');
+    }
+    key.push_str(path.trim_start_matches('/'));
+    if key.is_empty() || key.ends_with('/') {
+        key.push_str("index.html");
+    }
+    key
+}
+
+/// Uploads the site served at `server` to `bucket`, skipping pages that haven't
+/// changed since the last time, and deleting pages that are gone. What's been
+/// uploaded is kept in `published`. Returns how many pages the site has and how
+/// many were sent.
+pub fn publish(
+    server: &Url,
+    bucket: &Bucket,
+    published: &Path,
+) -> Result<(usize, usize), Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(60))
+        .build();
+    let mut all: BTreeMap<String, BTreeMap<String, String>> = match fs::read(published) {
+        Ok(data) => serde_json::from_slice(&data)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let target = format!("s3://{}/{}", bucket.target.bucket, bucket.target.prefix);
+    let mut previous = all.remove(&target).unwrap_or_default();
+    let pages = crawl(&agent, server);
+
+    let mut current = BTreeMap::new();
+    let mut sent = 0;
+    let mut result = Ok(());
+    for page in &pages {
+        let key = key(&bucket.target.prefix, &page.path);
+        let hash = page.hash();
+        if previous.remove(&key).as_ref() == Some(&hash) {
+            current.insert(key, hash);
+            continue;
+        }
+        if let Err(e) = bucket.send(&agent, &key, Some(page)) {
+            result = Err(e);
+            break;
+        }
+        info!("Uploaded {key}");
+        current.insert(key, hash);
+        sent += 1;
+    }
+    if result.is_ok() {
+        for key in std::mem::take(&mut previous).into_keys() {
+            if let Err(e) = bucket.send(&agent, &key, None) {
+                warn!("Failed to delete {key}: {e}");
+                previous.insert(key, String::new());
+            } else {
+                info!("Deleted {key}");
+            }
+        }
+    }
+    // What wasn't sent, or couldn't be deleted, is tried again next time.
+    current.extend(previous.into_keys().map(|key| (key, String::new())));
+    all.insert(target, current);
+    if let Some(parent) = published.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(published, serde_json::to_vec_pretty(&all)?)?;
+    result.map(|()| (pages.len(), sent))
+}
+
+/// HMAC-SHA256 of `data` with `key`.
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|x| x ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// The key requests to `service` in `region` are signed with on `date`, for AWS
+/// Signature Version 4.
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = hmac(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            crate::hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // AWS's example of deriving a signing key.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            crate::hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn targets_and_keys() {
+        let target: Target = "s3://site/notes/".parse().unwrap();
+        assert_eq!(target.bucket, "site");
+        assert_eq!(target.prefix, "notes");
+        assert!("https://site".parse::<Target>().is_err());
+        assert_eq!(key("notes", "/"), "notes/index.html");
+        assert_eq!(key("", "/"), "index.html");
+        assert_eq!(key("", "/note/a b.md"), "note/a b.md");
+        assert_eq!(key("", "/tag/rust/"), "tag/rust/index.html");
+    }
+
+    #[test]
+    fn crawling() {
+        let server = Url::parse("http://127.0.0.1:3000").unwrap();
+        let html = r#"<a href="/note/a.md#top">A</a><img src="/note/b.png">
+            <a href="/?page=2">2</a><a href="/api/search">x</a>
+            <a href="https://example.com/">out</a><loc>https://notes.example.com/note/c.md</loc>"#;
+        assert_eq!(
+            links(html, &server),
+            ["/note/a.md", "/note/b.png", "/note/c.md"]
+        );
+    }
+}
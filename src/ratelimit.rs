@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Most clients remembered at once. Beyond it, those whose buckets have refilled
+/// are forgotten.
+const MAX_CLIENTS: usize = 4096;
+
+/// How many requests each client may make.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Requests a second a client may keep making.
+    pub per_second: f64,
+    /// Requests a client may make at once, after a quiet while.
+    pub burst:      u32,
+}
+
+/// A client's allowance, which refills at `per_second` up to `burst`.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens:  f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refilled(self, config: &Config, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * config.per_second).min(f64::from(config.burst))
+    }
+}
+
+/// Token buckets of the clients that made requests lately, by address.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl Limiter {
+    /// Takes a request from `addr`'s bucket at `now`, or says how long until there's
+    /// one to take.
+    pub fn check(
+        &mut self,
+        config: &Config,
+        addr: IpAddr,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        if self.buckets.len() >= MAX_CLIENTS {
+            let burst = f64::from(config.burst);
+            self.buckets
+                .retain(|_, bucket| bucket.refilled(config, now) < burst);
+        }
+        let bucket = self.buckets.entry(addr.to_canonical()).or_insert(Bucket {
+            tokens:  f64::from(config.burst),
+            updated: now,
+        });
+        let tokens = bucket.refilled(config, now);
+        bucket.updated = now;
+        if tokens >= 1.0 {
+            bucket.tokens = tokens - 1.0;
+            return Ok(());
+        }
+        bucket.tokens = tokens;
+        Err(
+            Duration::try_from_secs_f64((1.0 - tokens) / config.per_second)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiting() {
+        let config = Config {
+            per_second: 2.0,
+            burst:      3,
+        };
+        let mut limiter = Limiter::default();
+        let (a, b) = (
+            "192.0.2.1".parse().unwrap(),
+            "::ffff:192.0.2.2".parse().unwrap(),
+        );
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check(&config, a, now).is_ok());
+        }
+        assert_eq!(
+            limiter.check(&config, a, now),
+            Err(Duration::from_millis(500))
+        );
+        assert!(limiter.check(&config, b, now).is_ok());
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check(&config, a, later).is_ok());
+        assert!(limiter.check(&config, a, later).is_err());
+        let much_later = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check(&config, a, much_later).is_ok());
+        }
+        assert!(limiter.check(&config, a, much_later).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use log::warn;
+
+/// The file in the content directory that lists redirects, one `from to [status]`
+/// rule per line.
+pub const FILE: &str = "_redirects";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    /// The path to match. Ending it with `*` matches every path starting with the
+    /// rest, and the matched part replaces `:splat` in `to`.
+    pub from:   String,
+    pub to:     String,
+    pub status: u16,
+}
+
+/// Parses a `_redirects` file, skipping blank lines, `#` comments and (with a
+/// warning) lines that aren't rules.
+pub fn parse(text: &str) -> Vec<Redirect> {
+    let mut redirects = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (from, to, status) = match fields[..] {
+            [] => continue,
+            [from, to] => (from, to, Some(301)),
+            [from, to, status] => (from, to, status.parse().ok()),
+            _ => (line, line, None),
+        };
+        match status.filter(|x| (300..400).contains(x)) {
+            Some(status) if from.starts_with('/') => redirects.push(Redirect {
+                from: from.to_string(),
+                to: to.to_string(),
+                status,
+            }),
+            _ => warn!("Ignoring line {} of {FILE}: \"{}\"", n + 1, line.trim()),
+        }
+    }
+    redirects
+}
+
+/// The first redirect matching `path`, and where it sends it.
+pub fn find<'a>(redirects: &'a [Redirect], path: &str) -> Option<(&'a Redirect, String)> {
+    redirects.iter().find_map(|redirect| {
+        let to = match redirect.from.strip_suffix('*') {
+            Some(prefix) => {
+                let splat = path.strip_prefix(prefix)?;
+                redirect.to.replace(":splat", splat)
+            }
+            None if redirect.from == path => redirect.to.clone(),
+            None => return None,
+        };
+        Some((redirect, to))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects() {
+        let redirects = parse(
+            "\
+# Moved when switching engines
+/blog/*   /note/posts/:splat
+/old      /note/new.md  302
+
+/gone     /            418
+nonsense
+",
+        );
+        assert_eq!(redirects.len(), 2);
+        let (redirect, to) = find(&redirects, "/blog/2020/hello.md").unwrap();
+        assert_eq!(
+            (redirect.status, to.as_str()),
+            (301, "/note/posts/2020/hello.md")
+        );
+        let (redirect, to) = find(&redirects, "/old").unwrap();
+        assert_eq!((redirect.status, to.as_str()), (302, "/note/new.md"));
+        assert_eq!(find(&redirects, "/older"), None);
+        assert_eq!(find(&redirects, "/gone"), None);
+    }
+}
@@ -0,0 +1,189 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// What the store is replicated as, besides the notes under `content/`.
+pub const STORE: &str = "store.json";
+/// Largest list of changes read from the primary, in bytes.
+const LIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Keeping a copy of another server's notes and store, pulled from its
+/// `/api/replicate` by the `replicate` task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// The primary's URL, such as `https://notes.example.com`.
+    pub primary: url::Url,
+    /// The primary's `api_token`.
+    pub token:   String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Request(Box<ureq::Error>),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid changes: {0}")]
+    Changes(#[from] serde_json::Error),
+    #[error("invalid file contents: {0}")]
+    Contents(#[from] base64::DecodeError),
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+}
+
+/// What a primary has, and what changed on it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Changes {
+    /// When the primary started looking, in seconds since the Unix epoch, which
+    /// the next pull asks for changes since.
+    pub now:     u64,
+    /// The SHA-256 of every file replicated, by its name, such as `content/a.md`.
+    pub files:   BTreeMap<String, String>,
+    /// The base64 contents of the files modified since the time asked for.
+    pub changed: BTreeMap<String, String>,
+}
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|x| x.as_encoded_bytes().starts_with(b"."))
+}
+
+/// The files in `content` and the `store`, with those modified since `since`, in
+/// seconds since the Unix epoch. Hidden files are left out.
+pub fn changes(content: &Path, store: &Path, since: u64) -> io::Result<Changes> {
+    let mut changes = Changes {
+        now: unix(SystemTime::now()),
+        ..Changes::default()
+    };
+    let mut add = |entry: String, path: &Path| -> io::Result<()> {
+        let data = fs::read(path)?;
+        let hash = crate::backup::hash(&mut data.as_slice())?;
+        if unix(fs::metadata(path)?.modified()?) >= since {
+            changes
+                .changed
+                .insert(entry.clone(), STANDARD.encode(&data));
+        }
+        changes.files.insert(entry, hash);
+        Ok(())
+    };
+    crate::walk(content, &mut |is_dir, path| {
+        if is_hidden(path) || is_dir {
+            return Ok(!is_hidden(path));
+        }
+        let rel_path = path.strip_prefix(content).unwrap_or(path);
+        add(format!("content/{}", rel_path.to_string_lossy()), path)?;
+        Ok(true)
+    })?;
+    if store.exists() {
+        add(String::from(STORE), store)?;
+    }
+    Ok(changes)
+}
+
+/// Where the file named `entry` goes. `None` for anything that would end up
+/// outside of `content`.
+fn destination(entry: &str, content: &Path, store: &Path) -> Option<PathBuf> {
+    match entry {
+        STORE => Some(store.to_path_buf()),
+        entry => crate::backup::destination(entry, &[("content", content)]),
+    }
+}
+
+/// Brings `content` and `store` up to date with the primary, asking for what
+/// changed since the last pull, which is kept in `last`. Notes the primary doesn't
+/// have are removed. Returns how many files were written or removed.
+pub fn pull(
+    config: &Config,
+    content: &Path,
+    store: &Path,
+    last: &Path,
+) -> Result<usize, Error> {
+    let since = match fs::read_to_string(last) {
+        Ok(since) => since.trim().parse().unwrap_or(0),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    let mut url = config.primary.join("/api/replicate")?;
+    url.query_pairs_mut()
+        .append_pair("since", &since.to_string());
+    let response = ureq::get(url.as_str())
+        .set("Authorization", &format!("Bearer {}", config.token))
+        .timeout(Duration::from_secs(300))
+        .call()
+        .map_err(|e| Error::Request(Box::new(e)))?;
+    let changes: Changes = serde_json::from_reader(response.into_reader().take(LIMIT))?;
+
+    let mut count = 0;
+    for (entry, data) in &changes.changed {
+        let Some(dest) = destination(entry, content, store) else {
+            continue;
+        };
+        let data = STANDARD.decode(data)?;
+        if fs::read(&dest).is_ok_and(|x| x == data) {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&dest, data)?;
+        count += 1;
+    }
+    // Files that differ from the primary's without having changed there, such as
+    // after a pull that failed halfway, are brought back by pulling everything
+    // next time.
+    let mut stale = changes
+        .files
+        .keys()
+        .filter_map(|entry| destination(entry, content, store))
+        .any(|dest| !dest.exists());
+    crate::walk(content, &mut |is_dir, path| {
+        if is_hidden(path) || is_dir {
+            return Ok(!is_hidden(path));
+        }
+        let rel_path = path.strip_prefix(content).unwrap_or(path);
+        let entry = format!("content/{}", rel_path.to_string_lossy());
+        match changes.files.get(&entry) {
+            Some(hash) => {
+                stale |= crate::backup::hash(&mut fs::File::open(path)?)? != *hash;
+            }
+            None => {
+                fs::remove_file(path)?;
+                count += 1;
+            }
+        }
+        Ok(true)
+    })?;
+    let next = if stale { 0 } else { changes.now };
+    fs::write(last, next.to_string())?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations() {
+        let (content, store) = (Path::new("/notes"), Path::new("/data/store.json"));
+        assert_eq!(
+            destination("content/a/b.md", content, store),
+            Some(PathBuf::from("/notes/a/b.md"))
+        );
+        assert_eq!(
+            destination(STORE, content, store),
+            Some(store.to_path_buf())
+        );
+        assert_eq!(destination("content/../etc/passwd", content, store), None);
+        assert_eq!(destination("data/search.json", content, store), None);
+    }
+}
@@ -0,0 +1,92 @@
+use regex::Regex;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// How many times a path may be rewritten before giving up on it.
+const MAX_REWRITES: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+#[error("rewriting \"{0}\" doesn't settle after {MAX_REWRITES} rewrites")]
+pub struct LoopError(String);
+
+/// Rules rewriting request paths, each a regex and its replacement, tried in the
+/// order they're written.
+#[derive(Debug, Clone, Default)]
+pub struct Rules(Vec<(Regex, String)>);
+
+impl Rules {
+    /// Rewrites `path` with the first rule that matches it, and the result again,
+    /// until no rule changes it.
+    pub fn apply(&self, path: &str) -> Result<String, LoopError> {
+        let mut path = path.to_string();
+        for _ in 0..=MAX_REWRITES {
+            let rewritten = self.0.iter().find_map(|(pattern, replacement)| {
+                pattern
+                    .is_match(&path)
+                    .then(|| pattern.replace(&path, replacement.as_str()).into_owned())
+            });
+            match rewritten {
+                Some(rewritten) if rewritten != path => path = rewritten,
+                _ => return Ok(path),
+            }
+        }
+        Err(LoopError(path))
+    }
+}
+
+impl Serialize for Rules {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(x, y)| (x.as_str(), y)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Rules {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RulesVisitor;
+
+        impl<'de> Visitor<'de> for RulesVisitor {
+            type Value = Rules;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a table of regexes and their replacements")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Rules, A::Error> {
+                let mut rules = Vec::new();
+                while let Some((pattern, replacement)) =
+                    map.next_entry::<String, String>()?
+                {
+                    let pattern = Regex::new(&pattern).map_err(de::Error::custom)?;
+                    rules.push((pattern, replacement));
+                }
+                Ok(Rules(rules))
+            }
+        }
+
+        deserializer.deserialize_map(RulesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites() {
+        let rules: Rules = toml::from_str(
+            r#"
+'^/(\d{4})/(\d{2})/(.+)\.html$' = "/posts/$1-$2-$3"
+'^/posts/(.+)$' = "/note/posts/$1.md"
+'^/ping$' = "/pong"
+'^/pong$' = "/ping"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.apply("/2019/04/hello.html").unwrap(),
+            "/note/posts/2019-04-hello.md"
+        );
+        assert_eq!(rules.apply("/search").unwrap(), "/search");
+        assert!(rules.apply("/ping").is_err());
+    }
+}
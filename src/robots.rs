@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+/// What `/robots.txt` asks of crawlers, and what `/humans.txt` says.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Paths crawlers may visit within disallowed ones.
+    pub allow:    Vec<String>,
+    /// Paths crawlers are asked not to visit.
+    pub disallow: Vec<String>,
+    /// Crawlers asked not to visit anything, by user agent, such as `GPTBot`.
+    pub blocked:  Vec<String>,
+    /// The text of `/humans.txt`. There's none without it.
+    pub humans:   Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            allow:    Vec::new(),
+            // Only the owner can use these, or they're endless.
+            disallow: ["/api/", "/dav/", "/edit/", "/search"]
+                .map(String::from)
+                .to_vec(),
+            blocked:  Vec::new(),
+            humans:   None,
+        }
+    }
+}
+
+impl Config {
+    /// The `robots.txt`, pointing at the sitemap under `base`.
+    pub fn robots_txt(&self, base: &str) -> String {
+        let mut txt = String::new();
+        for agent in &self.blocked {
+            txt.push_str(&format!("User-agent: {agent}\nDisallow: /\n\n"));
+        }
+        txt.push_str("User-agent: *\n");
+        for path in &self.allow {
+            txt.push_str(&format!("Allow: {path}\n"));
+        }
+        for path in &self.disallow {
+            txt.push_str(&format!("Disallow: {path}\n"));
+        }
+        // An empty rule allows everything, which a group needs one rule to say.
+        if self.allow.is_empty() && self.disallow.is_empty() {
+            txt.push_str("Disallow:\n");
+        }
+        txt.push_str(&format!("\nSitemap: {base}/sitemap.xml\n"));
+        txt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn robots() {
+        let config = Config {
+            blocked: vec![String::from("GPTBot")],
+            ..Config::default()
+        };
+        assert_eq!(
+            config.robots_txt("https://notes.example.com"),
+            "User-agent: GPTBot\nDisallow: /\n\nUser-agent: *\nDisallow: /api/\n\
+             Disallow: /dav/\nDisallow: /edit/\nDisallow: /search\n\n\
+             Sitemap: https://notes.example.com/sitemap.xml\n"
+        );
+        let config = Config {
+            disallow: Vec::new(),
+            ..Config::default()
+        };
+        assert!(config.robots_txt("").contains("User-agent: *\nDisallow:\n"));
+    }
+}
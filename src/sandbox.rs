@@ -0,0 +1,33 @@
+use landlock::{
+    ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetError,
+    RulesetStatus, path_beneath_rules,
+};
+use std::path::Path;
+
+/// Files outside of the notes that fetching pages and telling the time still need:
+/// name resolution, certificates and the local time zone. Missing ones are skipped.
+const SYSTEM: &[&str] = &[
+    "/etc/hosts",
+    "/etc/resolv.conf",
+    "/etc/nsswitch.conf",
+    "/etc/ssl",
+    "/etc/localtime",
+    "/usr/share/zoneinfo",
+];
+
+/// Restricts the current thread, and the threads it starts from now on, to the
+/// files beneath `writable` and `readable`, and to reading [`SYSTEM`] files.
+pub fn restrict(
+    writable: &[&Path],
+    readable: &[&Path],
+) -> Result<RulesetStatus, RulesetError> {
+    let abi = ABI::V2;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(writable, AccessFs::from_all(abi)))?
+        .add_rules(path_beneath_rules(readable, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(SYSTEM, AccessFs::from_read(abi)))?
+        .restrict_self()?;
+    Ok(status.ruleset)
+}
@@ -0,0 +1,921 @@
+use chrono::NaiveDate;
+use log::error;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Controls how much a match in each part of a note counts towards its score,
+/// relative to a match in the body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub title_boost:    f32,
+    pub tags_boost:     f32,
+    pub headings_boost: f32,
+    /// How much of each note's text is indexed, in bytes, which bounds the memory
+    /// the index uses. Applies as notes are reindexed.
+    pub max_body_bytes: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            title_boost:    3.0,
+            tags_boost:     2.0,
+            headings_boost: 1.5,
+            max_body_bytes: 256 * 1024,
+        }
+    }
+}
+
+impl Config {
+    fn boosts(&self) -> [f32; FIELD_COUNT] {
+        [self.title_boost, self.tags_boost, self.headings_boost, 1.0]
+    }
+
+    /// Cuts `body` down to [`Config::max_body_bytes`].
+    pub fn truncate(&self, mut body: String) -> String {
+        if body.len() > self.max_body_bytes {
+            let mut end = self.max_body_bytes;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+        body
+    }
+}
+
+/// Title, tags, headings and body.
+const FIELD_COUNT: usize = 4;
+
+// Standard BM25 parameters.
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// The field snippets are taken from.
+const BODY: usize = 3;
+/// How many words of the body a snippet shows.
+const SNIPPET_WORDS: usize = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub rel_path: String,
+    pub title:    String,
+    pub date:     NaiveDate,
+    pub tags:     Vec<String>,
+    /// The note's `lang`, which selects how its words are stemmed.
+    pub lang:     Option<String>,
+    pub headings: String,
+    pub body:     String,
+}
+
+impl Document {
+    fn fields(&self) -> [String; FIELD_COUNT] {
+        [
+            self.title.clone(),
+            self.tags.join(" "),
+            self.headings.clone(),
+            self.body.clone(),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Posting {
+    doc:       usize,
+    field:     usize,
+    positions: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    doc:      Document,
+    /// Modification time of the file the document was read from.
+    modified: SystemTime,
+    lengths:  [u32; FIELD_COUNT],
+    /// Byte range of every word in the body, indexed by position.
+    offsets:  Vec<(u32, u32)>,
+}
+
+/// Bumped whenever the way text is turned into terms changes, which invalidates
+/// indexes persisted by older versions.
+const VERSION: u32 = 2;
+
+/// An inverted index over the notes, persisted to disk and updated one document at
+/// a time so reloads only pay for files that changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    #[serde(default)]
+    version:   u32,
+    /// Removed documents leave a hole, which is reused by the next insertion.
+    entries:   Vec<Option<Entry>>,
+    totals:    [u64; FIELD_COUNT],
+    postings:  HashMap<String, Vec<Posting>>,
+    /// How many documents are in each language, to know how to stem queries.
+    languages: HashMap<String, usize>,
+    #[serde(skip)]
+    path:      PathBuf,
+    #[serde(skip)]
+    slots:     HashMap<String, usize>,
+    #[serde(skip)]
+    dirty:     bool,
+}
+
+pub struct Hit<'a> {
+    pub doc:   &'a Document,
+    pub score: f32,
+    slot:      usize,
+}
+
+/// The primary subtag of a `lang` such as `en-US`, which is English when unset.
+fn language(lang: Option<&str>) -> String {
+    lang.and_then(|x| x.split(['-', '_']).next())
+        .filter(|x| !x.is_empty())
+        .unwrap_or("en")
+        .to_ascii_lowercase()
+}
+
+fn algorithm(language: &str) -> Option<Algorithm> {
+    Some(match language {
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "en" => Algorithm::English,
+        "es" => Algorithm::Spanish,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "nl" => Algorithm::Dutch,
+        "no" | "nb" | "nn" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "sv" => Algorithm::Swedish,
+        "ta" => Algorithm::Tamil,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+fn stopwords(language: &str) -> &'static [&'static str] {
+    match language {
+        "en" => &[
+            "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in",
+            "into", "is", "it", "no", "not", "of", "on", "or", "such", "that", "the",
+            "their", "then", "there", "these", "they", "this", "to", "was", "will",
+            "with",
+        ],
+        "de" => &[
+            "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "das", "dass", "dem",
+            "den", "der", "des", "die", "ein", "eine", "einen", "einer", "es", "für",
+            "im", "in", "ist", "mit", "nicht", "oder", "sich", "sie", "und", "von", "zu",
+        ],
+        "fr" => &[
+            "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en",
+            "et", "il", "je", "la", "le", "les", "leur", "mais", "ne", "nous", "on",
+            "ou", "par", "pas", "pour", "qui", "sa", "se", "son", "sur", "un", "une",
+        ],
+        "es" => &[
+            "a", "al", "como", "con", "de", "del", "el", "en", "es", "la", "las", "lo",
+            "los", "más", "no", "o", "para", "pero", "por", "que", "se", "su", "sus",
+            "un", "una", "y",
+        ],
+        _ => &[],
+    }
+}
+
+/// Chinese, Japanese and Korean scripts, which don't separate words with spaces.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}')
+}
+
+/// Splits text into lowercase words along with their byte ranges, so a word's
+/// position is its index. Runs of CJK characters become overlapping bigrams, since
+/// there's no telling where their words end.
+fn spans(text: &str) -> Vec<((u32, u32), String)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let end = |(i, c): (usize, char)| (i + c.len_utf8()) as u32;
+    let mut spans = Vec::new();
+    for run in chars.chunk_by(|a, b| {
+        a.1.is_alphanumeric() == b.1.is_alphanumeric() && is_cjk(a.1) == is_cjk(b.1)
+    }) {
+        let (first, last) = (run[0], run[run.len() - 1]);
+        if !first.1.is_alphanumeric() {
+            continue;
+        }
+        if is_cjk(first.1) && run.len() > 1 {
+            for pair in run.windows(2) {
+                let word = pair.iter().map(|(_, c)| c).collect();
+                spans.push(((pair[0].0 as u32, end(pair[1])), word));
+            }
+        } else {
+            let word = text[first.0..end(last) as usize].to_lowercase();
+            spans.push(((first.0 as u32, end(last)), word));
+        }
+    }
+    spans
+}
+
+/// Splits text into lowercase words with their positions.
+fn words(text: &str) -> Vec<(u32, String)> {
+    spans(text)
+        .into_iter()
+        .enumerate()
+        .map(|(position, (_, word))| (position as u32, word))
+        .collect()
+}
+
+/// The terms stored in the index for text in `language`: stemmed words, without
+/// stopwords. Positions still count the stopwords, to keep phrases intact.
+fn terms(text: &str, language: &str) -> Vec<(u32, String)> {
+    let stemmer = algorithm(language).map(Stemmer::create);
+    let stopwords = stopwords(language);
+    words(text)
+        .into_iter()
+        .filter(|(_, word)| !stopwords.contains(&word.as_str()))
+        .map(|(position, word)| match &stemmer {
+            Some(stemmer) => (position, stemmer.stem(&word).into_owned()),
+            None => (position, word),
+        })
+        .collect()
+}
+
+/// Extracts the headings and the rest of the text from a markdown document, skipping
+/// metadata blocks.
+pub fn plaintext(md: &str) -> (String, String) {
+    use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+
+    let mut headings = String::new();
+    let mut body = String::new();
+    let mut in_heading = false;
+    let mut in_meta = false;
+    for event in Parser::new(md) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => in_heading = true,
+            Event::End(TagEnd::Heading(_)) => {
+                in_heading = false;
+                headings.push('\n');
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                in_meta = lang.trim() == "meta";
+            }
+            Event::End(TagEnd::CodeBlock) => in_meta = false,
+            Event::Text(text) | Event::Code(text) if !in_meta => {
+                if in_heading {
+                    headings.push_str(&text);
+                } else {
+                    body.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph) => {
+                body.push('\n');
+            }
+            _ => {}
+        }
+    }
+    (headings, body)
+}
+
+impl SearchIndex {
+    /// Loads the index persisted at `path`, or starts an empty one.
+    pub fn open(path: PathBuf) -> Self {
+        let mut index = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                error!("Discarding unreadable search index \"{path:?}\": {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        if index.version != VERSION {
+            index = Self {
+                version: VERSION,
+                dirty: true,
+                ..Self::default()
+            };
+        }
+        index.slots = index
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| Some((x.as_ref()?.doc.rel_path.clone(), i)))
+            .collect();
+        index.path = path;
+        index
+    }
+
+    /// Writes the index to disk if it changed since it was opened.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Whether the document at `rel_path` was indexed from a file last modified at
+    /// `modified`.
+    pub fn is_fresh(&self, rel_path: &str, modified: SystemTime) -> bool {
+        self.entry(rel_path).is_some_and(|x| x.modified == modified)
+    }
+
+    fn entry(&self, rel_path: &str) -> Option<&Entry> {
+        self.entries[*self.slots.get(rel_path)?].as_ref()
+    }
+
+    fn live(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// How many documents are indexed, and roughly how many bytes of memory the
+    /// index takes up.
+    pub fn usage(&self) -> (usize, usize) {
+        let documents: usize = self
+            .entries
+            .iter()
+            .flatten()
+            .map(|x| {
+                let doc = &x.doc;
+                doc.rel_path.len()
+                    + doc.title.len()
+                    + doc.tags.iter().map(String::len).sum::<usize>()
+                    + doc.headings.len()
+                    + doc.body.len()
+                    + x.offsets.len() * size_of::<(u32, u32)>()
+            })
+            .sum();
+        let postings: usize = self
+            .postings
+            .iter()
+            .map(|(term, postings)| {
+                term.len()
+                    + postings
+                        .iter()
+                        .map(|x| {
+                            size_of::<Posting>() + x.positions.len() * size_of::<u32>()
+                        })
+                        .sum::<usize>()
+            })
+            .sum();
+        (self.live(), documents + postings)
+    }
+
+    /// Adds a document, replacing any previous version of it.
+    pub fn update(&mut self, doc: Document, modified: SystemTime) {
+        self.remove(&doc.rel_path);
+        let slot = self
+            .entries
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.entries.len());
+
+        let language = language(doc.lang.as_deref());
+        let mut lengths = [0; FIELD_COUNT];
+        for (field, text) in doc.fields().iter().enumerate() {
+            let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+            for (position, term) in terms(text, &language) {
+                positions.entry(term).or_default().push(position);
+                lengths[field] += 1;
+            }
+            for (word, positions) in positions {
+                self.postings.entry(word).or_default().push(Posting {
+                    doc: slot,
+                    field,
+                    positions,
+                });
+            }
+            self.totals[field] += lengths[field] as u64;
+        }
+
+        *self.languages.entry(language).or_default() += 1;
+        self.slots.insert(doc.rel_path.clone(), slot);
+        let offsets = spans(&doc.body).into_iter().map(|(x, _)| x).collect();
+        let entry = Some(Entry {
+            doc,
+            modified,
+            lengths,
+            offsets,
+        });
+        if slot == self.entries.len() {
+            self.entries.push(entry);
+        } else {
+            self.entries[slot] = entry;
+        }
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, rel_path: &str) {
+        let Some(slot) = self.slots.remove(rel_path) else {
+            return;
+        };
+        let entry = self.entries[slot].take().expect("slots point at entries");
+        let language = language(entry.doc.lang.as_deref());
+        for (field, text) in entry.doc.fields().iter().enumerate() {
+            for (_, term) in terms(text, &language) {
+                if let Some(postings) = self.postings.get_mut(&term) {
+                    postings.retain(|x| x.doc != slot);
+                    if postings.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+            self.totals[field] -= entry.lengths[field] as u64;
+        }
+        if let Some(count) = self.languages.get_mut(&language) {
+            *count -= 1;
+            if *count == 0 {
+                self.languages.remove(&language);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Removes every document whose path doesn't satisfy `keep`.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let removed: Vec<_> = self
+            .slots
+            .keys()
+            .filter(|x| !keep(x.as_str()))
+            .cloned()
+            .collect();
+        for rel_path in removed {
+            self.remove(&rel_path);
+        }
+    }
+
+    /// Scores every document matching `query` with BM25F, best match first.
+    pub fn search(&self, query: &Query, config: &Config) -> Vec<Hit<'_>> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let boosts = config.boosts();
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        let words = query
+            .terms
+            .iter()
+            .chain(query.phrases.iter().flatten().map(|(_, word)| word));
+        for word in words {
+            let postings = self
+                .variants(word)
+                .into_iter()
+                .filter_map(|term| self.postings.get(&term))
+                .flatten();
+            let mut weighted: HashMap<usize, f32> = HashMap::new();
+            for posting in postings {
+                let Some(entry) = &self.entries[posting.doc] else {
+                    continue;
+                };
+                let length = entry.lengths[posting.field] as f32;
+                let average = (self.totals[posting.field] as f32
+                    / self.live().max(1) as f32)
+                    .max(1.0);
+                let tf = posting.positions.len() as f32;
+                *weighted.entry(posting.doc).or_default() +=
+                    boosts[posting.field] * tf / (1.0 - B + B * length / average);
+            }
+            let n = weighted.len() as f32;
+            let idf = (1.0 + (self.live() as f32 - n + 0.5) / (n + 0.5)).ln();
+            for (doc, tf) in weighted {
+                *scores.entry(doc).or_default() += idf * tf * (K1 + 1.0) / (tf + K1);
+            }
+        }
+
+        // Without any words to look for, the filters alone select documents.
+        if query.terms.is_empty() && query.phrases.is_empty() {
+            scores = self.slots.values().map(|&doc| (doc, 0.0)).collect();
+        }
+
+        let mut hits: Vec<_> = scores
+            .into_iter()
+            .filter(|&(doc, _)| query.phrases.iter().all(|x| self.has_phrase(doc, x)))
+            .filter_map(|(slot, score)| {
+                let doc = &self.entries[slot].as_ref()?.doc;
+                query
+                    .matches_filters(doc)
+                    .then_some(Hit { doc, score, slot })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.doc.date.cmp(&a.doc.date))
+        });
+        hits
+    }
+
+    /// Merges the notes found by semantic search into the keyword `hits`. Keyword
+    /// scores are scaled so the best is 1, to be comparable with similarities,
+    /// which count `weight` times; notes found both ways add up. Notes that don't
+    /// pass the query's filters are left out.
+    pub fn merge<'a>(
+        &'a self,
+        hits: Vec<Hit<'a>>,
+        similar: &[(&str, f32)],
+        query: &Query,
+        weight: f32,
+    ) -> Vec<Hit<'a>> {
+        let best = hits.iter().map(|x| x.score).fold(0.0, f32::max);
+        let mut scores: HashMap<usize, f32> = hits
+            .iter()
+            .map(|hit| (hit.slot, if best > 0.0 { hit.score / best } else { 0.0 }))
+            .collect();
+        for &(rel_path, similarity) in similar {
+            if let Some(&slot) = self.slots.get(rel_path) {
+                *scores.entry(slot).or_default() += weight * similarity;
+            }
+        }
+        let mut hits: Vec<_> = scores
+            .into_iter()
+            .filter_map(|(slot, score)| {
+                let doc = &self.entries[slot].as_ref()?.doc;
+                query
+                    .matches_filters(doc)
+                    .then_some(Hit { doc, score, slot })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.doc.date.cmp(&a.doc.date))
+        });
+        hits
+    }
+
+    /// An excerpt of a hit's body around where the query matches it most densely,
+    /// as HTML with the matching words wrapped in `<mark>`.
+    pub fn snippet(&self, hit: &Hit, query: &Query) -> String {
+        let Some(entry) = &self.entries[hit.slot] else {
+            return String::new();
+        };
+        let offsets = &entry.offsets;
+        if offsets.is_empty() {
+            return String::new();
+        }
+        let mut matches: Vec<usize> = query
+            .terms
+            .iter()
+            .chain(query.phrases.iter().flatten().map(|(_, word)| word))
+            .flat_map(|word| self.positions(&self.variants(word), hit.slot, BODY))
+            .map(|x| x as usize)
+            .collect();
+        matches.sort_unstable();
+        matches.dedup();
+
+        let densest = matches
+            .iter()
+            .enumerate()
+            .min_by_key(|&(i, &start)| {
+                let count = matches[i..]
+                    .iter()
+                    .take_while(|&&x| x < start + SNIPPET_WORDS)
+                    .count();
+                std::cmp::Reverse(count)
+            })
+            .map_or(0, |(_, &start)| start);
+        // Leave some context before the first match, without coming up short at
+        // the end of the body.
+        let start = densest
+            .saturating_sub(SNIPPET_WORDS / 5)
+            .min(offsets.len().saturating_sub(SNIPPET_WORDS));
+        let end = (start + SNIPPET_WORDS).min(offsets.len());
+
+        // CJK bigrams overlap, so their ranges are merged into a single mark.
+        let mut marks: Vec<(u32, u32)> = Vec::new();
+        for &(from, to) in matches
+            .iter()
+            .filter(|x| (start..end).contains(x))
+            .map(|&x| &offsets[x])
+        {
+            match marks.last_mut() {
+                Some(last) if from <= last.1 => last.1 = last.1.max(to),
+                _ => marks.push((from, to)),
+            }
+        }
+
+        let text = |from: u32, to: u32| {
+            crate::escape_html(&entry.doc.body[from as usize..to as usize])
+                .replace('\n', " ")
+        };
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        let mut cursor = offsets[start].0;
+        for (from, to) in marks {
+            snippet.push_str(&text(cursor, from));
+            snippet.push_str("<mark>");
+            snippet.push_str(&text(from, to));
+            snippet.push_str("</mark>");
+            cursor = to;
+        }
+        snippet.push_str(&text(cursor, offsets[end - 1].1));
+        if end < offsets.len() {
+            snippet.push('…');
+        }
+        snippet
+    }
+
+    /// The terms a query word could have been indexed as, one for each language in
+    /// the index. Stopwords have none.
+    fn variants(&self, word: &str) -> HashSet<String> {
+        self.languages
+            .keys()
+            .filter(|language| !stopwords(language).contains(&word))
+            .map(|language| match algorithm(language) {
+                Some(algorithm) => Stemmer::create(algorithm).stem(word).into_owned(),
+                None => word.to_string(),
+            })
+            .collect()
+    }
+
+    fn positions(
+        &self,
+        variants: &HashSet<String>,
+        doc: usize,
+        field: usize,
+    ) -> Vec<u32> {
+        variants
+            .iter()
+            .filter_map(|term| self.postings.get(term))
+            .flatten()
+            .filter(|x| x.doc == doc && x.field == field)
+            .flat_map(|x| x.positions.iter().copied())
+            .collect()
+    }
+
+    fn has_phrase(&self, doc: usize, phrase: &[(u32, String)]) -> bool {
+        // Stopwords aren't indexed, so they're left out of the comparison, but the
+        // positions of the remaining words still account for them.
+        let phrase: Vec<_> = phrase
+            .iter()
+            .map(|(offset, word)| (*offset, self.variants(word)))
+            .filter(|(_, variants)| !variants.is_empty())
+            .collect();
+        let Some(((first_offset, first), rest)) = phrase.split_first() else {
+            return true;
+        };
+        (0..FIELD_COUNT).any(|field| {
+            self.positions(first, doc, field).into_iter().any(|start| {
+                rest.iter().all(|(offset, variants)| {
+                    self.positions(variants, doc, field)
+                        .contains(&(start + offset - first_offset))
+                })
+            })
+        })
+    }
+}
+
+/// A parsed search query. Besides plain words, queries support `"quoted phrases"`,
+/// `tag:name`, `before:YYYY-MM-DD` and `after:YYYY-MM-DD`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    terms:   Vec<String>,
+    /// Words in each phrase, along with their offset in it.
+    phrases: Vec<Vec<(u32, String)>>,
+    tags:    Vec<String>,
+    before:  Option<NaiveDate>,
+    after:   Option<NaiveDate>,
+}
+
+impl Query {
+    pub fn parse(q: &str) -> Self {
+        let mut query = Self::default();
+        for (i, part) in q.split('"').enumerate() {
+            // Every other part is inside of quotes.
+            if i % 2 == 1 {
+                let phrase = words(part);
+                match phrase.len() {
+                    0 => {}
+                    1 => query.terms.extend(phrase.into_iter().map(|(_, word)| word)),
+                    _ => query.phrases.push(phrase),
+                }
+                continue;
+            }
+            for word in part.split_whitespace() {
+                let date = |x: &str| NaiveDate::parse_from_str(x, "%Y-%m-%d").ok();
+                if let Some(tag) = word.strip_prefix("tag:") {
+                    query.tags.push(tag.to_lowercase());
+                } else if let Some(before) = word.strip_prefix("before:").and_then(date) {
+                    query.before = Some(before);
+                } else if let Some(after) = word.strip_prefix("after:").and_then(date) {
+                    query.after = Some(after);
+                } else {
+                    query
+                        .terms
+                        .extend(words(word).into_iter().map(|(_, word)| word));
+                }
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The query's words and phrases without its filters, to look for by meaning.
+    pub fn text(&self) -> String {
+        let phrases = self
+            .phrases
+            .iter()
+            .map(|phrase| phrase.iter().map(|(_, word)| word.as_str()).collect());
+        self.terms
+            .iter()
+            .cloned()
+            .chain(phrases.map(|words: Vec<_>| words.join(" ")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// How many times the query's words occur in `text`, written in `lang`.
+    pub fn occurrences(&self, text: &str, lang: Option<&str>) -> usize {
+        let language = language(lang);
+        let wanted: HashSet<String> = self
+            .terms
+            .iter()
+            .chain(self.phrases.iter().flatten().map(|(_, word)| word))
+            .flat_map(|word| terms(word, &language))
+            .map(|(_, term)| term)
+            .collect();
+        terms(text, &language)
+            .into_iter()
+            .filter(|(_, term)| wanted.contains(term))
+            .count()
+    }
+
+    fn matches_filters(&self, doc: &Document) -> bool {
+        self.tags
+            .iter()
+            .all(|tag| doc.tags.iter().any(|x| x.to_lowercase() == *tag))
+            && self.before.is_none_or(|before| doc.date < before)
+            && self.after.is_none_or(|after| doc.date > after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(rel_path: &str, title: &str, tags: &[&str], body: &str) -> Document {
+        Document {
+            rel_path: rel_path.to_string(),
+            title:    title.to_string(),
+            date:     NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+            tags:     tags.iter().map(|x| x.to_string()).collect(),
+            lang:     None,
+            headings: String::new(),
+            body:     body.to_string(),
+        }
+    }
+
+    fn index(docs: Vec<Document>) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for doc in docs {
+            index.update(doc, SystemTime::UNIX_EPOCH);
+        }
+        index
+    }
+
+    #[test]
+    fn query() {
+        let query = Query::parse(r#"rust "borrow checker" tag:Lang before:2025-02-01 x"#);
+        assert_eq!(query.terms, ["rust", "x"]);
+        assert_eq!(
+            query.phrases,
+            [[(0, "borrow".to_string()), (1, "checker".to_string())]]
+        );
+        assert_eq!(query.tags, ["lang"]);
+        assert_eq!(query.before, NaiveDate::from_ymd_opt(2025, 2, 1));
+        assert_eq!(query.after, None);
+        assert!(Query::parse("  ").is_empty());
+    }
+
+    #[test]
+    fn ranking() {
+        let index = index(vec![
+            doc("a.md", "Cooking", &[], "rust on a cast iron pan"),
+            doc(
+                "b.md",
+                "Rust",
+                &["lang"],
+                "the borrow checker checks borrows",
+            ),
+            doc("c.md", "Checkers", &[], "checker borrow"),
+        ]);
+        let config = Config::default();
+        let paths = |q: &str| -> Vec<_> {
+            index
+                .search(&Query::parse(q), &config)
+                .into_iter()
+                .map(|x| x.doc.rel_path.as_str())
+                .collect()
+        };
+        // Title matches are boosted over body matches.
+        assert_eq!(paths("rust"), ["b.md", "a.md"]);
+        assert_eq!(paths(r#""borrow checker""#), ["b.md"]);
+        assert_eq!(paths("tag:lang"), ["b.md"]);
+        assert_eq!(paths("nothing"), Vec::<&str>::new());
+        // Stopwords are skipped, but still count towards phrase positions.
+        assert_eq!(paths(r#""the borrow checker checks""#), ["b.md"]);
+        assert_eq!(paths(r#""borrow the checker""#), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn languages() {
+        let mut index = SearchIndex::default();
+        let mut german = doc(
+            "de.md",
+            "Bereitstellung",
+            &[],
+            "Wir bereiten die Häuser vor",
+        );
+        german.lang = Some(String::from("de-DE"));
+        for doc in [
+            doc("en.md", "Deployment", &[], "notes on deployment"),
+            german,
+            doc("ja.md", "旅行", &[], "東京タワーに行きました"),
+        ] {
+            index.update(doc, SystemTime::UNIX_EPOCH);
+        }
+        let config = Config::default();
+        let paths = |q: &str| -> Vec<_> {
+            index
+                .search(&Query::parse(q), &config)
+                .into_iter()
+                .map(|x| x.doc.rel_path.as_str())
+                .collect()
+        };
+        assert_eq!(paths("deploying"), ["en.md"]);
+        assert_eq!(paths("haus"), ["de.md"]);
+        assert_eq!(paths("タワー"), ["ja.md"]);
+        assert_eq!(paths("the"), Vec::<&str>::new());
+        assert_eq!(words("東京タワー")[0], (0, String::from("東京")));
+    }
+
+    #[test]
+    fn snippets() {
+        let long: Vec<_> = (0..100).map(|i| format!("w{i}")).collect();
+        let index = index(vec![
+            doc(
+                "a.md",
+                "A",
+                &[],
+                "The borrow checker & you\nchecks borrows.",
+            ),
+            doc("b.md", "B", &[], &long.join(" ")),
+            doc("c.md", "C", &[], "東京タワーに行きました"),
+        ]);
+        let config = Config::default();
+        let snippet = |q: &str| {
+            let query = Query::parse(q);
+            let hits = index.search(&query, &config);
+            index.snippet(&hits[0], &query)
+        };
+        assert_eq!(
+            snippet("borrow"),
+            "The <mark>borrow</mark> checker &amp; you checks <mark>borrows</mark>"
+        );
+        let window = snippet("w50");
+        assert!(window.starts_with("…w44 "));
+        assert!(window.contains(" <mark>w50</mark> "));
+        assert!(window.ends_with(" w73…"));
+        assert_eq!(snippet("タワー"), "東京<mark>タワー</mark>に行きました");
+    }
+
+    #[test]
+    fn merging() {
+        let index = index(vec![
+            doc("a.md", "A", &["rust"], "deploying the server"),
+            doc("b.md", "B", &["rust"], "shipping a release"),
+            doc("c.md", "C", &[], "rolling out updates"),
+        ]);
+        let query = Query::parse("deploying tag:rust");
+        assert_eq!(query.text(), "deploying");
+        let hits = index.search(&query, &Config::default());
+        let similar = [("b.md", 0.8), ("c.md", 0.9), ("a.md", 0.6)];
+        let merged: Vec<_> = index
+            .merge(hits, &similar, &query, 1.0)
+            .into_iter()
+            .map(|x| x.doc.rel_path.as_str())
+            .collect();
+        // a.md is found both ways, and c.md isn't tagged.
+        assert_eq!(merged, ["a.md", "b.md"]);
+    }
+}
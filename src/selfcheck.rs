@@ -0,0 +1,112 @@
+use std::io::Read;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// How much of a response is read, to look for notes to check.
+const LIMIT: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}: {1}")]
+    Request(String, Box<ureq::Error>),
+    #[error("{0}: expected {1}, got \"{2}\"")]
+    ContentType(String, &'static str, String),
+    #[error("{0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("the index links to no notes")]
+    NoNotes,
+}
+
+/// Requests the index, a note it links to, picked at random, the feed and
+/// `/health` from the site at `base`, printing how each went. Returns what failed.
+pub fn run(base: &Url) -> Vec<Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(10))
+        .build();
+    let check = |path: &str, content_type: &'static str| -> Result<String, Error> {
+        let url = base.join(path)?;
+        fetch(&agent, url, content_type)
+    };
+    let mut failures = Vec::new();
+    let index = report(&mut failures, "/", check("/", "text/html"));
+    let notes = index.as_deref().map(note_links).unwrap_or_default();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as usize;
+    match notes.get(nanos % notes.len().max(1)) {
+        Some(note) => {
+            report(&mut failures, note, check(note, "text/html"));
+        }
+        None if index.is_some() => {
+            report(&mut failures, "a note", Err(Error::NoNotes));
+        }
+        None => {}
+    }
+    let feed = check("/feed.xml", "application/rss+xml");
+    report(&mut failures, "/feed.xml", feed);
+    report(&mut failures, "/health", check("/health", "text/plain"));
+    failures
+}
+
+/// Prints how checking `what` went, adding it to `failures` if it failed.
+fn report(
+    failures: &mut Vec<Error>,
+    what: &str,
+    result: Result<String, Error>,
+) -> Option<String> {
+    match result {
+        Ok(body) => {
+            println!("ok   {what}");
+            Some(body)
+        }
+        Err(e) => {
+            println!("FAIL {what}: {e}");
+            failures.push(e);
+            None
+        }
+    }
+}
+
+/// The body of a successful response to `GET url` with a `content_type`.
+fn fetch(
+    agent: &ureq::Agent,
+    url: Url,
+    content_type: &'static str,
+) -> Result<String, Error> {
+    let response = match agent.get(url.as_str()).call() {
+        Ok(response) => response,
+        Err(e) => return Err(Error::Request(url.into(), Box::new(e))),
+    };
+    if response.content_type() != content_type {
+        let got = response.content_type().to_string();
+        return Err(Error::ContentType(url.into(), content_type, got));
+    }
+    let mut body = String::new();
+    match response.into_reader().take(LIMIT).read_to_string(&mut body) {
+        Ok(_) => Ok(body),
+        Err(e) => Err(Error::Read(url.into(), e)),
+    }
+}
+
+/// The paths of the notes `html` links to.
+fn note_links(html: &str) -> Vec<&str> {
+    html.split(r#"href=""#)
+        .skip(1)
+        .filter_map(|x| x.split('"').next())
+        .filter(|x| x.starts_with("/note/"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let html = r#"<a href="/note/a.md">A</a> <a href="https://example.com">B</a><a class="permalink" href="/note/b/c.md">#</a>"#;
+        assert_eq!(note_links(html), ["/note/a.md", "/note/b/c.md"]);
+    }
+}
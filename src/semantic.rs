@@ -0,0 +1,223 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Semantic search, which finds notes by what they're about rather than the words
+/// they use. Embeddings come from a model behind an OpenAI-compatible
+/// `/v1/embeddings` API, such as a local Ollama or llama.cpp server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Such as `http://localhost:11434/v1/embeddings`. Semantic search is off when
+    /// unset.
+    pub endpoint:  Option<Url>,
+    pub model:     String,
+    /// Sent as a bearer token, for endpoints that need one.
+    pub api_key:   Option<String>,
+    /// Least cosine similarity between a query and a note for the note to match.
+    pub threshold: f32,
+    /// How much a semantic match counts against the best keyword match.
+    pub weight:    f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint:  None,
+            model:     String::from("nomic-embed-text"),
+            api_key:   None,
+            threshold: 0.5,
+            weight:    1.0,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("semantic search is disabled")]
+    Disabled,
+    #[error("failed to request embeddings: {0}")]
+    Request(#[from] Box<ureq::Error>),
+    #[error("failed to read embeddings: {0}")]
+    Io(#[from] io::Error),
+    #[error("expected {0} embeddings, got {1}")]
+    Count(usize, usize),
+}
+
+/// How long to wait for the model, which may have to load first.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Embeds each of `texts`, normalized so similarity is a dot product.
+fn embed(config: &Config, texts: &[&str]) -> Result<Vec<Vec<f32>>, Error> {
+    #[derive(Deserialize)]
+    struct Embedding {
+        index:     usize,
+        embedding: Vec<f32>,
+    }
+    #[derive(Deserialize)]
+    struct Embeddings {
+        data: Vec<Embedding>,
+    }
+
+    let endpoint = config.endpoint.as_ref().ok_or(Error::Disabled)?;
+    let mut request = ureq::post(endpoint.as_str())
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json");
+    if let Some(key) = &config.api_key {
+        request = request.set("Authorization", &format!("Bearer {key}"));
+    }
+    let response = request
+        .send_string(&json!({ "model": config.model, "input": texts }).to_string())
+        .map_err(Box::new)?;
+    let mut embeddings: Embeddings =
+        serde_json::from_reader(response.into_reader()).map_err(io::Error::from)?;
+    if embeddings.data.len() != texts.len() {
+        return Err(Error::Count(texts.len(), embeddings.data.len()));
+    }
+    embeddings.data.sort_by_key(|x| x.index);
+    Ok(embeddings
+        .data
+        .into_iter()
+        .map(|x| normalize(x.embedding))
+        .collect())
+}
+
+/// Embeds the chunks of a note.
+pub fn embed_note(config: &Config, chunks: &[&str]) -> Result<Vec<Vec<f32>>, Error> {
+    match chunks.is_empty() {
+        true => Ok(Vec::new()),
+        false => embed(config, chunks),
+    }
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let length = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= length);
+    }
+    vector
+}
+
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Note {
+    /// Modification time of the file the chunks were read from.
+    modified: SystemTime,
+    /// An embedding for each of the note's chunks.
+    vectors:  Vec<Vec<f32>>,
+}
+
+/// Embeddings of every note's chunks, persisted to disk and updated one note at a
+/// time so reloads only ask the model about files that changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    /// The model the embeddings came from, since another's can't be compared.
+    model: String,
+    notes: HashMap<String, Note>,
+    #[serde(skip)]
+    path:  PathBuf,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Index {
+    /// Loads the embeddings persisted at `path`, discarding them if they came from
+    /// a model other than `model`.
+    pub fn open(path: PathBuf, model: &str) -> Self {
+        let mut index: Self = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                error!("Discarding unreadable embeddings \"{path:?}\": {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        if index.model != model {
+            index = Self {
+                model: model.to_string(),
+                dirty: true,
+                ..Self::default()
+            };
+        }
+        index.path = path;
+        index
+    }
+
+    /// Writes the embeddings to disk if they changed since they were opened.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn is_fresh(&self, rel_path: &str, modified: SystemTime) -> bool {
+        self.notes
+            .get(rel_path)
+            .is_some_and(|x| x.modified == modified)
+    }
+
+    /// Keeps the embeddings [`embed_note`] made of a note's chunks.
+    pub fn insert(
+        &mut self,
+        rel_path: &str,
+        vectors: Vec<Vec<f32>>,
+        modified: SystemTime,
+    ) {
+        self.notes
+            .insert(rel_path.to_string(), Note { modified, vectors });
+        self.dirty = true;
+    }
+
+    /// Forgets the notes whose paths `keep` rejects.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let before = self.notes.len();
+        self.notes.retain(|rel_path, _| keep(rel_path));
+        self.dirty |= self.notes.len() != before;
+    }
+
+    /// The notes similar enough to `query`, with the similarity of their closest
+    /// chunk, most similar first.
+    pub fn search(
+        &self,
+        config: &Config,
+        query: &str,
+    ) -> Result<Vec<(&str, f32)>, Error> {
+        let Some(query) = embed(config, &[query])?.pop() else {
+            return Ok(Vec::new());
+        };
+        let mut matches: Vec<_> = self
+            .notes
+            .iter()
+            .filter_map(|(rel_path, note)| {
+                let best = note
+                    .vectors
+                    .iter()
+                    .map(|x| similarity(&query, x))
+                    .max_by(f32::total_cmp)?;
+                (best >= config.threshold).then_some((rel_path.as_str(), best))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(matches)
+    }
+}
@@ -0,0 +1,51 @@
+use std::ops::Range;
+
+/// The shard of notes whose titles don't start with a letter.
+pub const OTHER: &str = "other";
+
+/// Which alphabetical shard, `/index/<shard>`, the note titled `title` is listed
+/// in: the first letter of its title in lower case, or [`OTHER`].
+pub fn shard(title: &str) -> String {
+    match title.chars().find(|c| c.is_alphanumeric()) {
+        Some(c) if c.is_alphabetic() => c.to_lowercase().collect(),
+        _ => String::from(OTHER),
+    }
+}
+
+/// Which of `len` notes page `page` of the index lists, counting from 1, with
+/// `size` notes per page. `None` when there's no such page, though the first one
+/// always exists.
+pub fn page(len: usize, size: usize, page: usize) -> Option<Range<usize>> {
+    let size = size.max(1);
+    let start = page.checked_sub(1)?.checked_mul(size)?;
+    if page > 1 && start >= len {
+        return None;
+    }
+    Some(start..len.min(start + size))
+}
+
+/// How many pages `len` notes fill, at least one.
+pub fn pages(len: usize, size: usize) -> usize {
+    len.div_ceil(size.max(1)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharding() {
+        assert_eq!(shard("Rust"), "r");
+        assert_eq!(shard("\"Émile\""), "é");
+        assert_eq!(shard("2024 in review"), OTHER);
+        assert_eq!(shard(""), OTHER);
+
+        assert_eq!(page(0, 10, 1), Some(0..0));
+        assert_eq!(page(25, 10, 1), Some(0..10));
+        assert_eq!(page(25, 10, 3), Some(20..25));
+        assert_eq!(page(25, 10, 4), None);
+        assert_eq!(page(25, 10, 0), None);
+        assert_eq!(pages(25, 10), 3);
+        assert_eq!(pages(0, 10), 1);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::cards::Review;
+use crate::integrity::Checksums;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Metadata about notes that doesn't belong in the notes themselves, persisted as
+/// JSON in the data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Store {
+    #[serde(skip)]
+    path:            PathBuf,
+    /// Highlights keyed by the note's path relative to the content directory.
+    #[serde(default)]
+    pub annotations: HashMap<String, Vec<Annotation>>,
+    /// Searches made by readers, keyed by the scrubbed query.
+    #[serde(default)]
+    pub queries:     HashMap<String, QueryStats>,
+    /// The owner's reading list: paths of notes to read later, next first.
+    #[serde(default)]
+    pub queue:       Vec<String>,
+    /// How well the owner knows each flashcard, keyed by [`Card::key`].
+    ///
+    /// [`Card::key`]: crate::cards::Card::key
+    #[serde(default)]
+    pub cards:       HashMap<String, Review>,
+    /// What each file in the content directory was like when it was last modified,
+    /// to find those that changed without being modified.
+    #[serde(default)]
+    pub checksums:   Checksums,
+    /// How much the content and data directories took each day.
+    #[serde(default)]
+    pub usage:       crate::usage::History,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    /// The highlighted text, which anchors the annotation in the note.
+    pub quote:   String,
+    pub comment: Option<String>,
+    pub created: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStats {
+    pub count:     u64,
+    /// How many results the query had the last time it was made.
+    pub results:   usize,
+    pub last_seen: NaiveDate,
+}
+
+/// How many distinct queries are kept before the least popular are forgotten.
+const MAX_QUERIES: usize = 1000;
+
+/// Normalizes a search query for the log, replacing anything that looks like an
+/// email address or a long number (phone numbers, IDs) so the log doesn't keep
+/// personal data readers pasted into the search box.
+fn scrub(query: &str) -> Option<String> {
+    let words: Vec<_> = query
+        .split_whitespace()
+        .map(|word| {
+            if word.contains('@') {
+                String::from("[email]")
+            } else if word.chars().filter(char::is_ascii_digit).count() >= 6 {
+                String::from("[number]")
+            } else {
+                word.to_lowercase()
+            }
+        })
+        .collect();
+    let scrubbed: String = words.join(" ").chars().take(100).collect();
+    (!scrubbed.is_empty()).then_some(scrubbed)
+}
+
+impl Store {
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut store: Self = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        store.path = path;
+        Ok(store)
+    }
+
+    /// Counts a search and how many results it had.
+    pub fn record_query(&mut self, query: &str, results: usize, date: NaiveDate) {
+        let Some(query) = scrub(query) else {
+            return;
+        };
+        if self.queries.len() >= MAX_QUERIES && !self.queries.contains_key(&query) {
+            let forgotten = self
+                .queries
+                .iter()
+                .min_by_key(|(_, x)| (x.count, x.last_seen))
+                .map(|(query, _)| query.clone());
+            if let Some(forgotten) = forgotten {
+                self.queries.remove(&forgotten);
+            }
+        }
+        let stats = self.queries.entry(query).or_insert(QueryStats {
+            count: 0,
+            results,
+            last_seen: date,
+        });
+        stats.count += 1;
+        stats.results = results;
+        stats.last_seen = date;
+    }
+
+    /// Adds the note at `rel_path` to the end of the reading list, unless it's on it
+    /// already.
+    pub fn enqueue(&mut self, rel_path: &str) {
+        if !self.queue.iter().any(|x| x == rel_path) {
+            self.queue.push(rel_path.to_string());
+        }
+    }
+
+    pub fn dequeue(&mut self, rel_path: &str) {
+        self.queue.retain(|x| x != rel_path);
+    }
+
+    /// Moves the note at `rel_path` `by` places later in the reading list, or
+    /// earlier when negative, stopping at either end.
+    pub fn move_queued(&mut self, rel_path: &str, by: isize) {
+        let Some(from) = self.queue.iter().position(|x| x == rel_path) else {
+            return;
+        };
+        let to = from.saturating_add_signed(by).min(self.queue.len() - 1);
+        let queued = self.queue.remove(from);
+        self.queue.insert(to, queued);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename, so a crash never leaves a half-written store behind.
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubbed_queries() {
+        assert_eq!(
+            scrub("  Contact  me@example.com or +1-555-123-4567 "),
+            Some(String::from("contact [email] or [number]"))
+        );
+        assert_eq!(scrub("Rust 2024"), Some(String::from("rust 2024")));
+        assert_eq!(scrub(" "), None);
+    }
+
+    #[test]
+    fn queue() {
+        let mut store = Store::default();
+        for note in ["a.md", "b.md", "c.md", "a.md"] {
+            store.enqueue(note);
+        }
+        assert_eq!(store.queue, ["a.md", "b.md", "c.md"]);
+        store.move_queued("c.md", -1);
+        assert_eq!(store.queue, ["a.md", "c.md", "b.md"]);
+        store.move_queued("a.md", -1);
+        store.move_queued("b.md", 1);
+        assert_eq!(store.queue, ["a.md", "c.md", "b.md"]);
+        store.dequeue("c.md");
+        assert_eq!(store.queue, ["a.md", "b.md"]);
+    }
+}
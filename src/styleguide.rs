@@ -0,0 +1,39 @@
+/// A note using everything the renderer can emit, to see how a theme shows it.
+pub const FIXTURE: &str = include_str!("styleguide.md");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn components() {
+        let (html, meta) = crate::render_markdown(
+            FIXTURE,
+            crate::Meta::inferred(String::from("Style guide"), NaiveDate::default()),
+            &crate::filter::Filters::default(),
+            &crate::wikilink::Links::default(),
+        );
+        assert!(meta.desc.is_some());
+        for component in [
+            "<em>",
+            "<strong>",
+            "<code>",
+            "<time ",
+            r#"class="wikilink missing""#,
+            r#"class="footnote-reference""#,
+            "<h4>",
+            "<blockquote>",
+            "markdown-alert-note",
+            "markdown-alert-warning",
+            "<ol>",
+            "<pre",
+            "math-inline",
+            "math-display",
+            "<hr />",
+            "<img ",
+        ] {
+            assert!(html.contains(component), "no {component} in {html}");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+/// Least [`similarity`] a name must have to be suggested.
+const THRESHOLD: f32 = 0.5;
+
+/// The runs of three characters in each word of `s`, ignoring case and
+/// punctuation, with the ends of words padded so short words have some too.
+fn trigrams(s: &str) -> HashSet<[char; 3]> {
+    let mut trigrams = HashSet::new();
+    for word in s
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+    {
+        let chars: Vec<_> = [' ', ' ']
+            .into_iter()
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain([' '])
+            .collect();
+        trigrams.extend(chars.windows(3).map(|x| [x[0], x[1], x[2]]));
+    }
+    trigrams
+}
+
+/// How alike `a` and `b` are, from 0 when they have no trigram in common to 1
+/// when they have the same ones.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f32 / (a.len() + b.len()) as f32
+}
+
+/// Up to `n` of `candidates` with a name like `wanted`, closest first, by the
+/// closest of the names `names` gives each.
+pub fn closest<'a, T, N>(
+    wanted: &str,
+    candidates: &'a [T],
+    names: impl Fn(&'a T) -> N,
+    n: usize,
+) -> Vec<&'a T>
+where
+    N: IntoIterator<Item = &'a str>,
+{
+    let mut scored: Vec<_> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            let score = names(candidate)
+                .into_iter()
+                .map(|name| similarity(wanted, name))
+                .fold(0.0, f32::max);
+            (score >= THRESHOLD).then_some((score, candidate))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(n).map(|(_, x)| x).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(similarity("Rust", "rust!"), 1.0);
+        assert_eq!(similarity("", "rust"), 0.0);
+        let titles = [
+            "Installing Rust",
+            "Rust tips and tricks",
+            "Rust tips",
+            "Gardening",
+        ];
+        assert_eq!(
+            closest("rust-tip", &titles, |x| [*x], 5),
+            [&"Rust tips", &"Rust tips and tricks"]
+        );
+        assert_eq!(closest("rust-tip", &titles, |x| [*x], 1), [&"Rust tips"]);
+        assert!(closest("sourdough", &titles, |x| [*x], 5).is_empty());
+    }
+}
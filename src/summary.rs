@@ -0,0 +1,254 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Summaries written by a language model for long notes without a `desc`, from
+/// an OpenAI-compatible `/v1/chat/completions` API such as a local Ollama server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Such as `http://localhost:11434/v1/chat/completions`. Summaries are off when
+    /// unset.
+    pub endpoint:  Option<Url>,
+    pub model:     String,
+    /// Sent as a bearer token, for endpoints that need one.
+    pub api_key:   Option<String>,
+    /// Notes with fewer words than this are left alone.
+    pub min_words: usize,
+    /// The instructions the model gets before the note.
+    pub prompt:    String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint:  None,
+            model:     String::from("llama3.2"),
+            api_key:   None,
+            min_words: 300,
+            prompt:    String::from(
+                "Summarize the following note in one or two plain sentences, written \
+                 as a description of the note. Reply with only the summary.",
+            ),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to request summary: {0}")]
+    Request(#[from] Box<ureq::Error>),
+    #[error("failed to read summary: {0}")]
+    Io(#[from] io::Error),
+    #[error("the model's reply is empty")]
+    Empty,
+}
+
+/// How long to wait for the model, which may have to load first.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Asks the model at `endpoint` to summarize `text`.
+fn summarize(config: &Config, endpoint: &Url, text: &str) -> Result<String, Error> {
+    #[derive(Deserialize)]
+    struct Message {
+        content: String,
+    }
+    #[derive(Deserialize)]
+    struct Choice {
+        message: Message,
+    }
+    #[derive(Deserialize)]
+    struct Completion {
+        choices: Vec<Choice>,
+    }
+
+    let mut request = ureq::post(endpoint.as_str())
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json");
+    if let Some(key) = &config.api_key {
+        request = request.set("Authorization", &format!("Bearer {key}"));
+    }
+    let body = json!({
+        "model": config.model,
+        "messages": [
+            { "role": "system", "content": config.prompt },
+            { "role": "user", "content": text },
+        ],
+    });
+    let response = request.send_string(&body.to_string()).map_err(Box::new)?;
+    let completion: Completion =
+        serde_json::from_reader(response.into_reader()).map_err(io::Error::from)?;
+    let summary = completion
+        .choices
+        .into_iter()
+        .next()
+        .map(|x| x.message.content.trim().to_string())
+        .unwrap_or_default();
+    match summary.is_empty() {
+        true => Err(Error::Empty),
+        false => Ok(summary),
+    }
+}
+
+/// Summaries persisted as JSON in the data directory, keyed by a hash of the text
+/// they summarize, so a note is only summarized again when it changes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Summaries {
+    #[serde(skip)]
+    path:      PathBuf,
+    summaries: HashMap<String, String>,
+    #[serde(skip)]
+    dirty:     bool,
+    /// Notes [`Summaries::get`] had no summary for yet.
+    #[serde(skip)]
+    missing:   Vec<Missing>,
+}
+
+/// A note without a summary, as it was when modified at `modified`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Missing {
+    pub rel_path: String,
+    pub modified: SystemTime,
+    text:         String,
+}
+
+impl Summaries {
+    pub fn open(path: PathBuf) -> Self {
+        let mut summaries: Self = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                error!("Discarding unreadable summaries \"{path:?}\": {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        summaries.path = path;
+        summaries
+    }
+
+    /// Writes the summaries to disk if they changed since they were opened.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Forgets the summaries `keep` rejects, such as those of notes that changed
+    /// since.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let before = self.summaries.len();
+        self.summaries.retain(|_, summary| keep(summary));
+        self.dirty |= self.summaries.len() != before;
+    }
+
+    /// The summary of `text`, the plain text of the note at `rel_path`, if it's long
+    /// enough to need one and summaries are on. Asking the model takes a while, so
+    /// one that isn't summarized yet is left for [`fetch`], by way of
+    /// [`Summaries::take_missing`].
+    pub fn get(
+        &mut self,
+        config: &Config,
+        rel_path: &str,
+        modified: SystemTime,
+        text: &str,
+    ) -> Option<String> {
+        config.endpoint.as_ref()?;
+        if text.split_whitespace().count() < config.min_words {
+            return None;
+        }
+        let summary = self.summaries.get(&hash(text)).cloned();
+        if summary.is_none() {
+            self.missing.push(Missing {
+                rel_path: rel_path.to_string(),
+                modified,
+                text: text.to_string(),
+            });
+        }
+        summary
+    }
+
+    /// The notes [`Summaries::get`] had no summary for since this was last called.
+    pub fn take_missing(&mut self) -> Vec<Missing> {
+        std::mem::take(&mut self.missing)
+    }
+
+    /// Keeps `summary` as that of the note `missing` is about.
+    pub fn insert(&mut self, missing: &Missing, summary: String) {
+        self.summaries.insert(hash(&missing.text), summary);
+        self.dirty = true;
+    }
+}
+
+fn hash(text: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    crate::hex(&Sha256::digest(text))
+}
+
+/// Summarizes the notes in `missing`, giving up on the rest if the model can't be
+/// reached.
+pub fn fetch<'a>(config: &Config, missing: &'a [Missing]) -> Vec<(&'a Missing, String)> {
+    let Some(endpoint) = &config.endpoint else {
+        return Vec::new();
+    };
+    let mut summaries = Vec::new();
+    for note in missing {
+        match summarize(config, endpoint, &note.text) {
+            Ok(summary) => {
+                info!("Summarized \"{}\" as \"{summary}\"", note.rel_path);
+                summaries.push((note, summary));
+            }
+            Err(e) => {
+                error!("Failed to summarize \"{}\": {e}", note.rel_path);
+                break;
+            }
+        }
+    }
+    summaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_summaries_are_left_for_later() {
+        let config = Config {
+            // Nothing listens there, so asking would fail.
+            endpoint: Some(Url::parse("http://127.0.0.1:9/v1/chat/completions").unwrap()),
+            min_words: 3,
+            ..Config::default()
+        };
+        let modified = SystemTime::UNIX_EPOCH;
+        let mut summaries = Summaries::default();
+        assert_eq!(
+            summaries.get(&config, "short.md", modified, "Too short"),
+            None
+        );
+        let text = "Long enough to need a summary";
+        assert_eq!(summaries.get(&config, "long.md", modified, text), None);
+        let missing = summaries.take_missing();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].rel_path, "long.md");
+
+        summaries.insert(&missing[0], String::from("A summary."));
+        assert_eq!(
+            summaries.get(&config, "long.md", modified, text).as_deref(),
+            Some("A summary.")
+        );
+        assert!(summaries.take_missing().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Maintenance run periodically, while serving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    /// Read the notes again, regenerating the index, the feeds and the search index.
+    Reload,
+    /// Forget rendered pages, so they're rendered again when next visited.
+    ClearCache,
+    /// `git pull` the content directory, then reload.
+    GitPull,
+    /// Log the files notes link to or embed that are missing.
+    CheckLinks,
+    /// Check that every identity links back to `base_url`.
+    VerifyIdentities,
+    /// Pull what changed from the primary of `replica`, then reload.
+    Replicate,
+}
+
+/// How often to run each task, in seconds. Tasks set to 0 aren't run.
+pub type Config = BTreeMap<Task, u64>;
+
+/// When each task last ran.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    last: BTreeMap<Task, Instant>,
+}
+
+impl Schedule {
+    /// The tasks in `config` that are due at `now`, which are then counted as run.
+    /// A task is first due an interval after it's first seen here.
+    pub fn due(&mut self, config: &Config, now: Instant) -> Vec<Task> {
+        let mut due = Vec::new();
+        for (&task, &interval) in config.iter().filter(|(_, interval)| **interval > 0) {
+            let last = self.last.entry(task).or_insert(now);
+            if now.duration_since(*last) >= Duration::from_secs(interval) {
+                *last = now;
+                due.push(task);
+            }
+        }
+        due
+    }
+}
+
+/// Fast-forwards the git repository at `dir` to its upstream.
+pub fn git_pull(dir: &Path) -> io::Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["pull", "--ff-only", "--quiet"])
+        .output()?;
+    match output.status.success() {
+        true => Ok(()),
+        false => {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            Err(io::Error::other(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduling() {
+        let config = Config::from([(Task::Reload, 60), (Task::GitPull, 0)]);
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        assert!(schedule.due(&config, start).is_empty());
+        assert!(
+            schedule
+                .due(&config, start + Duration::from_secs(59))
+                .is_empty()
+        );
+        let at = start + Duration::from_secs(60);
+        assert_eq!(schedule.due(&config, at), [Task::Reload]);
+        assert!(
+            schedule
+                .due(&config, at + Duration::from_secs(30))
+                .is_empty()
+        );
+        assert_eq!(
+            schedule.due(&config, at + Duration::from_secs(61)),
+            [Task::Reload]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The stylesheet of the `default` theme, which the other built-in themes build on.
+const STYLES: &str = include_str!("styles.css");
+/// What the `light` theme changes about the default one.
+const LIGHT: &str = include_str!("themes/light.css");
+
+/// The themes compiled in, by name.
+pub const BUILT_IN: [&str; 2] = ["default", "light"];
+
+/// The file describing a theme, at the root of its directory or zip archive.
+pub const MANIFEST: &str = "theme.toml";
+
+/// A theme's [`MANIFEST`]. Files under the theme's `assets` directory are served
+/// under `/theme/`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    name:     Option<String>,
+    /// Stylesheets inlined into every page, in order.
+    styles:   Vec<String>,
+    /// A Jinja template for pages, replacing the built-in one. It gets `title`,
+    /// `lang`, `desc`, `noindex`, `refresh`, `bookmark_url`, `micro`, `identities`,
+    /// `favicon` (a `data:` URL, if any), `menu` (each item with a `label` and `url`)
+    /// and `owner`, and the HTML of `styles`, `content`, `footer` and `scripts`.
+    template: Option<String>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            name:     None,
+            styles:   vec![String::from("styles.css")],
+            template: None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read theme: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to read theme archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("invalid {MANIFEST}: {0}")]
+    Manifest(#[from] toml::de::Error),
+    #[error("theme has no \"{0}\"")]
+    Missing(String),
+    #[error("\"{0}\" isn't UTF-8")]
+    NotUtf8(String),
+    #[error("invalid template: {0}")]
+    Template(#[from] minijinja::Error),
+}
+
+/// How pages look.
+#[derive(Debug)]
+pub struct Theme {
+    pub name:   String,
+    /// Every stylesheet, concatenated.
+    pub styles: String,
+    /// The page template, by name, if the theme has one.
+    template:   Option<(String, minijinja::Environment<'static>)>,
+    /// Files under the theme's `assets` directory, by path within it.
+    assets:     HashMap<String, Vec<u8>>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::built_in("default", STYLES.to_string())
+    }
+}
+
+impl Theme {
+    fn built_in(name: &str, styles: String) -> Self {
+        Self {
+            name: name.to_string(),
+            styles,
+            template: None,
+            assets: HashMap::new(),
+        }
+    }
+
+    /// Where the theme named `theme` is read from, unless it's built in.
+    pub fn path(theme: &str, config_dir: &Path) -> Option<PathBuf> {
+        match BUILT_IN.contains(&theme) {
+            true => None,
+            false => Some(config_dir.join(theme)),
+        }
+    }
+
+    /// Loads the theme named `theme`: a built-in one, or a directory or zip archive
+    /// at that path, relative to `config_dir`.
+    pub fn load(theme: &str, config_dir: &Path) -> Result<Self, Error> {
+        let Some(path) = Self::path(theme, config_dir) else {
+            return Ok(match theme {
+                "light" => Self::built_in(theme, format!("{STYLES}\n{LIGHT}")),
+                _ => Self::default(),
+            });
+        };
+        let files = match path.is_dir() {
+            true => read_dir(&path)?,
+            false => read_zip(&path)?,
+        };
+        Self::from_files(theme, &files)
+    }
+
+    fn from_files(name: &str, files: &HashMap<String, Vec<u8>>) -> Result<Self, Error> {
+        let text = |path: &str| {
+            let data = files
+                .get(path)
+                .ok_or_else(|| Error::Missing(path.to_string()))?;
+            String::from_utf8(data.clone()).map_err(|_| Error::NotUtf8(path.to_string()))
+        };
+        let manifest: Manifest = toml::from_str(&text(MANIFEST)?)?;
+        let mut styles = String::new();
+        for path in &manifest.styles {
+            styles.push_str(&text(path)?);
+            styles.push('\n');
+        }
+        let template = match manifest.template {
+            Some(path) => {
+                let mut env = minijinja::Environment::new();
+                env.add_template_owned(path.clone(), text(&path)?)?;
+                Some((path, env))
+            }
+            None => None,
+        };
+        let assets = files
+            .iter()
+            .filter_map(|(path, data)| {
+                Some((path.strip_prefix("assets/")?.to_string(), data.clone()))
+            })
+            .collect();
+        Ok(Self {
+            name: manifest.name.unwrap_or_else(|| name.to_string()),
+            styles,
+            template,
+            assets,
+        })
+    }
+
+    /// The theme's page template, or `None` when pages use the built-in one.
+    pub fn template(&self) -> Option<minijinja::Template<'_, '_>> {
+        let (name, env) = self.template.as_ref()?;
+        env.get_template(name).ok()
+    }
+
+    /// The contents of the asset at `path`, within the theme's `assets` directory.
+    pub fn asset(&self, path: &str) -> Option<&[u8]> {
+        self.assets.get(path).map(Vec::as_slice)
+    }
+}
+
+/// Every file under `root`, by its path within it.
+fn read_dir(root: &Path) -> io::Result<HashMap<String, Vec<u8>>> {
+    let mut files = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let rel_path: Vec<_> = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect();
+            files.insert(rel_path.join("/"), fs::read(&path)?);
+        }
+    }
+    Ok(files)
+}
+
+/// Every file in the zip archive at `path`, by its path within it.
+fn read_zip(path: &Path) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let mut files = HashMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        files.insert(file.name().to_string(), data);
+    }
+    // Archives often hold the theme in a directory of its own.
+    let prefix = files.keys().find_map(|x| {
+        let prefix = x.strip_suffix(MANIFEST)?;
+        (prefix.ends_with('/') && prefix.matches('/').count() == 1)
+            .then(|| prefix.to_string())
+    });
+    if let (Some(prefix), false) = (prefix, files.contains_key(MANIFEST)) {
+        files = files
+            .into_iter()
+            .filter_map(|(path, data)| {
+                Some((path.strip_prefix(&prefix)?.to_string(), data))
+            })
+            .collect();
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading() {
+        let files = |entries: &[(&str, &str)]| -> HashMap<_, _> {
+            entries
+                .iter()
+                .map(|(path, data)| (path.to_string(), data.as_bytes().to_vec()))
+                .collect()
+        };
+        let theme = Theme::from_files(
+            "themes/mine",
+            &files(&[
+                (
+                    MANIFEST,
+                    "styles = [\"base.css\", \"extra.css\"]\ntemplate = \"page.html\"",
+                ),
+                ("base.css", "body {}"),
+                ("extra.css", "main {}"),
+                ("page.html", "<title>{{ title }}</title>{{ content }}"),
+                ("assets/logo.svg", "<svg/>"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(theme.name, "themes/mine");
+        assert_eq!(theme.styles, "body {}\nmain {}\n");
+        assert_eq!(theme.asset("logo.svg"), Some(&b"<svg/>"[..]));
+        assert_eq!(theme.asset("page.html"), None);
+        let page = theme
+            .template()
+            .unwrap()
+            .render(minijinja::context! { title => "<Hi>", content => "x" })
+            .unwrap();
+        assert_eq!(page, "<title>&lt;Hi&gt;</title>x");
+
+        let missing = Theme::from_files("broken", &files(&[(MANIFEST, "")]));
+        assert!(matches!(missing, Err(Error::Missing(path)) if path == "styles.css"));
+        let invalid = Theme::from_files(
+            "broken",
+            &files(&[
+                (MANIFEST, "styles = []\ntemplate = \"page.html\""),
+                ("page.html", "{% if %}"),
+            ]),
+        );
+        assert!(matches!(invalid, Err(Error::Template(_))));
+    }
+}
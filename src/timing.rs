@@ -0,0 +1,105 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Time spent in work that's interleaved with other stages, such as
+    /// highlighting code while parsing markdown.
+    static SPENT: RefCell<HashMap<&'static str, Duration>> = RefCell::new(HashMap::new());
+}
+
+/// Runs `f`, adding the time it took to the current thread's total for `name`.
+pub fn record<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    SPENT.with_borrow_mut(|x| *x.entry(name).or_default() += start.elapsed());
+    result
+}
+
+/// Takes the current thread's recorded totals, to hand them to another thread.
+pub fn take() -> HashMap<&'static str, Duration> {
+    SPENT.take()
+}
+
+/// Adds totals taken from another thread to the current thread's.
+pub fn add(spent: HashMap<&'static str, Duration>) {
+    SPENT.with_borrow_mut(|x| {
+        for (name, duration) in spent {
+            *x.entry(name).or_default() += duration;
+        }
+    });
+}
+
+/// Per-stage timings of handling a request, reported in a `Server-Timing` header.
+pub struct Timings {
+    start:  Instant,
+    last:   Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn start() -> Self {
+        SPENT.with_borrow_mut(HashMap::clear);
+        let now = Instant::now();
+        Self {
+            start:  now,
+            last:   now,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Ends the stage `name`, which began when the previous one ended.
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now - self.last));
+        self.last = now;
+    }
+
+    /// Reports the time recorded for `name` as its own stage, taking it out of the
+    /// stage it happened during.
+    pub fn split(&mut self, during: &'static str, name: &'static str) {
+        let spent = SPENT
+            .with_borrow_mut(|x| x.remove(name))
+            .unwrap_or_default();
+        if let Some((_, duration)) = self.stages.iter_mut().find(|(x, _)| *x == during) {
+            *duration = duration.saturating_sub(spent);
+        }
+        self.stages.push((name, spent));
+    }
+
+    /// The `Server-Timing` header value, ending with the total.
+    pub fn header(&self) -> String {
+        self.stages
+            .iter()
+            .copied()
+            .chain([("total", self.start.elapsed())])
+            .map(|(name, duration)| {
+                format!("{name};dur={:.3}", duration.as_secs_f64() * 1000.0)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_timing() {
+        let mut timings = Timings::start();
+        timings.stage("read");
+        record("highlight", || std::thread::sleep(Duration::from_millis(2)));
+        timings.stage("parse");
+        timings.split("parse", "highlight");
+        let header = timings.header();
+        let names: Vec<_> = header
+            .split(", ")
+            .map(|x| x.split_once(";dur=").unwrap().0)
+            .collect();
+        assert_eq!(names, ["read", "parse", "highlight", "total"]);
+        let highlight = header.split("highlight;dur=").nth(1).unwrap();
+        let highlight: f64 = highlight.split(',').next().unwrap().parse().unwrap();
+        assert!(highlight >= 2.0);
+    }
+}
@@ -0,0 +1,139 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+const MARKERS: [&str; 3] = ["TODO", "FIXME", "XXX"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Todo {
+    /// The `TODO:`-style marker, or `None` for an unchecked task-list item.
+    pub marker: Option<&'static str>,
+    pub text:   String,
+}
+
+impl Todo {
+    /// A text fragment pointing browsers at the todo within its note.
+    pub fn fragment(&self) -> String {
+        let mut text = self.marker.map(|x| format!("{x}: ")).unwrap_or_default();
+        // The whole text could span elements that don't render next to each other,
+        // the first few words are enough to find it.
+        text.extend(
+            self.text
+                .split_whitespace()
+                .take(5)
+                .map(|x| x.to_string() + " "),
+        );
+        let encoded = crate::uri::percent_encode(text.trim_end()).replace('-', "%2D");
+        format!("#:~:text={encoded}")
+    }
+}
+
+/// Finds the marker in `text`, returning it along with the text after it.
+fn marker(text: &str) -> Option<(&'static str, &str)> {
+    MARKERS.iter().find_map(|&marker| {
+        let (before, after) = text.split_once(marker)?;
+        let after = after.strip_prefix(':')?;
+        let at_word = before
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        at_word.then_some((marker, after))
+    })
+}
+
+/// Finds the unchecked task-list items and `TODO:`-style markers in a note's text,
+/// ignoring code blocks.
+pub fn find(md: &str) -> Vec<Todo> {
+    let mut todos = Vec::new();
+    let mut current: Option<Todo> = None;
+    let mut in_code = false;
+    let mut finish = |current: &mut Option<Todo>| {
+        if let Some(mut todo) = current.take() {
+            todo.text = todo.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            todos.push(todo);
+        }
+    };
+    for event in Parser::new_ext(md, Options::ENABLE_TASKLISTS) {
+        let is_task = current.as_ref().is_some_and(|x| x.marker.is_none());
+        match event {
+            Event::TaskListMarker(false) => {
+                current = Some(Todo {
+                    marker: None,
+                    text:   String::new(),
+                })
+            }
+            Event::Start(Tag::Item) | Event::End(TagEnd::Item) => finish(&mut current),
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph)
+                if is_task =>
+            {
+                if let Some(todo) = &mut current {
+                    todo.text.push(' ');
+                }
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::TableCell) => {
+                finish(&mut current)
+            }
+            Event::Text(text) | Event::Code(text) if !in_code => match &mut current {
+                Some(todo) => todo.text.push_str(&text),
+                None => {
+                    if let Some((marker, rest)) = marker(&text) {
+                        current = Some(Todo {
+                            marker: Some(marker),
+                            text:   rest.to_string(),
+                        });
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    finish(&mut current);
+    todos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn todos() {
+        let md = "\
+```meta
+title = \"TODO: not this\"
+```
+
+- [ ] Water the *plants*
+- [x] Done already
+- [ ] Call the
+  bank
+
+Some text. TODO: fix `this`
+and not this. Also NOTODO: nope.
+
+```rust
+// FIXME: not in code
+```
+
+# FIXME: heading
+";
+        let todo = |marker, text: &str| Todo {
+            marker,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            find(md),
+            [
+                todo(None, "Water the plants"),
+                todo(None, "Call the bank"),
+                todo(Some("TODO"), "fix this"),
+                todo(Some("FIXME"), "heading"),
+            ]
+        );
+        assert_eq!(
+            todo(Some("TODO"), "fix the well-known bug").fragment(),
+            "#:~:text=TODO%3A%20fix%20the%20well%2Dknown%20bug"
+        );
+    }
+}
@@ -173,27 +173,50 @@ fn is_scheme(c: char) -> bool {
 }
 
 pub fn percent_decode(s: impl AsRef<str>) -> Option<String> {
-    let s = s.as_ref();
-    let mut out = String::new();
-    let mut rem = 0;
-    for (i, ch) in s.chars().enumerate() {
-        if rem == 0 {
-            if ch == '%' {
-                rem = 2;
-            } else {
-                out.push(ch);
-            }
-            continue;
-        }
-        rem -= 1;
-        if rem == 0 {
-            out.push(u8::from_str_radix(&s[i - 1..=i], 16).ok().map(char::from)?);
+    let s = s.as_ref().as_bytes();
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'%' {
+            let hex = std::str::from_utf8(s.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(s[i]);
+            i += 1;
         }
     }
-    Some(out)
+    String::from_utf8(out).ok()
 }
 
-// TODO: Percent Encode
+/// Decodes `application/x-www-form-urlencoded` data, such as a query string, into
+/// key-value pairs.
+pub fn parse_query(s: &str) -> Vec<(String, String)> {
+    s.split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((
+                percent_decode(key.replace('+', " "))?,
+                percent_decode(value.replace('+', " "))?,
+            ))
+        })
+        .collect()
+}
+
+/// Encodes everything but unreserved characters, making `s` safe to use in any
+/// part of a URI.
+pub fn percent_encode(s: impl AsRef<str>) -> String {
+    let mut out = String::with_capacity(s.as_ref().len());
+    for &b in s.as_ref().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
 
 #[cfg(test)]
 mod tests {
@@ -204,6 +227,25 @@ mod tests {
         assert_eq!(
             percent_decode("%21%40%23%24%25%2A%28%29With Some Text in the middle%7E%7B%7D%3A%3C%3E%3F_%2B").unwrap(),
             "!@#$%*()With Some Text in the middle~{}:<>?_+");
+        assert_eq!(percent_decode("caf%C3%A9").unwrap(), "café");
+        assert_eq!(percent_decode("%C3"), None);
+        assert_eq!(percent_decode("100%"), None);
+        assert_eq!(percent_encode("a b/café~"), "a%20b%2Fcaf%C3%A9~");
+        assert_eq!(percent_decode(percent_encode("?x=1&y")).unwrap(), "?x=1&y");
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            parse_query("q=hello+world&empty=&flag&x=a%26b"),
+            [
+                ("q", "hello world"),
+                ("empty", ""),
+                ("flag", ""),
+                ("x", "a&b")
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
     }
 
     #[test]
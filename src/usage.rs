@@ -0,0 +1,138 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Most days of usage kept.
+const HISTORY: usize = 365;
+
+/// Bytes taken on disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// By the content directory.
+    pub content: u64,
+    /// By the data directory: the store, and caches such as the search index.
+    pub data:    u64,
+}
+
+impl Usage {
+    pub fn measure(content: &Path, data: &Path) -> io::Result<Self> {
+        Ok(Self {
+            content: size(content)?,
+            data:    size(data)?,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.content.saturating_add(self.data)
+    }
+}
+
+/// The size of the files under `dir`.
+fn size(dir: &Path) -> io::Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut size = 0;
+    crate::walk(dir, &mut |is_dir, path| {
+        if !is_dir {
+            size += fs::symlink_metadata(path)?.len();
+        }
+        Ok(true)
+    })?;
+    Ok(size)
+}
+
+/// Usage by day.
+pub type History = BTreeMap<NaiveDate, Usage>;
+
+/// Records `usage` as `today`'s, forgetting the oldest days beyond a year's worth.
+/// Returns whether anything changed.
+pub fn record(history: &mut History, today: NaiveDate, usage: Usage) -> bool {
+    let changed = history.insert(today, usage) != Some(usage);
+    while history.len() > HISTORY {
+        history.pop_first();
+    }
+    changed
+}
+
+/// `bytes` in the largest binary unit it makes at least one of, such as `1.5 MiB`.
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} bytes"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// An SVG chart of the total usage in `history`, and of the notes' share of it,
+/// with a dashed line at `quota`. Empty without any history.
+pub fn chart(history: &History, quota: Option<u64>) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 150.0;
+    let Some(first) = history.keys().next() else {
+        return String::new();
+    };
+    let highest = history.values().map(Usage::total).chain(quota).max();
+    let highest = highest.unwrap_or_default().max(1) as f64;
+    let step = WIDTH / history.len().saturating_sub(1).max(1) as f64;
+    let y = |bytes: u64| HEIGHT - bytes as f64 / highest * HEIGHT;
+    let line = |bytes: fn(&Usage) -> u64| {
+        let points: Vec<_> = history
+            .values()
+            .enumerate()
+            .map(|(i, usage)| format!("{:.1},{:.1}", i as f64 * step, y(bytes(usage))))
+            .collect();
+        points.join(" ")
+    };
+    let mut svg = format!(
+        r#"<svg class="usage" xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {HEIGHT}" role="img"><title>Disk usage since {first}</title>"#
+    );
+    if let Some(quota) = quota {
+        let y = y(quota);
+        svg.push_str(&format!(
+            r#"<line x1="0" y1="{y:.1}" x2="{WIDTH}" y2="{y:.1}" stroke="currentColor" stroke-dasharray="4"/>"#
+        ));
+    }
+    svg.push_str(&format!(
+        r#"<polyline points="{}" fill="none" stroke="currentColor"/><polyline points="{}" fill="none" stroke="var(--accent-color)"/></svg>"#,
+        line(Usage::total),
+        line(|x| x.content),
+    ));
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 1, d).unwrap();
+        let usage = |content| Usage { content, data: 50 };
+        let mut history = History::new();
+        assert!(record(&mut history, day(1), usage(50)));
+        assert!(!record(&mut history, day(1), usage(50)));
+        assert!(record(&mut history, day(2), usage(150)));
+        assert_eq!(
+            chart(&history, Some(400)),
+            r#"<svg class="usage" xmlns="http://www.w3.org/2000/svg" viewBox="0 0 600 150" role="img"><title>Disk usage since 2025-01-01</title><line x1="0" y1="0.0" x2="600" y2="0.0" stroke="currentColor" stroke-dasharray="4"/><polyline points="0.0,112.5 600.0,75.0" fill="none" stroke="currentColor"/><polyline points="0.0,131.2 600.0,93.8" fill="none" stroke="var(--accent-color)"/></svg>"#
+        );
+        assert!(chart(&History::new(), Some(400)).is_empty());
+
+        for d in 0..400 {
+            record(&mut history, day(1) + chrono::Days::new(d), usage(d));
+        }
+        assert_eq!(history.len(), HISTORY);
+        assert_eq!(bytes(512), "512 bytes");
+        assert_eq!(bytes(1536 * 1024), "1.5 MiB");
+    }
+}
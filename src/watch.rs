@@ -0,0 +1,44 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the content directory has to be quiet before reloading, so saving
+/// several files at once (or an editor's swap files) causes a single reload.
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Whether a change to `path` can change what's served: notes and redirects, but
+/// not hidden files such as `.git` or an editor's swap files.
+fn matters(content_path: &Path, path: &Path) -> bool {
+    let path = path.strip_prefix(content_path).unwrap_or(path);
+    let hidden = path
+        .components()
+        .any(|x| x.as_os_str().as_encoded_bytes().starts_with(b"."));
+    !hidden
+        && (path.extension().is_some_and(|x| x == "md")
+            || path
+                .file_name()
+                .is_some_and(|x| x == crate::redirects::FILE))
+}
+
+/// Watches the content directory, recording when something in it last changed.
+/// The watch stops when the returned watcher is dropped.
+pub fn watch(
+    content_path: &Path,
+    changed: Arc<Mutex<Option<Instant>>>,
+) -> notify::Result<RecommendedWatcher> {
+    let content_path = content_path.canonicalize()?;
+    let mut watcher = notify::recommended_watcher({
+        let content_path = content_path.clone();
+        move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            let relevant = !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|x| matters(&content_path, x));
+            if let (true, Ok(mut changed)) = (relevant, changed.lock()) {
+                *changed = Some(Instant::now());
+            }
+        }
+    })?;
+    watcher.watch(&content_path, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
@@ -0,0 +1,81 @@
+use crate::uri;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The notes a `[[wikilink]]` can point at, by lowercase path, title and slug (the
+/// file name without its extension). Paths win over titles, and titles over slugs.
+#[derive(Debug, Default)]
+pub struct Links {
+    paths:  HashMap<String, String>,
+    titles: HashMap<String, String>,
+    slugs:  HashMap<String, String>,
+}
+
+impl Links {
+    /// Links to `notes`, given as title and path. Earlier notes win ties.
+    pub fn new<'a>(notes: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut links = Self::default();
+        for (title, rel_path) in notes {
+            let add = |map: &mut HashMap<_, _>, key: &str| {
+                map.entry(key.to_lowercase())
+                    .or_insert_with(|| rel_path.to_string());
+            };
+            add(&mut links.paths, rel_path);
+            add(&mut links.titles, title);
+            if let Some(slug) = Path::new(rel_path).file_stem().and_then(|x| x.to_str()) {
+                add(&mut links.slugs, slug);
+            }
+        }
+        links
+    }
+
+    /// The URL of the note `target` names, which may end in a `#fragment`.
+    pub fn resolve(&self, target: &str) -> Option<String> {
+        let (name, fragment) = match target.split_once('#') {
+            Some((name, fragment)) => (name, Some(fragment)),
+            None => (target, None),
+        };
+        let name = name.trim().to_lowercase();
+        let rel_path = self
+            .paths
+            .get(&name)
+            .or_else(|| self.titles.get(&name))
+            .or_else(|| self.slugs.get(&name))?;
+        let path: Vec<_> = rel_path.split('/').map(uri::percent_encode).collect();
+        let mut url = format!("/note/{}", path.join("/"));
+        if let Some(fragment) = fragment {
+            url.push('#');
+            url.push_str(&uri::percent_encode(fragment.trim()));
+        }
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolving() {
+        let links = Links::new([
+            ("Rust Notes", "dev/rust-notes.md"),
+            ("Ideas", "ideas.md"),
+            ("Old Ideas", "2020/ideas.md"),
+        ]);
+        assert_eq!(
+            links.resolve("rust notes").as_deref(),
+            Some("/note/dev/rust-notes.md")
+        );
+        assert_eq!(
+            links.resolve("rust-notes#Error handling").as_deref(),
+            Some("/note/dev/rust-notes.md#Error%20handling")
+        );
+        // A title beats another note's slug.
+        assert_eq!(links.resolve("Ideas").as_deref(), Some("/note/ideas.md"));
+        assert_eq!(
+            links.resolve("2020/ideas.md").as_deref(),
+            Some("/note/2020/ideas.md")
+        );
+        assert_eq!(links.resolve("Nowhere"), None);
+    }
+}